@@ -0,0 +1,99 @@
+// times finding the tiles a body overlaps by checking every tile, like the physics system used
+// to, against querying the broad phase grid first, for levels of a thousand tiles and up
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use cgmath::Vector2;
+use slotmap::{DefaultKey, SlotMap};
+use web_gpu_engine::broad_phase::SpatialGrid;
+
+const TILE_SIZE: Vector2<f32> = Vector2::new(32., 25.);
+const BODY_SIZE: Vector2<f32> = Vector2::new(25., 75.);
+const BODIES: usize = 100;
+const ROUNDS: u32 = 20;
+
+type Bounds = (Vector2<f32>, Vector2<f32>);
+
+fn overlaps(a: &Bounds, b: &Bounds) -> bool {
+    a.0.x <= b.1.x && b.0.x <= a.1.x && a.0.y <= b.1.y && b.0.y <= a.1.y
+}
+
+// a square level, every tile solid
+fn tiles(count: usize) -> (SlotMap<DefaultKey, Bounds>, f32) {
+    let columns = (count as f32).sqrt().ceil() as usize;
+    let mut tiles = SlotMap::new();
+    (0..count).for_each(|index| {
+        let min = Vector2::new(
+            (index % columns) as f32 * TILE_SIZE.x,
+            (index / columns) as f32 * TILE_SIZE.y,
+        );
+        tiles.insert((min, min + TILE_SIZE));
+    });
+    (tiles, columns as f32 * TILE_SIZE.x)
+}
+
+// spread over the level by a fixed sequence, so every run tests the same boxes
+fn bodies(level_size: f32) -> Vec<Bounds> {
+    (0..BODIES)
+        .map(|index| {
+            let min = Vector2::new(
+                (index as f32 * 0.618).fract() * level_size,
+                (index as f32 * 0.414).fract() * level_size * TILE_SIZE.y / TILE_SIZE.x,
+            );
+            (min, min + BODY_SIZE)
+        })
+        .collect()
+}
+
+fn time(mut round: impl FnMut() -> usize) -> (Duration, usize) {
+    let hits = round();
+    let start = Instant::now();
+    (0..ROUNDS).for_each(|_| {
+        black_box(round());
+    });
+    (start.elapsed() / ROUNDS, hits)
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>14} {:>14} {:>8}",
+        "tiles", "every tile", "broad phase", "hits"
+    );
+    [1_000, 4_000, 16_000, 64_000]
+        .into_iter()
+        .for_each(|count| {
+            let (tiles, level_size) = tiles(count);
+            let bodies = bodies(level_size);
+
+            let (brute_force, brute_force_hits) = time(|| {
+                bodies
+                    .iter()
+                    .map(|body| tiles.values().filter(|tile| overlaps(body, tile)).count())
+                    .sum()
+            });
+
+            let mut grid = SpatialGrid::new(128.);
+            tiles
+                .iter()
+                .for_each(|(entity, (min, max))| grid.update(entity, *min, *max, u32::MAX));
+            assert_eq!(grid.len(), count);
+            let (broad_phase, broad_phase_hits) = time(|| {
+                bodies
+                    .iter()
+                    .map(|body| {
+                        grid.query(body.0, body.1, u32::MAX)
+                            .into_iter()
+                            .filter(|entity| overlaps(body, &tiles[*entity]))
+                            .count()
+                    })
+                    .sum()
+            });
+            assert_eq!(brute_force_hits, broad_phase_hits);
+
+            println!(
+                "{:>8} {:>14?} {:>14?} {:>8}",
+                count, brute_force, broad_phase, broad_phase_hits
+            );
+        });
+}
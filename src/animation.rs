@@ -1,26 +1,45 @@
 use std::{collections::HashMap, time::Duration};
 
-use crate::{component, sprite::SheetPositionComponent, utils};
+use crate::{component, sprite::SheetPositionComponent, world::World};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationFrame {
+    pub sheet_index: u32,
+    pub duration: Duration,
+}
 
 pub struct SpriteAnimation {
+    pub frames: Vec<AnimationFrame>,
     pub animation_index: u32,
-    pub sprite_count: u32,
-    pub start_index: u32,
-    pub per_sprite_duration: Duration,
     pub current_elapsed_time: Duration,
 }
 
 impl SpriteAnimation {
+    pub fn new(frames: Vec<AnimationFrame>) -> Self {
+        assert!(!frames.is_empty());
+        Self {
+            frames,
+            animation_index: 0,
+            current_elapsed_time: Duration::new(0, 0),
+        }
+    }
+
     pub fn update(&mut self, delta_time: Duration) {
         self.current_elapsed_time += delta_time;
-        if self.current_elapsed_time > self.per_sprite_duration {
-            self.current_elapsed_time -= self.per_sprite_duration;
-            self.animation_index = (self.animation_index + 1) % self.sprite_count;
+        while self.current_elapsed_time > self.current_frame().duration
+            && !self.current_frame().duration.is_zero()
+        {
+            self.current_elapsed_time -= self.current_frame().duration;
+            self.animation_index = (self.animation_index + 1) % self.frames.len() as u32;
         }
     }
 
     pub fn get_sheet_index(&self) -> u32 {
-        self.start_index + self.animation_index
+        self.current_frame().sheet_index
+    }
+
+    fn current_frame(&self) -> &AnimationFrame {
+        &self.frames[self.animation_index as usize]
     }
 }
 pub struct SpriteAnimationControllerComponent {
@@ -44,35 +63,23 @@ impl SpriteAnimationControllerComponent {
 pub struct AnimationSystem {}
 
 impl AnimationSystem {
-    pub fn update_animations(
-        sprite_animation_controller_components: &mut component::EntityMap<
-            SpriteAnimationControllerComponent,
-        >,
-        sheet_position_components: &mut component::EntityMap<SheetPositionComponent>,
-        character_state_components: &mut component::EntityMap<component::CharacterStateComponent>,
-        delta_time: Duration,
-    ) {
-        utils::zip3_entities_mut(
-            sprite_animation_controller_components,
-            sheet_position_components,
-            character_state_components,
-        )
-        .for_each(
-            |(
-                _,
-                sprite_animation_controller,
-                sheet_position_component,
-                character_state_component,
-            )| {
-                if let (
-                    Some(sprite_animation_controller),
-                    Some(sheet_position_component),
-                    Some(character_state_component),
-                ) = (
-                    sprite_animation_controller,
-                    sheet_position_component,
-                    character_state_component,
-                ) {
+    pub fn update_animations(world: &World, delta_time: Duration) {
+        world
+            .query::<(
+                &mut SpriteAnimationControllerComponent,
+                &mut SheetPositionComponent,
+                &component::CharacterStateComponent,
+            )>()
+            .iter()
+            .for_each(
+                |(
+                    _,
+                    (
+                        sprite_animation_controller,
+                        sheet_position_component,
+                        character_state_component,
+                    ),
+                )| {
                     let sprite_animation = sprite_animation_controller
                         .animation_map
                         .get_mut(&character_state_component.character_state);
@@ -84,8 +91,7 @@ impl AnimationSystem {
                             .borrow()
                             .get_position_by_index(sprite_animation.get_sheet_index());
                     }
-                }
-            },
-        );
+                },
+            );
     }
 }
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use cgmath::Vector2;
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::animation;
+use crate::atlas::Rect;
+use crate::component;
+use crate::sprite;
+
+// imports the json Aseprite writes next to an exported sprite sheet (File > Export Sprite Sheet,
+// with json data), with the frames as either a hash or an array. every frame keeps its own
+// duration, frame tags become animations played in the tag's direction, and the pivot of the
+// first slice that has one is kept per frame. sheets exported with trimming or packing aren't on
+// a grid, their frames have to be set on the sprite sheet with sprite_frames.
+
+#[derive(Deserialize)]
+struct AsepriteJson {
+    #[serde(deserialize_with = "frames_in_order")]
+    frames: Vec<AsepriteJsonFrame>,
+    meta: AsepriteMeta,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AsepriteJsonFrame {
+    frame: AsepriteRect,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: AsepriteRect,
+    source_size: AsepriteSize,
+    duration: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AsepriteMeta {
+    #[serde(default)]
+    frame_tags: Vec<AsepriteTag>,
+    #[serde(default)]
+    slices: Vec<AsepriteSlice>,
+}
+
+#[derive(Deserialize)]
+struct AsepriteTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default = "forward")]
+    direction: String,
+}
+
+fn forward() -> String {
+    "forward".to_string()
+}
+
+#[derive(Deserialize)]
+struct AsepriteSlice {
+    keys: Vec<AsepriteSliceKey>,
+}
+
+#[derive(Deserialize)]
+struct AsepriteSliceKey {
+    frame: usize,
+    bounds: AsepriteRect,
+    #[serde(default)]
+    pivot: Option<AsepritePoint>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct AsepriteRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct AsepriteSize {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct AsepritePoint {
+    x: f32,
+    y: f32,
+}
+
+// frames exported as a hash are keyed by file name, which doesn't sort in frame order, so they're
+// read in the order they're written in
+fn frames_in_order<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<AsepriteJsonFrame>, D::Error> {
+    struct FramesVisitor;
+
+    impl<'de> Visitor<'de> for FramesVisitor {
+        type Value = Vec<AsepriteJsonFrame>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an array or a map of frames")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> std::result::Result<Self::Value, A::Error> {
+            let mut frames = Vec::new();
+            while let Some(frame) = seq.next_element()? {
+                frames.push(frame);
+            }
+            Ok(frames)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> std::result::Result<Self::Value, A::Error> {
+            let mut frames = Vec::new();
+            while let Some((_, frame)) = map.next_entry::<String, AsepriteJsonFrame>()? {
+                frames.push(frame);
+            }
+            Ok(frames)
+        }
+    }
+
+    deserializer.deserialize_any(FramesVisitor)
+}
+
+pub struct AsepriteFrame {
+    // where the frame is on the sheet image, trimmed if it was exported trimmed
+    pub rect: Rect,
+    // where the trimmed rect is in the untrimmed frame
+    pub offset: (u32, u32),
+    pub duration: Duration,
+    // in pixels from the untrimmed frame's top left
+    pub pivot: Option<Vector2<f32>>,
+}
+
+pub struct AsepriteSheet {
+    // every frame in an Aseprite file has the same size before it's trimmed
+    pub sprite_size: (u32, u32),
+    pub frames: Vec<AsepriteFrame>,
+    // frame indices in the order they play in
+    pub tags: HashMap<String, Vec<usize>>,
+}
+
+impl AsepriteSheet {
+    pub fn from_json(source: &str) -> Result<Self> {
+        let json: AsepriteJson =
+            serde_json::from_str(source).map_err(|e| anyhow!("invalid aseprite json: {}", e))?;
+        let first = json
+            .frames
+            .first()
+            .ok_or_else(|| anyhow!("no frames in the aseprite json"))?;
+        let sprite_size = (first.source_size.w, first.source_size.h);
+
+        let mut pivots = vec![None; json.frames.len()];
+        if let Some(slice) = json
+            .meta
+            .slices
+            .iter()
+            .find(|slice| slice.keys.iter().any(|key| key.pivot.is_some()))
+        {
+            // a key holds from its frame until the next one
+            slice.keys.iter().for_each(|key| {
+                let pivot = key.pivot.as_ref().map(|pivot| {
+                    Vector2::new(key.bounds.x as f32 + pivot.x, key.bounds.y as f32 + pivot.y)
+                });
+                pivots
+                    .iter_mut()
+                    .skip(key.frame)
+                    .for_each(|frame_pivot| *frame_pivot = pivot);
+            });
+        }
+
+        let frames = json
+            .frames
+            .iter()
+            .zip(pivots)
+            .enumerate()
+            .map(|(index, (frame, pivot))| {
+                ensure!(!frame.rotated, "frame {} is rotated", index);
+                ensure!(
+                    (frame.source_size.w, frame.source_size.h) == sprite_size,
+                    "frame {} is {}x{}, the first one is {}x{}",
+                    index,
+                    frame.source_size.w,
+                    frame.source_size.h,
+                    sprite_size.0,
+                    sprite_size.1
+                );
+                Ok(AsepriteFrame {
+                    rect: Rect {
+                        x: frame.frame.x,
+                        y: frame.frame.y,
+                        width: frame.frame.w,
+                        height: frame.frame.h,
+                    },
+                    offset: (frame.sprite_source_size.x, frame.sprite_source_size.y),
+                    duration: Duration::from_millis(frame.duration),
+                    pivot,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let tags = json
+            .meta
+            .frame_tags
+            .iter()
+            .map(|tag| {
+                ensure!(
+                    tag.from <= tag.to && tag.to < frames.len(),
+                    "tag {} has frames {}..={} but there are {} frames",
+                    tag.name,
+                    tag.from,
+                    tag.to,
+                    frames.len()
+                );
+                let forward = tag.from..=tag.to;
+                let order: Vec<usize> = match tag.direction.as_str() {
+                    "forward" => forward.collect(),
+                    "reverse" => forward.rev().collect(),
+                    // the ends aren't shown twice in a row when it loops
+                    "pingpong" => forward
+                        .clone()
+                        .chain((tag.from + 1..tag.to).rev())
+                        .collect(),
+                    "pingpong_reverse" => {
+                        forward.clone().rev().chain(tag.from + 1..tag.to).collect()
+                    }
+                    direction => return Err(anyhow!("unknown direction {}", direction)),
+                };
+                Ok((tag.name.clone(), order))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(Self {
+            sprite_size,
+            frames,
+            tags,
+        })
+    }
+
+    // frames without a pivot are pivoted at the bottom middle
+    pub fn sprite_frames(&self) -> Vec<sprite::SpriteFrame> {
+        self.frames
+            .iter()
+            .map(|frame| sprite::SpriteFrame {
+                rect: frame.rect,
+                offset: frame.offset,
+                source_size: self.sprite_size,
+                pivot: frame.pivot.unwrap_or(Vector2::new(
+                    self.sprite_size.0 as f32 / 2.,
+                    self.sprite_size.1 as f32,
+                )),
+            })
+            .collect()
+    }
+
+    // for a sheet that's a grid the frames have to be on it, which they are if the sheet was
+    // loaded with this sprite size from an untrimmed image exported with the json. a sheet with
+    // frames has to have the ones from sprite_frames
+    pub fn animation(
+        &self,
+        tag: &str,
+        sprite_sheet: &sprite::SpriteSheet,
+    ) -> Result<animation::SpriteAnimation> {
+        let order = self
+            .tags
+            .get(tag)
+            .ok_or_else(|| anyhow!("no tag {}", tag))?;
+        if !sprite_sheet.frames().is_empty() {
+            ensure!(
+                sprite_sheet.frames().len() == self.frames.len(),
+                "sprite sheet {} has {} frames, not {}",
+                sprite_sheet.label(),
+                sprite_sheet.frames().len(),
+                self.frames.len()
+            );
+        }
+        let (sprite_width, sprite_height) = sprite_sheet.sprite_size();
+
+        let frames = order
+            .iter()
+            .map(|index| {
+                let frame = &self.frames[*index];
+                if !sprite_sheet.frames().is_empty() {
+                    return Ok(animation::AnimationFrame {
+                        sheet_index: *index as u32,
+                        duration: frame.duration,
+                    });
+                }
+                let position =
+                    Vector2::new(frame.rect.x / sprite_width, frame.rect.y / sprite_height);
+                ensure!(
+                    frame.rect.width == sprite_width
+                        && frame.rect.height == sprite_height
+                        && position.x * sprite_width == frame.rect.x
+                        && position.y * sprite_height == frame.rect.y,
+                    "frame {} isn't on the {}x{} grid of sprite sheet {}",
+                    index,
+                    sprite_width,
+                    sprite_height,
+                    sprite_sheet.label()
+                );
+                Ok(animation::AnimationFrame {
+                    sheet_index: sprite_sheet.get_index_by_position(position),
+                    duration: frame.duration,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(animation::SpriteAnimation::new(frames))
+    }
+
+    pub fn animation_controller(
+        &self,
+        sprite_sheet: &sprite::SpriteSheet,
+        tags: &[(component::CharacterState, &str)],
+    ) -> Result<animation::SpriteAnimationControllerComponent> {
+        let mut controller = animation::SpriteAnimationControllerComponent::new();
+        for (character_state, tag) in tags {
+            let animation = self
+                .animation(tag, sprite_sheet)
+                .with_context(|| format!("in the {:?} animation", character_state))?;
+            controller
+                .animation_map
+                .insert(character_state.clone(), animation);
+        }
+        Ok(controller)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::context;
+    use crate::texture;
+
+    // four untrimmed 16x16 frames on a 2x2 grid, out of grid order
+    const FRAMES: [(u32, u32, u64); 4] = [(16, 0, 100), (0, 16, 150), (16, 16, 200), (0, 0, 50)];
+
+    const TAGS: &str = r#"[
+        { "name": "idle", "from": 0, "to": 1 },
+        { "name": "run", "from": 0, "to": 3, "direction": "pingpong" },
+        { "name": "back", "from": 1, "to": 3, "direction": "reverse" },
+        { "name": "sway", "from": 0, "to": 2, "direction": "pingpong_reverse" }
+    ]"#;
+
+    fn frame((x, y, duration): (u32, u32, u64)) -> String {
+        format!(
+            r#"{{
+                "frame": {{ "x": {}, "y": {}, "w": 16, "h": 16 }},
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": {{ "x": 0, "y": 0, "w": 16, "h": 16 }},
+                "sourceSize": {{ "w": 16, "h": 16 }},
+                "duration": {}
+            }}"#,
+            x, y, duration
+        )
+    }
+
+    fn json(frames: &str, tags: &str) -> String {
+        format!(
+            r#"{{ "frames": {}, "meta": {{ "size": {{ "w": 32, "h": 32 }}, "frameTags": {} }} }}"#,
+            frames, tags
+        )
+    }
+
+    // keyed by file name, which sorts differently from the frames' order
+    fn hash() -> String {
+        let frames = FRAMES
+            .iter()
+            .enumerate()
+            .map(|(index, f)| format!(r#""hero {}.aseprite": {}"#, 9 + index, frame(*f)))
+            .collect::<Vec<_>>();
+        json(&format!("{{ {} }}", frames.join(", ")), TAGS)
+    }
+
+    fn array() -> String {
+        let frames = FRAMES.map(frame);
+        json(&format!("[ {} ]", frames.join(", ")), TAGS)
+    }
+
+    #[test]
+    fn hash_and_array_frames_are_read_in_order() {
+        for source in [hash(), array()] {
+            let sheet = AsepriteSheet::from_json(&source).unwrap();
+            assert_eq!(sheet.sprite_size, (16, 16));
+            assert_eq!(
+                sheet
+                    .frames
+                    .iter()
+                    .map(|frame| (frame.rect, frame.offset, frame.duration))
+                    .collect::<Vec<_>>(),
+                FRAMES
+                    .iter()
+                    .map(|(x, y, duration)| (
+                        Rect {
+                            x: *x,
+                            y: *y,
+                            width: 16,
+                            height: 16
+                        },
+                        (0, 0),
+                        Duration::from_millis(*duration)
+                    ))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn tags_play_in_their_direction() {
+        let sheet = AsepriteSheet::from_json(&array()).unwrap();
+        assert_eq!(sheet.tags.len(), 4);
+        assert_eq!(sheet.tags["idle"], [0, 1]);
+        assert_eq!(sheet.tags["run"], [0, 1, 2, 3, 2, 1]);
+        assert_eq!(sheet.tags["back"], [3, 2, 1]);
+        assert_eq!(sheet.tags["sway"], [2, 1, 0, 1]);
+
+        let frames = FRAMES.map(frame).join(", ");
+        let past_the_end = json(
+            &format!("[ {} ]", frames),
+            r#"[{ "name": "jump", "from": 2, "to": 4 }]"#,
+        );
+        assert!(AsepriteSheet::from_json(&past_the_end).is_err());
+        let sideways = json(
+            &format!("[ {} ]", frames),
+            r#"[{ "name": "jump", "from": 0, "to": 1, "direction": "sideways" }]"#,
+        );
+        assert!(AsepriteSheet::from_json(&sideways).is_err());
+    }
+
+    #[test]
+    fn animations_find_their_frames_on_the_grid() {
+        let Some((device, queue)) = context::headless_device() else {
+            eprintln!("no graphics adapter, skipping");
+            return;
+        };
+        let image = image::DynamicImage::new_rgba8(32, 32);
+        let texture = Arc::new(
+            texture::Texture::from_image(&device, &queue, &image, None, None, false).unwrap(),
+        );
+        let sheet = AsepriteSheet::from_json(&hash()).unwrap();
+
+        let grid = sprite::SpriteSheet::new("hero", texture.clone(), 16, 16);
+        let animation = sheet.animation("run", &grid).unwrap();
+        assert_eq!(
+            animation
+                .frames
+                .iter()
+                .map(|frame| (frame.sheet_index, frame.duration.as_millis()))
+                .collect::<Vec<_>>(),
+            [(1, 100), (2, 150), (3, 200), (0, 50), (3, 200), (2, 150)]
+        );
+        assert!(sheet.animation("jump", &grid).is_err());
+
+        let wrong_grid = sprite::SpriteSheet::new("hero", texture.clone(), 8, 8);
+        assert!(sheet.animation("run", &wrong_grid).is_err());
+
+        // a sheet with frames is indexed by frame
+        let mut packed = sprite::SpriteSheet::new("hero", texture, 16, 16);
+        packed.set_frames(sheet.sprite_frames()).unwrap();
+        let animation = sheet.animation("back", &packed).unwrap();
+        assert_eq!(
+            animation
+                .frames
+                .iter()
+                .map(|frame| frame.sheet_index)
+                .collect::<Vec<_>>(),
+            [3, 2, 1]
+        );
+    }
+}
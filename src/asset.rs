@@ -0,0 +1,593 @@
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::atlas;
+use crate::context;
+use crate::sprite;
+use crate::texture;
+
+// something the asset server can load. it's handed out as Shared so whatever uses it can hold
+// onto it
+pub trait Asset: 'static {
+    type Shared: Clone + 'static;
+}
+
+pub struct Font {
+    pub data: Vec<u8>,
+}
+
+pub struct Shader {
+    // what other shaders #include it by
+    pub name: String,
+    pub source: String,
+}
+
+impl Asset for texture::Texture {
+    type Shared = Arc<texture::Texture>;
+}
+
+impl Asset for sprite::SpriteSheet {
+    type Shared = Rc<RefCell<sprite::SpriteSheet>>;
+}
+
+impl Asset for image::DynamicImage {
+    type Shared = Rc<image::DynamicImage>;
+}
+
+impl Asset for Font {
+    type Shared = Rc<Font>;
+}
+
+impl Asset for Shader {
+    type Shared = Rc<Shader>;
+}
+
+impl Asset for String {
+    type Shared = Rc<String>;
+}
+
+pub struct Handle<T> {
+    index: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    // handles of each asset type count up from 0 in load order, which is also the order
+    // sprite sheets are bound to the renderer in, so a sprite sheet's index is its texture index
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.index)
+    }
+}
+
+// a sprite sheet to pack into an atlas, see AssetServer::load_atlas
+pub struct AtlasSheet<'a> {
+    pub label: &'static str,
+    pub path: &'a str,
+    pub sprite_size: (u32, u32),
+}
+
+// what an atlas was packed from, to pack it again when one of its images changes
+#[derive(Clone)]
+struct LoadedAtlas {
+    name: String,
+    paths: Vec<String>,
+    manual_premultiply: bool,
+}
+
+// bakes an asset into the binary, so it can still be loaded where it can't be read from disk
+macro_rules! embed {
+    ($asset_server:expr, $path:literal) => {
+        $asset_server.embed(
+            $path,
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path)),
+        )
+    };
+}
+pub(crate) use embed;
+
+// loads assets by path, relative to the root. a path that's loaded again as the same type gets
+// the handle from the first load.
+pub struct AssetServer {
+    root: PathBuf,
+    embedded: HashMap<String, &'static [u8]>,
+    // a Vec<T::Shared> for each asset type, indexed by handle
+    assets: HashMap<TypeId, Box<dyn Any>>,
+    handles: HashMap<(TypeId, String), u32>,
+    // how each texture was loaded (normal map path and premultiply), to load it the same way again
+    texture_options: HashMap<String, (Option<String>, bool)>,
+    atlases: Vec<LoadedAtlas>,
+    #[cfg(not(target_arch = "wasm32"))]
+    watcher: Option<(
+        notify::RecommendedWatcher,
+        std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+    )>,
+}
+
+impl AssetServer {
+    const ATLAS_PAGE_SIZE: u32 = 2048;
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            embedded: HashMap::new(),
+            assets: HashMap::new(),
+            handles: HashMap::new(),
+            texture_options: HashMap::new(),
+            atlases: Vec::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            watcher: None,
+        }
+    }
+
+    // starts watching the directories (relative to the root) for changes, see changed
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch(&mut self, directories: &[&str]) -> Result<()> {
+        use notify::Watcher;
+
+        // events come with absolute paths, which changed strips the root back off of
+        let root = self.root.canonicalize()?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        directories.iter().try_for_each(|directory| {
+            let path = root.join(directory);
+            watcher
+                .watch(&path, notify::RecursiveMode::Recursive)
+                .with_context(|| format!("failed to watch {}", path.display()))
+        })?;
+        self.watcher = Some((watcher, receiver));
+        Ok(())
+    }
+
+    // the loaded paths that were written to since the last call, once each
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn changed(&self) -> Vec<String> {
+        let Some((_, receiver)) = &self.watcher else {
+            return Vec::new();
+        };
+        let root = self
+            .root
+            .canonicalize()
+            .unwrap_or_else(|_| self.root.clone());
+
+        let mut changed = Vec::new();
+        receiver
+            .try_iter()
+            .filter_map(|event| {
+                event
+                    .map_err(|e| log::warn!("failed to watch assets: {}", e))
+                    .ok()
+            })
+            .filter(|event| {
+                !matches!(
+                    event.kind,
+                    notify::EventKind::Access(_) | notify::EventKind::Remove(_)
+                )
+            })
+            .flat_map(|event| event.paths)
+            .filter_map(|path| {
+                path.strip_prefix(&root)
+                    .ok()
+                    .map(|path| path.to_string_lossy().replace('\\', "/"))
+            })
+            .filter(|path| self.is_loaded(path))
+            .for_each(|path| {
+                if !changed.contains(&path) {
+                    changed.push(path);
+                }
+            });
+        changed
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn changed(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    pub fn embed(&mut self, path: &str, bytes: &'static [u8]) {
+        self.embedded.insert(path.to_string(), bytes);
+    }
+
+    // from disk on desktop, falling back to the embedded copy if there is one. the web build
+    // only has the embedded copies
+    pub fn read(&self, path: &str) -> Result<Cow<'static, [u8]>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let full_path = self.root.join(path);
+            match std::fs::read(&full_path) {
+                Ok(bytes) => return Ok(Cow::Owned(bytes)),
+                Err(e) if !self.embedded.contains_key(path) => {
+                    return Err(e)
+                        .with_context(|| format!("failed to read {}", full_path.display()))
+                }
+                Err(e) => log::warn!(
+                    "failed to read {} ({}), using the embedded copy",
+                    full_path.display(),
+                    e
+                ),
+            }
+        }
+
+        self.embedded
+            .get(path)
+            .map(|bytes| Cow::Borrowed(*bytes))
+            .ok_or_else(|| anyhow!("{} isn't embedded", path))
+    }
+
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> T::Shared {
+        self.storage::<T>()
+            .and_then(|storage| storage.get(handle.index as usize))
+            .expect("handle from another asset server")
+            .clone()
+    }
+
+    pub fn get_handle<T: Asset>(&self, path: &str) -> Option<Handle<T>> {
+        self.handles
+            .get(&(TypeId::of::<T>(), path.to_string()))
+            .map(|index| Self::handle(*index))
+    }
+
+    // every loaded T, in handle order
+    pub fn all<T: Asset>(&self) -> Vec<T::Shared> {
+        self.storage::<T>().cloned().unwrap_or_default()
+    }
+
+    pub fn load_text(&mut self, path: &str) -> Result<Handle<String>> {
+        self.load_with(path, |bytes| {
+            Ok(Rc::new(String::from_utf8(bytes.into_owned())?))
+        })
+    }
+
+    pub fn load_shader(&mut self, path: &str) -> Result<Handle<Shader>> {
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow!("{} isn't a file", path))?;
+        self.load_with(path, |bytes| {
+            Ok(Rc::new(Shader {
+                name,
+                source: String::from_utf8(bytes.into_owned())?,
+            }))
+        })
+    }
+
+    pub fn load_font(&mut self, path: &str) -> Result<Handle<Font>> {
+        self.load_with(path, |bytes| {
+            Ok(Rc::new(Font {
+                data: bytes.into_owned(),
+            }))
+        })
+    }
+
+    pub fn load_image(&mut self, path: &str) -> Result<Handle<image::DynamicImage>> {
+        self.load_with(path, |bytes| Ok(Rc::new(image::load_from_memory(&bytes)?)))
+    }
+
+    pub fn load_texture(
+        &mut self,
+        context: &context::Context,
+        path: &str,
+        normal_path: Option<&str>,
+        manual_premultiply: bool,
+    ) -> Result<Handle<texture::Texture>> {
+        if let Some(handle) = self.get_handle(path) {
+            return Ok(handle);
+        }
+        self.texture_options.insert(
+            path.to_string(),
+            (normal_path.map(str::to_string), manual_premultiply),
+        );
+        let texture = self.texture(context, path)?;
+        Ok(self.insert(path, texture))
+    }
+
+    pub fn load_sprite_sheet(
+        &mut self,
+        context: &context::Context,
+        label: &'static str,
+        path: &str,
+        normal_path: Option<&str>,
+        (sprite_width, sprite_height): (u32, u32),
+        manual_premultiply: bool,
+    ) -> Result<Handle<sprite::SpriteSheet>> {
+        if let Some(handle) = self.get_handle(path) {
+            return Ok(handle);
+        }
+        let texture = self.load_texture(context, path, normal_path, manual_premultiply)?;
+        let texture = self.get(texture);
+        Ok(self.insert(
+            path,
+            Rc::new(RefCell::new(sprite::SpriteSheet::new(
+                label,
+                texture,
+                sprite_width,
+                sprite_height,
+            ))),
+        ))
+    }
+
+    pub fn reload_text(&mut self, path: &str) -> Result<()> {
+        self.reload_with::<String>(path, |bytes| {
+            Ok(Rc::new(String::from_utf8(bytes.into_owned())?))
+        })
+    }
+
+    pub fn reload_shader(&mut self, path: &str) -> Result<()> {
+        let name = self
+            .get_handle::<Shader>(path)
+            .map(|handle| self.get(handle).name.clone())
+            .ok_or_else(|| anyhow!("{} isn't loaded", path))?;
+        self.reload_with::<Shader>(path, |bytes| {
+            Ok(Rc::new(Shader {
+                name,
+                source: String::from_utf8(bytes.into_owned())?,
+            }))
+        })
+    }
+
+    // reloads every texture read from the path, as the image or as its normal map, and points
+    // the sprite sheets cut from them at the new texture. atlases with the image in them are
+    // packed again. the renderer has to be handed the textures again afterwards, if anything was
+    // reloaded
+    pub fn reload_textures(&mut self, context: &context::Context, path: &str) -> Result<bool> {
+        let texture_paths: Vec<String> = self
+            .texture_options
+            .iter()
+            .filter(|(texture_path, (normal_path, _))| {
+                *texture_path == path || normal_path.as_deref() == Some(path)
+            })
+            .map(|(texture_path, _)| texture_path.clone())
+            .collect();
+        let atlases: Vec<LoadedAtlas> = self
+            .atlases
+            .iter()
+            .filter(|atlas| atlas.paths.iter().any(|atlas_path| atlas_path == path))
+            .cloned()
+            .collect();
+
+        texture_paths.iter().try_for_each(|texture_path| {
+            let texture = self.texture(context, texture_path)?;
+            if let Some(handle) = self.get_handle::<sprite::SpriteSheet>(texture_path) {
+                self.get(handle).borrow_mut().set_texture(texture.clone());
+            }
+            self.replace::<texture::Texture>(texture_path, texture)
+        })?;
+
+        if !atlases.is_empty() {
+            self.reload_with::<image::DynamicImage>(path, |bytes| {
+                Ok(Rc::new(image::load_from_memory(&bytes)?))
+            })?;
+        }
+        atlases.iter().try_for_each(|loaded| {
+            let (atlas, pages) = self.pack_atlas(context, loaded)?;
+            loaded.paths.iter().try_for_each(|sheet_path| {
+                let region = atlas.regions[sheet_path];
+                let handle = self
+                    .get_handle::<sprite::SpriteSheet>(sheet_path)
+                    .ok_or_else(|| anyhow!("{} isn't loaded", sheet_path))?;
+                self.get(handle).borrow_mut().set_atlas_region(
+                    pages[region.page].clone(),
+                    atlas.uv_rect(sheet_path).unwrap(),
+                    (region.rect.width, region.rect.height),
+                )
+            })
+        })?;
+
+        Ok(!texture_paths.is_empty() || !atlases.is_empty())
+    }
+
+    // packs the sprite sheets into as few textures as it can, so they take up fewer bindings.
+    // the handles are in the order the sheets are given in. editing one of the images packs the
+    // atlas again, see reload_textures
+    pub fn load_atlas(
+        &mut self,
+        context: &context::Context,
+        name: &str,
+        sheets: &[AtlasSheet],
+        manual_premultiply: bool,
+    ) -> Result<Vec<Handle<sprite::SpriteSheet>>> {
+        if let Some(sheet) = sheets
+            .iter()
+            .find(|sheet| self.get_handle::<sprite::SpriteSheet>(sheet.path).is_some())
+        {
+            return Err(anyhow!(
+                "{} is already loaded as a sprite sheet",
+                sheet.path
+            ));
+        }
+
+        sheets
+            .iter()
+            .try_for_each(|sheet| self.load_image(sheet.path).map(|_| ()))?;
+        let loaded = LoadedAtlas {
+            name: name.to_string(),
+            paths: sheets.iter().map(|sheet| sheet.path.to_string()).collect(),
+            manual_premultiply,
+        };
+        let (atlas, pages) = self.pack_atlas(context, &loaded)?;
+        self.atlases.push(loaded);
+
+        Ok(sheets
+            .iter()
+            .map(|sheet| {
+                let region = atlas.regions[sheet.path];
+                let sprite_sheet = sprite::SpriteSheet::from_atlas(
+                    sheet.label,
+                    pages[region.page].clone(),
+                    atlas.uv_rect(sheet.path).unwrap(),
+                    (region.rect.width, region.rect.height),
+                    sheet.sprite_size.0,
+                    sheet.sprite_size.1,
+                );
+                self.insert(sheet.path, Rc::new(RefCell::new(sprite_sheet)))
+            })
+            .collect())
+    }
+
+    // the images have to be loaded already
+    fn pack_atlas(
+        &self,
+        context: &context::Context,
+        loaded: &LoadedAtlas,
+    ) -> Result<(atlas::Atlas, Vec<Arc<texture::Texture>>)> {
+        let page_size = context
+            .device
+            .limits()
+            .max_texture_dimension_2d
+            .min(Self::ATLAS_PAGE_SIZE);
+        let mut atlas_builder = atlas::AtlasBuilder::new((page_size, page_size), 1);
+        loaded.paths.iter().try_for_each(|path| {
+            let image = self
+                .get_handle::<image::DynamicImage>(path)
+                .ok_or_else(|| anyhow!("{} isn't loaded", path))?;
+            atlas_builder.add(path, &self.get(image))
+        })?;
+        let atlas = atlas_builder
+            .build()
+            .with_context(|| format!("failed to pack atlas {}", loaded.name))?;
+
+        let pages = atlas
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(index, page)| {
+                Ok(Arc::new(texture::Texture::from_image(
+                    &context.device,
+                    &context.queue,
+                    &image::DynamicImage::ImageRgba8(page.clone()),
+                    None,
+                    Some(&format!("{} atlas page {}", loaded.name, index)),
+                    loaded.manual_premultiply,
+                )?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((atlas, pages))
+    }
+
+    fn texture(&self, context: &context::Context, path: &str) -> Result<Arc<texture::Texture>> {
+        let (normal_path, manual_premultiply) = &self.texture_options[path];
+        let bytes = self.read(path)?;
+        let normal_bytes = normal_path
+            .as_deref()
+            .map(|normal_path| self.read(normal_path))
+            .transpose()?;
+        Ok(Arc::new(
+            texture::Texture::from_bytes(
+                &context.device,
+                &context.queue,
+                &bytes,
+                normal_bytes.as_deref(),
+                path,
+                *manual_premultiply,
+            )
+            .with_context(|| format!("failed to load {}", path))?,
+        ))
+    }
+
+    // anything loaded from the path, including normal maps
+    fn is_loaded(&self, path: &str) -> bool {
+        self.handles.keys().any(|(_, loaded)| loaded == path)
+            || self
+                .texture_options
+                .values()
+                .any(|(normal_path, _)| normal_path.as_deref() == Some(path))
+    }
+
+    fn load_with<T: Asset>(
+        &mut self,
+        path: &str,
+        load: impl FnOnce(Cow<'static, [u8]>) -> Result<T::Shared>,
+    ) -> Result<Handle<T>> {
+        if let Some(handle) = self.get_handle(path) {
+            return Ok(handle);
+        }
+        let asset = load(self.read(path)?).with_context(|| format!("failed to load {}", path))?;
+        Ok(self.insert(path, asset))
+    }
+
+    // loads the asset again into the same slot, so its handle stays valid. whatever still holds
+    // the old T::Shared keeps the old asset
+    fn reload_with<T: Asset>(
+        &mut self,
+        path: &str,
+        load: impl FnOnce(Cow<'static, [u8]>) -> Result<T::Shared>,
+    ) -> Result<()> {
+        let asset = load(self.read(path)?).with_context(|| format!("failed to load {}", path))?;
+        self.replace::<T>(path, asset)
+    }
+
+    fn replace<T: Asset>(&mut self, path: &str, asset: T::Shared) -> Result<()> {
+        let handle = self
+            .get_handle::<T>(path)
+            .ok_or_else(|| anyhow!("{} isn't loaded", path))?;
+        self.assets
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.downcast_mut::<Vec<T::Shared>>())
+            .expect("loaded assets have storage")[handle.index as usize] = asset;
+        Ok(())
+    }
+
+    fn insert<T: Asset>(&mut self, path: &str, asset: T::Shared) -> Handle<T> {
+        let storage = self
+            .assets
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Vec::<T::Shared>::new()))
+            .downcast_mut::<Vec<T::Shared>>()
+            .unwrap();
+        storage.push(asset);
+
+        let index = storage.len() as u32 - 1;
+        self.handles
+            .insert((TypeId::of::<T>(), path.to_string()), index);
+        Self::handle(index)
+    }
+
+    fn storage<T: Asset>(&self) -> Option<&Vec<T::Shared>> {
+        self.assets
+            .get(&TypeId::of::<T>())
+            .map(|storage| storage.downcast_ref::<Vec<T::Shared>>().unwrap())
+    }
+
+    fn handle<T>(index: u32) -> Handle<T> {
+        Handle {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, ensure, Result};
+use cgmath::Vector2;
+use image::{GenericImage, GenericImageView};
+
+// in pixels, from the top left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// where an added image ended up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub rect: Rect,
+}
+
+pub struct Atlas {
+    pub pages: Vec<image::RgbaImage>,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    // top left corner and size of the region in texture coordinates of its page
+    pub fn uv_rect(&self, name: &str) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let region = self.regions.get(name)?;
+        let (page_width, page_height) = self.pages[region.page].dimensions();
+        Some((
+            Vector2::new(
+                region.rect.x as f32 / page_width as f32,
+                region.rect.y as f32 / page_height as f32,
+            ),
+            Vector2::new(
+                region.rect.width as f32 / page_width as f32,
+                region.rect.height as f32 / page_height as f32,
+            ),
+        ))
+    }
+}
+
+// packs images, or parts of them, into as few pages as it can. it's a shelf packer: the tallest
+// images go first, left to right in rows. images are kept padding pixels apart so sampling at
+// the edge of one doesn't pick up its neighbour.
+pub struct AtlasBuilder {
+    page_size: (u32, u32),
+    padding: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(page_size: (u32, u32), padding: u32) -> Self {
+        Self {
+            page_size,
+            padding,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, image: &image::DynamicImage) -> Result<()> {
+        let (width, height) = image.dimensions();
+        self.add_rect(
+            name,
+            image,
+            Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        )
+    }
+
+    pub fn add_rect(&mut self, name: &str, image: &image::DynamicImage, rect: Rect) -> Result<()> {
+        ensure!(
+            self.images.iter().all(|(added, _)| added != name),
+            "{} is already in the atlas",
+            name
+        );
+        ensure!(
+            rect.width > 0
+                && rect.height > 0
+                && rect.x + rect.width <= image.width()
+                && rect.y + rect.height <= image.height(),
+            "{:?} of {} isn't inside the image",
+            rect,
+            name
+        );
+        ensure!(
+            rect.width <= self.page_size.0 && rect.height <= self.page_size.1,
+            "{} ({}x{}) doesn't fit on a {}x{} atlas page",
+            name,
+            rect.width,
+            rect.height,
+            self.page_size.0,
+            self.page_size.1
+        );
+
+        self.images.push((
+            name.to_string(),
+            image
+                .view(rect.x, rect.y, rect.width, rect.height)
+                .to_image(),
+        ));
+        Ok(())
+    }
+
+    // pages are cropped to what's on them, so they can come out smaller than the page size
+    pub fn build(mut self) -> Result<Atlas> {
+        self.images
+            .sort_by_key(|(_, image)| std::cmp::Reverse((image.height(), image.width())));
+
+        // the next free spot on the last page, and the height of the shelf it's on
+        let mut cursor = (0, 0);
+        let mut shelf_height = 0;
+        let mut page = 0;
+        let mut regions = HashMap::new();
+        for (name, image) in self.images.iter() {
+            let (width, height) = image.dimensions();
+            if cursor.0 + width > self.page_size.0 {
+                cursor = (0, cursor.1 + shelf_height + self.padding);
+                shelf_height = 0;
+            }
+            if cursor.1 + height > self.page_size.1 {
+                cursor = (0, 0);
+                shelf_height = 0;
+                page += 1;
+            }
+
+            regions.insert(
+                name.clone(),
+                AtlasRegion {
+                    page,
+                    rect: Rect {
+                        x: cursor.0,
+                        y: cursor.1,
+                        width,
+                        height,
+                    },
+                },
+            );
+            cursor.0 += width + self.padding;
+            shelf_height = shelf_height.max(height);
+        }
+
+        let mut pages: Vec<image::RgbaImage> = (0..=page)
+            .map(|page| {
+                let (width, height) = regions
+                    .values()
+                    .filter(|region: &&AtlasRegion| region.page == page)
+                    .fold((1, 1), |(width, height), region| {
+                        (
+                            width.max(region.rect.x + region.rect.width),
+                            height.max(region.rect.y + region.rect.height),
+                        )
+                    });
+                image::RgbaImage::new(width, height)
+            })
+            .collect();
+        self.images.iter().try_for_each(|(name, image)| {
+            let region = &regions[name];
+            pages[region.page]
+                .copy_from(image, region.rect.x, region.rect.y)
+                .map_err(|e| anyhow!("failed to copy {} into the atlas: {}", name, e))
+        })?;
+
+        Ok(Atlas { pages, regions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, value: u8) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            width,
+            height,
+            image::Rgba([value, value, value, 255]),
+        ))
+    }
+
+    fn region(page: usize, x: u32, y: u32, width: u32, height: u32) -> AtlasRegion {
+        AtlasRegion {
+            page,
+            rect: Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+        }
+    }
+
+    #[test]
+    fn packs_tallest_first_in_padded_shelves() {
+        let mut builder = AtlasBuilder::new((16, 16), 1);
+        builder.add("b", &image(6, 4, 2)).unwrap();
+        builder.add("c", &image(4, 4, 3)).unwrap();
+        builder.add("a", &image(8, 8, 1)).unwrap();
+        builder.add("d", &image(8, 6, 4)).unwrap();
+        let atlas = builder.build().unwrap();
+
+        assert_eq!(atlas.regions["a"], region(0, 0, 0, 8, 8));
+        // d doesn't fit next to a with the padding, so it starts the next shelf
+        assert_eq!(atlas.regions["d"], region(0, 0, 9, 8, 6));
+        assert_eq!(atlas.regions["b"], region(0, 9, 9, 6, 4));
+        // and c doesn't fit on the page at all anymore
+        assert_eq!(atlas.regions["c"], region(1, 0, 0, 4, 4));
+
+        // pages are cropped to what's on them
+        assert_eq!(atlas.pages[0].dimensions(), (15, 15));
+        assert_eq!(atlas.pages[1].dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn padding_keeps_images_apart() {
+        let mut builder = AtlasBuilder::new((32, 32), 2);
+        (0..20).for_each(|i| {
+            builder
+                .add(&i.to_string(), &image(3 + i % 5, 2 + i % 7, i as u8 + 1))
+                .unwrap()
+        });
+        let atlas = builder.build().unwrap();
+
+        let regions: Vec<_> = atlas.regions.iter().collect();
+        regions.iter().for_each(|(name, region)| {
+            // every pixel of the region is the image that was added under its name
+            let value = name.parse::<u8>().unwrap() + 1;
+            let page = &atlas.pages[region.page];
+            (region.rect.y..region.rect.y + region.rect.height).for_each(|y| {
+                (region.rect.x..region.rect.x + region.rect.width)
+                    .for_each(|x| assert_eq!(page.get_pixel(x, y).0[0], value))
+            });
+        });
+        regions.iter().enumerate().for_each(|(i, (_, a))| {
+            regions[i + 1..].iter().for_each(|(_, b)| {
+                assert!(
+                    a.page != b.page
+                        || a.rect.x + a.rect.width + 2 <= b.rect.x
+                        || b.rect.x + b.rect.width + 2 <= a.rect.x
+                        || a.rect.y + a.rect.height + 2 <= b.rect.y
+                        || b.rect.y + b.rect.height + 2 <= a.rect.y,
+                    "{:?} and {:?} are less than the padding apart",
+                    a,
+                    b
+                )
+            })
+        });
+    }
+
+    #[test]
+    fn add_rect_copies_part_of_the_image() {
+        let mut source = image::RgbaImage::new(4, 4);
+        source.put_pixel(2, 1, image::Rgba([7, 7, 7, 255]));
+        let mut builder = AtlasBuilder::new((8, 8), 0);
+        builder
+            .add_rect(
+                "part",
+                &image::DynamicImage::ImageRgba8(source),
+                Rect {
+                    x: 2,
+                    y: 1,
+                    width: 2,
+                    height: 3,
+                },
+            )
+            .unwrap();
+        let atlas = builder.build().unwrap();
+
+        assert_eq!(atlas.pages[0].dimensions(), (2, 3));
+        assert_eq!(atlas.pages[0].get_pixel(0, 0).0, [7, 7, 7, 255]);
+        assert_eq!(atlas.pages[0].get_pixel(1, 0).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rejects_bad_images() {
+        let mut builder = AtlasBuilder::new((8, 8), 0);
+        builder.add("a", &image(2, 2, 1)).unwrap();
+        assert!(builder.add("a", &image(2, 2, 1)).is_err());
+        assert!(builder.add("big", &image(9, 2, 1)).is_err());
+        assert!(builder
+            .add_rect(
+                "outside",
+                &image(4, 4, 1),
+                Rect {
+                    x: 2,
+                    y: 2,
+                    width: 3,
+                    height: 1,
+                },
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn uv_rect_is_relative_to_the_page() {
+        let mut builder = AtlasBuilder::new((16, 16), 0);
+        builder.add("a", &image(8, 4, 1)).unwrap();
+        builder.add("b", &image(2, 2, 1)).unwrap();
+        let atlas = builder.build().unwrap();
+
+        // the page is cropped to 10x4
+        assert_eq!(
+            atlas.uv_rect("b"),
+            Some((Vector2::new(0.8, 0.), Vector2::new(0.2, 0.5)))
+        );
+        assert_eq!(atlas.uv_rect("missing"), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use cgmath::Vector2;
+
+use crate::component::Entity;
+
+type CellRange = ((i32, i32), (i32, i32));
+
+// a uniform grid for finding the colliders near a box without looking at all of them. an entity
+// is in every cell its bounds overlap, including ones they only touch the edge of, so colliders
+// that just touch a box are found too. bounds that span too many cells to list, or aren't
+// finite, are kept aside and come up in every query. entities are on collision layers, as bits,
+// and queries only find the ones on a layer in the mask they're given
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Entity, u32)>>,
+    // None for the entities kept aside
+    entries: HashMap<Entity, (Option<CellRange>, u32)>,
+    oversized: Vec<(Entity, u32)>,
+}
+
+impl SpatialGrid {
+    const MAX_CELLS_PER_ENTITY: f32 = 1024.;
+
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.);
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entries: HashMap::new(),
+            oversized: Vec::new(),
+        }
+    }
+
+    fn cell_range(&self, min: Vector2<f32>, max: Vector2<f32>) -> Option<CellRange> {
+        let cell = |point: Vector2<f32>| {
+            (
+                (point.x / self.cell_size).floor(),
+                (point.y / self.cell_size).floor(),
+            )
+        };
+        let (min, max) = (cell(min), cell(max));
+        let count = (max.0 - min.0 + 1.) * (max.1 - min.1 + 1.);
+        if !(1. ..=Self::MAX_CELLS_PER_ENTITY).contains(&count) {
+            return None;
+        }
+        Some(((min.0 as i32, min.1 as i32), (max.0 as i32, max.1 as i32)))
+    }
+
+    fn cells_in(((min_x, min_y), (max_x, max_y)): CellRange) -> impl Iterator<Item = (i32, i32)> {
+        (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+    }
+
+    // adds the entity, or moves it if its bounds have changed cells
+    pub fn update(&mut self, entity: Entity, min: Vector2<f32>, max: Vector2<f32>, layers: u32) {
+        let entry = (self.cell_range(min, max), layers);
+        match self.entries.get(&entity) {
+            Some(existing) if *existing == entry => return,
+            Some(_) => self.remove(entity),
+            None => {}
+        }
+
+        match entry.0 {
+            Some(range) => Self::cells_in(range)
+                .for_each(|cell| self.cells.entry(cell).or_default().push((entity, layers))),
+            None => self.oversized.push((entity, layers)),
+        }
+        self.entries.insert(entity, entry);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        match self.entries.remove(&entity) {
+            Some((Some(range), _)) => Self::cells_in(range).for_each(|cell| {
+                if let Some(entities) = self.cells.get_mut(&cell) {
+                    entities.retain(|(in_cell, _)| *in_cell != entity);
+                    if entities.is_empty() {
+                        self.cells.remove(&cell);
+                    }
+                }
+            }),
+            Some((None, _)) => self.oversized.retain(|(oversized, _)| *oversized != entity),
+            None => {}
+        }
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(Entity) -> bool) {
+        let removed: Vec<Entity> = self
+            .entries
+            .keys()
+            .copied()
+            .filter(|entity| !keep(*entity))
+            .collect();
+        removed.into_iter().for_each(|entity| self.remove(entity));
+    }
+
+    // the entities whose cells the bounds overlap, sorted and without duplicates. they're only
+    // near the bounds, whether they actually overlap is up to the caller
+    pub fn query(&self, min: Vector2<f32>, max: Vector2<f32>, mask: u32) -> Vec<Entity> {
+        let in_mask = |(entity, layers): &(Entity, u32)| (layers & mask != 0).then_some(*entity);
+        let mut entities: Vec<Entity> = self.oversized.iter().filter_map(in_mask).collect();
+        match self.cell_range(min, max) {
+            Some(range) => Self::cells_in(range)
+                .filter_map(|cell| self.cells.get(&cell))
+                .for_each(|in_cell| entities.extend(in_cell.iter().filter_map(in_mask))),
+            None => entities.extend(
+                self.entries
+                    .iter()
+                    .filter(|(_, (_, layers))| layers & mask != 0)
+                    .map(|(entity, _)| *entity),
+            ),
+        }
+        entities.sort();
+        entities.dedup();
+        entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slotmap::SlotMap;
+
+    fn entities(count: usize) -> Vec<Entity> {
+        let mut entities = SlotMap::<Entity, ()>::with_key();
+        (0..count).map(|_| entities.insert(())).collect()
+    }
+
+    fn v(x: f32, y: f32) -> Vector2<f32> {
+        Vector2::new(x, y)
+    }
+
+    #[test]
+    fn query_finds_entities_in_overlapping_cells() {
+        let e = entities(3);
+        let mut grid = SpatialGrid::new(10.);
+        grid.update(e[0], v(1., 1.), v(4., 4.), 1);
+        grid.update(e[1], v(-15., -5.), v(-12., -1.), 1);
+        grid.update(e[2], v(45., 45.), v(55., 55.), 1);
+
+        assert_eq!(grid.query(v(0., 0.), v(5., 5.), u32::MAX), vec![e[0]]);
+        assert_eq!(grid.query(v(-11., -11.), v(-1., -1.), u32::MAX), vec![e[1]]);
+        // e[2] spans four cells, any of them finds it
+        assert_eq!(grid.query(v(41., 58.), v(42., 59.), u32::MAX), vec![e[2]]);
+        assert_eq!(grid.query(v(100., 100.), v(101., 101.), u32::MAX), vec![]);
+        assert_eq!(grid.len(), 3);
+    }
+
+    #[test]
+    fn query_finds_entities_touching_cell_edges() {
+        let e = entities(1);
+        let mut grid = SpatialGrid::new(10.);
+        grid.update(e[0], v(0., 0.), v(10., 10.), 1);
+        assert_eq!(grid.query(v(10., 10.), v(15., 15.), u32::MAX), vec![e[0]]);
+    }
+
+    #[test]
+    fn query_filters_by_layer() {
+        let e = entities(3);
+        let mut grid = SpatialGrid::new(10.);
+        grid.update(e[0], v(0., 0.), v(1., 1.), 0b01);
+        grid.update(e[1], v(0., 0.), v(1., 1.), 0b10);
+        grid.update(e[2], v(0., 0.), v(1., 1.), 0b11);
+
+        assert_eq!(grid.query(v(0., 0.), v(1., 1.), 0b01), vec![e[0], e[2]]);
+        assert_eq!(grid.query(v(0., 0.), v(1., 1.), 0b10), vec![e[1], e[2]]);
+        assert_eq!(grid.query(v(0., 0.), v(1., 1.), 0), vec![]);
+    }
+
+    #[test]
+    fn update_moves_and_remove_removes() {
+        let e = entities(2);
+        let mut grid = SpatialGrid::new(10.);
+        grid.update(e[0], v(0., 0.), v(1., 1.), 1);
+        grid.update(e[1], v(0., 0.), v(1., 1.), 1);
+        grid.update(e[0], v(30., 0.), v(31., 1.), 1);
+
+        assert_eq!(grid.query(v(0., 0.), v(1., 1.), u32::MAX), vec![e[1]]);
+        assert_eq!(grid.query(v(30., 0.), v(31., 1.), u32::MAX), vec![e[0]]);
+        // changing layers moves it too
+        grid.update(e[0], v(30., 0.), v(31., 1.), 2);
+        assert_eq!(grid.query(v(30., 0.), v(31., 1.), 1), vec![]);
+
+        grid.remove(e[0]);
+        assert_eq!(grid.query(v(30., 0.), v(31., 1.), u32::MAX), vec![]);
+        grid.retain(|entity| entity != e[1]);
+        assert!(grid.is_empty());
+        assert!(grid.cells.is_empty());
+    }
+
+    #[test]
+    fn huge_bounds_come_up_everywhere() {
+        let e = entities(3);
+        let mut grid = SpatialGrid::new(1.);
+        grid.update(e[0], v(0., 0.), v(1000., 1000.), 1);
+        grid.update(e[1], v(f32::NEG_INFINITY, 0.), v(0., 1.), 1);
+        grid.update(e[2], v(500., 500.), v(501., 501.), 2);
+
+        assert_eq!(
+            grid.query(v(-50., -50.), v(-49., -49.), 1),
+            vec![e[0], e[1]]
+        );
+        // queries that span too many cells get everything in the mask
+        assert_eq!(
+            grid.query(v(-1e6, -1e6), v(1e6, 1e6), u32::MAX),
+            vec![e[0], e[1], e[2]]
+        );
+
+        grid.remove(e[0]);
+        grid.remove(e[1]);
+        assert_eq!(grid.query(v(-50., -50.), v(-49., -49.), 1), vec![]);
+    }
+}
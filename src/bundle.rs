@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath::ElementWise;
+
+use crate::component::{self, Entity};
+use crate::physics;
+use crate::sprite;
+use crate::world::{Bundle, World};
+
+pub fn bounding_box_for(position_component: &component::PositionComponent) -> physics::BoundingBox {
+    physics::BoundingBox {
+        position: position_component.position,
+        bottom_left_offset: -1.0 * position_component.scale / 2.0,
+        top_right_offset: position_component.scale / 2.0,
+        rotation: position_component.rotation,
+        pivot: position_component
+            .pivot
+            .mul_element_wise(position_component.scale),
+    }
+}
+
+// covers the entity's quad and only notices the player
+fn player_trigger(position_component: &component::PositionComponent) -> physics::TriggerComponent {
+    physics::TriggerComponent::new(
+        bounding_box_for(position_component),
+        &[physics::CollisionLayer::Player],
+    )
+}
+
+pub struct ParallaxLayerBundle {
+    pub position: component::PositionComponent,
+    pub texture_index: u32,
+    pub tex_coord_scale: cgmath::Vector2<f32>,
+    pub layer: u32,
+    pub move_speed: f32,
+}
+
+impl Bundle for ParallaxLayerBundle {
+    fn insert(self, world: &mut World, entity: Entity) {
+        world.insert(
+            entity,
+            component::VertexArrayComponent::textured_quad_with_coords(
+                self.texture_index,
+                component::VertexArrayComponent::BACKGROUND_Z * self.layer as f32,
+                self.tex_coord_scale,
+            ),
+        );
+        world.insert(entity, self.position);
+        world.insert(entity, component::MetadataComponent::new(false, false));
+        world.insert(
+            entity,
+            component::ParallaxComponent {
+                move_speed: self.move_speed,
+                layer: self.layer,
+            },
+        );
+    }
+}
+
+// solid ground/platform tile, add a MovingPlatformComponent on top of it to make it move
+pub struct PlatformTileBundle {
+    pub position: component::PositionComponent,
+    pub texture_index: u32,
+    pub sprite_sheet: Rc<RefCell<sprite::SpriteSheet>>,
+    pub sheet_position: cgmath::Vector2<u32>,
+}
+
+impl Bundle for PlatformTileBundle {
+    fn insert(self, world: &mut World, entity: Entity) {
+        world.insert(
+            entity,
+            component::VertexArrayComponent::textured_quad(
+                self.texture_index,
+                component::VertexArrayComponent::FOREGROUND_Z,
+            ),
+        );
+        world.insert(
+            entity,
+            physics::ColliderBoxComponent {
+                bounding_box: bounding_box_for(&self.position),
+                filter: physics::CollisionFilter {
+                    layers: physics::CollisionLayer::Terrain.bit(),
+                    ..physics::CollisionFilter::all()
+                },
+            },
+        );
+        world.insert(entity, self.position);
+        world.insert(entity, component::MetadataComponent::new(false, false));
+        world.insert(
+            entity,
+            sprite::SheetPositionComponent {
+                sprite_sheet: self.sprite_sheet,
+                sheet_position: self.sheet_position,
+            },
+        );
+    }
+}
+
+pub struct CollectibleScrollBundle {
+    pub position: component::PositionComponent,
+    pub texture_index: u32,
+    pub popup_text: String,
+}
+
+impl Bundle for CollectibleScrollBundle {
+    fn insert(self, world: &mut World, entity: Entity) {
+        let mut vertex_array_component = component::VertexArrayComponent::textured_quad(
+            self.texture_index,
+            component::VertexArrayComponent::OBJECT_Z,
+        );
+        vertex_array_component.shader_type = component::ShaderType::COLLECTIBLE;
+
+        world.insert(entity, vertex_array_component);
+        world.insert(
+            entity,
+            component::CollectibleComponent {
+                is_collected: false,
+                popup_text: self.popup_text,
+            },
+        );
+        world.insert(entity, player_trigger(&self.position));
+        world.insert(entity, self.position);
+        world.insert(entity, component::MetadataComponent::new(false, false));
+    }
+}
+
+pub struct SignpostBundle {
+    pub position: component::PositionComponent,
+    pub texture_index: u32,
+    pub popup_text: String,
+}
+
+impl Bundle for SignpostBundle {
+    fn insert(self, world: &mut World, entity: Entity) {
+        world.insert(
+            entity,
+            component::VertexArrayComponent::textured_quad(
+                self.texture_index,
+                component::VertexArrayComponent::OBJECT_Z,
+            ),
+        );
+        world.insert(
+            entity,
+            component::SignComponent {
+                in_range: false,
+                popup_text: self.popup_text,
+            },
+        );
+        world.insert(entity, player_trigger(&self.position));
+        world.insert(entity, self.position);
+        world.insert(entity, component::MetadataComponent::new(false, false));
+    }
+}
@@ -4,11 +4,7 @@ use num_traits::{clamp_max, clamp_min};
 // use log::debug;
 use wgpu::util::DeviceExt;
 
-use crate::{
-    component::{self, EntityMap},
-    uniform::WorldUniform,
-    utils,
-};
+use crate::{component, uniform::WorldUniform, world::World};
 
 // #[rustfmt::skip]
 // pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -330,6 +326,14 @@ impl OrthographicCamera {
         return self.center;
     }
 
+    // a point in the window, in physical pixels from its top left, in screen space
+    pub fn window_to_screen(&self, position: Vector2<f32>) -> Vector2<f32> {
+        Vector2::new(
+            self.center.x - self.width / 2. + position.x,
+            self.center.y - self.height / 2. + position.y,
+        )
+    }
+
     pub fn update_position(&mut self, position: Vector3<f32>) {
         self.center = position;
         self.uniform
@@ -367,12 +371,11 @@ impl CameraController {
     pub fn update(
         // context: &context::Context,
         player_position_vec: cgmath::Vector2<f32>,
-        camera: &mut OrthographicCamera,
-        world_uniform: &WorldUniform,
-        parallax_components: &mut EntityMap<component::ParallaxComponent>,
-        vertex_array_components: &mut EntityMap<component::VertexArrayComponent>,
-        position_components: &mut EntityMap<component::PositionComponent>,
+        world: &World,
     ) {
+        let mut camera = world.resource_mut::<OrthographicCamera>();
+        let camera = &mut *camera;
+        let world_uniform = world.resource::<WorldUniform>();
         let world_uniform_reg: cgmath::Matrix4<f32> = world_uniform.world_to_screen.into();
         let world_uniform_inv: cgmath::Matrix4<f32> = world_uniform.screen_to_world.into();
 
@@ -382,23 +385,16 @@ impl CameraController {
         let original_position =
             cgmath::Vector2::new(camera.width as f32 / 2.0, camera.height as f32 / 2.0);
 
-        let mut update_parallax = |_: cgmath::Vector2<f32>, camera: &mut OrthographicCamera| {
-            utils::zip3_entities_mut(
-                parallax_components,
-                vertex_array_components,
-                position_components,
-            )
-            .for_each(
-                |(_, parallax_component, vertex_array_component, position_component)| {
-                    if let (
-                        Some(parallax_component),
-                        Some(vertex_array_component),
-                        Some(position_component),
-                    ) = (
-                        parallax_component,
-                        vertex_array_component,
-                        position_component,
-                    ) {
+        let update_parallax = |_: cgmath::Vector2<f32>, camera: &mut OrthographicCamera| {
+            world
+                .query::<(
+                    &component::ParallaxComponent,
+                    &mut component::VertexArrayComponent,
+                    &mut component::PositionComponent,
+                )>()
+                .iter()
+                .for_each(
+                    |(_, (parallax_component, vertex_array_component, position_component))| {
                         let new_position_screen_space = cgmath::vec2(
                             clamp_min(camera.position().x, original_position.x),
                             clamp_max(camera.position().y, original_position.y),
@@ -428,9 +424,8 @@ impl CameraController {
                             );
 
                         position_component.position = new_position_world_space.xy();
-                    }
-                },
-            )
+                    },
+                )
         };
 
         if screen_position.x > camera.width as f32 / 2.0 {
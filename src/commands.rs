@@ -0,0 +1,58 @@
+use crate::component::Entity;
+use crate::transform::TransformSystem;
+use crate::world::{Bundle, World};
+
+type Command = Box<dyn FnOnce(&mut World)>;
+
+// structural changes queued up by systems while the world is borrowed, they get applied in
+// order by World::apply_commands at the schedule's sync point
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn spawn<B: Bundle + 'static>(&mut self, bundle: B) {
+        self.add(move |world| {
+            world.builder().with_bundle(bundle).build();
+        });
+    }
+
+    // also despawns any children and unlinks the entity from its parent
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| TransformSystem::despawn_recursive(world, entity));
+    }
+
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+        self.add(move |world| {
+            if world.contains(child) && world.contains(parent) {
+                if let Err(e) = TransformSystem::set_parent(world, child, parent) {
+                    log::error!("{:#}", e);
+                }
+            }
+        });
+    }
+
+    pub fn insert_component<T: 'static>(&mut self, entity: Entity, component: T) {
+        self.add(move |world| {
+            // the entity might have been despawned earlier in the queue
+            if world.contains(entity) {
+                world.insert(entity, component);
+            }
+        });
+    }
+
+    pub fn remove_component<T: 'static>(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    pub fn add(&mut self, command: impl FnOnce(&mut World) + 'static) {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn apply(self, world: &mut World) {
+        self.queue.into_iter().for_each(|command| command(world));
+    }
+}
@@ -1,12 +1,10 @@
 use std::f64;
 
-use cgmath::ElementWise;
-use slotmap::DenseSlotMap;
-
-use crate::physics;
+use cgmath::{ElementWise, Rotation, Rotation2};
+use slotmap::SecondaryMap;
 
 pub type Entity = slotmap::DefaultKey;
-pub type EntityMap<T> = DenseSlotMap<Entity, Option<T>>;
+pub type EntityMap<T> = SecondaryMap<Entity, T>;
 
 pub trait Component {
     fn name(&self) -> String;
@@ -131,6 +129,10 @@ impl VertexArrayComponent {
 pub struct PositionComponent {
     pub position: cgmath::Vector2<f32>,
     pub scale: cgmath::Vector2<f32>,
+    // radians, counter clockwise around the pivot
+    pub rotation: f32,
+    // relative to the center in unscaled quad space, so (0., 0.5) is the middle of the top edge
+    pub pivot: cgmath::Vector2<f32>,
 }
 
 impl Component for PositionComponent {
@@ -140,16 +142,72 @@ impl Component for PositionComponent {
 }
 
 impl PositionComponent {
+    pub fn new(position: cgmath::Vector2<f32>, scale: cgmath::Vector2<f32>) -> Self {
+        Self {
+            position,
+            scale,
+            rotation: 0.,
+            pivot: cgmath::Vector2::new(0., 0.),
+        }
+    }
+
+    // takes a vertex in unscaled quad space to world space
+    pub fn transform_point(&self, point: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+        let rotation = cgmath::Basis2::from_angle(cgmath::Rad(self.rotation));
+        let pivot = self.pivot.mul_element_wise(self.scale);
+        let from_pivot = (point - self.pivot).mul_element_wise(self.scale);
+        self.position + pivot + rotation.rotate_vector(from_pivot)
+    }
+
     pub fn scale_outward(&mut self, scale: cgmath::Vector2<f32>) {
         assert!(scale.x >= 1. && scale.y >= 1.);
         self.scale = self.scale.mul_element_wise(scale);
     }
 }
 
+// offset from the parent's position, in the parent's unflipped and unrotated space. the parent's
+// scale doesn't apply, the scale here is the child's own. the world space PositionComponent of a
+// child is overwritten by the transform system every frame
+#[derive(Debug, Clone)]
+pub struct LocalPositionComponent {
+    pub position: cgmath::Vector2<f32>,
+    pub scale: cgmath::Vector2<f32>,
+    pub rotation: f32,
+}
+
+impl Component for LocalPositionComponent {
+    fn name(&self) -> String {
+        "LocalPosition".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParentComponent {
+    pub parent: Entity,
+}
+
+impl Component for ParentComponent {
+    fn name(&self) -> String {
+        "Parent".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChildrenComponent {
+    pub children: Vec<Entity>,
+}
+
+impl Component for ChildrenComponent {
+    fn name(&self) -> String {
+        "Children".to_string()
+    }
+}
+
 #[derive(Eq, Hash, PartialEq, Clone, Debug)]
 pub enum CharacterState {
     IDLE,
     MOVE,
+    #[allow(unused)]
     ATTACK,
     JUMPUP,
     JUMPDOWN,
@@ -233,21 +291,64 @@ impl MetadataComponent {
 
 pub struct ParallaxComponent {
     pub move_speed: f32,
+    #[allow(unused)]
     pub layer: u32,
 }
 
 impl ParallaxComponent {}
 
+// stable name for an entity, entity keys change between runs so anything written to disk
+// refers to entities by this instead
+#[derive(Debug, Clone)]
+pub struct NameComponent {
+    pub name: String,
+}
+
+impl Component for NameComponent {
+    fn name(&self) -> String {
+        "Name".to_string()
+    }
+}
+
+// collectibles, signs, checkpoints, level exits and kill zones are triggers, these say what the player walking
+// into one does
 pub struct CollectibleComponent {
     pub is_collected: bool,
-    pub bounding_box: physics::BoundingBox,
-    pub popup_text: &'static str,
+    pub popup_text: String,
 }
 
 pub struct SignComponent {
     pub in_range: bool,
-    pub bounding_box: physics::BoundingBox,
-    pub popup_text: &'static str,
+    pub popup_text: String,
+}
+
+// the player respawns here after walking into it
+pub struct CheckpointComponent;
+
+impl Component for CheckpointComponent {
+    fn name(&self) -> String {
+        "Checkpoint".to_string()
+    }
+}
+
+// the player walking into it moves on to the scene at the path
+pub struct LevelExitComponent {
+    pub scene: String,
+}
+
+impl Component for LevelExitComponent {
+    fn name(&self) -> String {
+        "LevelExit".to_string()
+    }
+}
+
+// sends the player back to the last checkpoint, anything else in it is despawned
+pub struct KillZoneComponent;
+
+impl Component for KillZoneComponent {
+    fn name(&self) -> String {
+        "KillZone".to_string()
+    }
 }
 
 #[derive(Debug)]
@@ -257,6 +358,8 @@ pub struct MovingPlatformComponent {
     pub original_position: cgmath::Vector2<f32>,
     pub horizontal: bool,
     pub prev_change: f32,
+    // time spent moving, so where it is in its period
+    pub elapsed_secs: f32,
 }
 
 // pub fn update_platforms(
@@ -93,3 +93,16 @@ impl<'a> Context<'a> {
         self.surface.configure(&self.device, &self.config);
     }
 }
+
+// a device without a window, for tests that need textures. there isn't one without an adapter,
+// which can be a software one
+#[cfg(test)]
+pub fn headless_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }))?;
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+}
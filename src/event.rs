@@ -0,0 +1,215 @@
+use std::marker::PhantomData;
+
+use crate::component::Entity;
+
+#[derive(Debug, Clone)]
+pub enum EngineEvent {
+    Spawned(Entity),
+    Despawned(Entity),
+    ComponentAdded {
+        entity: Entity,
+        component: &'static str,
+    },
+    CollectibleCollected {
+        entity: Entity,
+        popup_text: String,
+    },
+    SignEntered(Entity),
+    SignExited(Entity),
+    Grounded(Entity),
+    Ungrounded(Entity),
+}
+
+impl EngineEvent {
+    pub fn entity(&self) -> Entity {
+        match self {
+            EngineEvent::Spawned(entity)
+            | EngineEvent::Despawned(entity)
+            | EngineEvent::ComponentAdded { entity, .. }
+            | EngineEvent::CollectibleCollected { entity, .. }
+            | EngineEvent::SignEntered(entity)
+            | EngineEvent::SignExited(entity)
+            | EngineEvent::Grounded(entity)
+            | EngineEvent::Ungrounded(entity) => *entity,
+        }
+    }
+}
+
+pub type Subscriber<T> = fn(&EngineEvent, &mut T);
+
+// events are sent into the world by whatever system detects them and handed to every
+// subscriber at the schedule's sync point, in the order they were sent
+pub struct EventBus<T> {
+    subscribers: Vec<Subscriber<T>>,
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        Self {
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+impl<T> EventBus<T> {
+    pub fn subscribe(&mut self, subscriber: Subscriber<T>) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn dispatch(&self, events: Vec<EngineEvent>, context: &mut T) {
+        events.iter().for_each(|event| {
+            self.subscribers
+                .iter()
+                .for_each(|subscriber| subscriber(event, context))
+        });
+    }
+}
+
+struct EventInstance<T> {
+    id: usize,
+    event: T,
+}
+
+// events live for two updates, so a reader that runs once per frame sees everything sent
+// since it last ran no matter where in the frame the sender is. each reader keeps its own
+// cursor, so any number of them can read the same events.
+pub struct Events<T> {
+    previous: Vec<EventInstance<T>>,
+    current: Vec<EventInstance<T>>,
+    event_count: usize,
+    // for sending from outside the frame loop, e.g. the gui or an async task
+    channel: (flume::Sender<T>, flume::Receiver<T>),
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            event_count: 0,
+            channel: flume::unbounded(),
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(EventInstance {
+            id: self.event_count,
+            event,
+        });
+        self.event_count += 1;
+    }
+
+    pub fn sender(&self) -> flume::Sender<T> {
+        self.channel.0.clone()
+    }
+
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader::default()
+    }
+
+    // called once per frame, drops everything from two updates ago
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+        while let Ok(event) = self.channel.1.try_recv() {
+            self.send(event);
+        }
+    }
+}
+
+pub struct EventReader<T> {
+    next_id: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> EventReader<T> {
+    pub fn read<'e>(&mut self, events: &'e Events<T>) -> impl Iterator<Item = &'e T> {
+        let next_id = self.next_id;
+        self.next_id = events.event_count;
+        events
+            .previous
+            .iter()
+            .chain(events.current.iter())
+            .filter(move |instance| instance.id >= next_id)
+            .map(|instance| &instance.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(reader: &mut EventReader<u32>, events: &Events<u32>) -> Vec<u32> {
+        reader.read(events).copied().collect()
+    }
+
+    #[test]
+    fn readers_see_each_event_once() {
+        let mut events = Events::default();
+        let mut reader = events.reader();
+        events.send(1);
+        events.send(2);
+        assert_eq!(read(&mut reader, &events), vec![1, 2]);
+        assert_eq!(read(&mut reader, &events), Vec::<u32>::new());
+        events.send(3);
+        assert_eq!(read(&mut reader, &events), vec![3]);
+    }
+
+    #[test]
+    fn readers_have_independent_cursors() {
+        let mut events = Events::default();
+        let (mut early, mut late) = (events.reader(), events.reader());
+        events.send(1);
+        assert_eq!(read(&mut early, &events), vec![1]);
+        events.send(2);
+        assert_eq!(read(&mut early, &events), vec![2]);
+        assert_eq!(read(&mut late, &events), vec![1, 2]);
+    }
+
+    #[test]
+    fn events_survive_one_update() {
+        let mut events = Events::default();
+        let mut reader = events.reader();
+        events.send(1);
+        events.update();
+        events.send(2);
+        assert_eq!(read(&mut reader, &events), vec![1, 2]);
+        // already read events don't come back after the swap
+        events.update();
+        events.send(3);
+        assert_eq!(read(&mut reader, &events), vec![3]);
+    }
+
+    #[test]
+    fn events_are_dropped_after_two_updates() {
+        let mut events = Events::default();
+        let mut reader = events.reader();
+        events.send(1);
+        events.update();
+        events.update();
+        events.send(2);
+        assert_eq!(read(&mut reader, &events), vec![2]);
+    }
+
+    #[test]
+    fn sender_events_arrive_on_update() {
+        let mut events = Events::default();
+        let mut reader = events.reader();
+        let sender = events.sender();
+        sender.send(1).unwrap();
+        events.send(2);
+        assert_eq!(read(&mut reader, &events), vec![2]);
+        events.update();
+        assert_eq!(read(&mut reader, &events), vec![1]);
+    }
+}
@@ -1,11 +1,23 @@
 use crate::{
-    component::{self, EntityMap},
-    physics, utils,
+    component::{self, Entity},
+    event::{EngineEvent, EventReader},
+    physics,
+    world::World,
 };
 
+// sent when the player closes a scroll or sign popup
+pub struct DialogueFinished;
+
+// sent when the player walks into a level exit, with the scene it leads to
+pub struct LevelExited {
+    pub scene: String,
+}
+
 pub struct PlatformerGameState {
     pub notes_collected: u32,
     pub character_init_position: cgmath::Vector2<f32>,
+    pub dialogue_reader: EventReader<DialogueFinished>,
+    pub trigger_reader: EventReader<physics::TriggerEvent>,
 }
 
 impl PlatformerGameState {
@@ -13,33 +25,104 @@ impl PlatformerGameState {
         Self {
             notes_collected: 0,
             character_init_position,
+            dialogue_reader: EventReader::default(),
+            trigger_reader: EventReader::default(),
         }
     }
 
-    pub fn update(
-        &mut self,
-        position_components: &mut EntityMap<component::PositionComponent>,
-        collider_box_components: &mut EntityMap<physics::ColliderBoxComponent>,
-        metadata_components: &mut EntityMap<component::MetadataComponent>,
+    pub fn update(&mut self, world: &World) {
+        self.handle_triggers(world);
+
+        world
+            .query::<(
+                &mut component::PositionComponent,
+                Option<&mut physics::ColliderBoxComponent>,
+                &component::MetadataComponent,
+            )>()
+            .iter()
+            .for_each(|(_, (pos, collider, metadata))| {
+                if metadata.is_controllable() && pos.position.y < 0. {
+                    self.respawn(pos, collider);
+                }
+            });
+    }
+
+    fn respawn(
+        &self,
+        pos: &mut component::PositionComponent,
+        collider: Option<&mut physics::ColliderBoxComponent>,
     ) {
-        utils::zip3_entities_mut(
-            position_components,
-            collider_box_components,
-            metadata_components,
-        )
-        .for_each(|(_, pos, collider, metadata)| {
-            if metadata.as_ref().unwrap().is_controllable() {
-                assert!(pos.is_some());
-                if let Some(pos) = pos {
-                    if pos.position.y < 0. {
-                        pos.position = self.character_init_position;
-                        if let Some(collider_box) = collider {
-                            collider_box.bounding_box.update(pos.position);
+        pos.position = self.character_init_position;
+        if let Some(collider_box) = collider {
+            collider_box.bounding_box.update(pos.position);
+        }
+    }
+
+    fn handle_triggers(&mut self, world: &World) {
+        let events = world.events::<physics::TriggerEvent>();
+        let is_player = |body: Entity| {
+            world
+                .get::<component::MetadataComponent>(body)
+                .is_some_and(|metadata| metadata.is_controllable())
+        };
+
+        self.trigger_reader
+            .read(&events)
+            .for_each(|event| match *event {
+                physics::TriggerEvent::Enter { trigger, body } if is_player(body) => {
+                    if let Some(mut collectible) =
+                        world.get_mut::<component::CollectibleComponent>(trigger)
+                    {
+                        if !collectible.is_collected {
+                            collectible.is_collected = true;
+                            world.send_event(EngineEvent::CollectibleCollected {
+                                entity: trigger,
+                                popup_text: collectible.popup_text.clone(),
+                            });
+                            world.commands().despawn(trigger);
                         }
                     }
+
+                    if let Some(mut sign) = world.get_mut::<component::SignComponent>(trigger) {
+                        sign.in_range = true;
+                        world.send_event(EngineEvent::SignEntered(trigger));
+                    }
+
+                    if world
+                        .get::<component::CheckpointComponent>(trigger)
+                        .is_some()
+                    {
+                        if let Some(pos) = world.get::<component::PositionComponent>(trigger) {
+                            self.character_init_position = pos.position;
+                        }
+                    }
+
+                    if let Some(level_exit) = world.get::<component::LevelExitComponent>(trigger) {
+                        world.send(LevelExited {
+                            scene: level_exit.scene.clone(),
+                        });
+                    }
+
+                    if world.get::<component::KillZoneComponent>(trigger).is_some() {
+                        if let Some(mut pos) = world.get_mut::<component::PositionComponent>(body) {
+                            let mut collider = world.get_mut::<physics::ColliderBoxComponent>(body);
+                            self.respawn(&mut pos, collider.as_deref_mut());
+                        }
+                    }
+                }
+                physics::TriggerEvent::Enter { trigger, body } => {
+                    if world.get::<component::KillZoneComponent>(trigger).is_some() {
+                        world.commands().despawn(body);
+                    }
+                }
+                physics::TriggerEvent::Exit { trigger, body } if is_player(body) => {
+                    if let Some(mut sign) = world.get_mut::<component::SignComponent>(trigger) {
+                        sign.in_range = false;
+                        world.send_event(EngineEvent::SignExited(trigger));
+                    }
                 }
-            }
-        });
+                physics::TriggerEvent::Stay { .. } | physics::TriggerEvent::Exit { .. } => {}
+            });
     }
 }
 
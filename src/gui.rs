@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use anyhow::Result;
 use bytemuck::Zeroable;
 use cgmath::num_traits::{clamp, clamp_max, clamp_min};
 use egui::epaint::Shadow;
@@ -16,7 +17,7 @@ use egui_winit::winit::window::Window;
 use egui_winit::State;
 use log::debug;
 
-use crate::{context, game, render_system};
+use crate::{asset, context, game, render_system};
 
 #[derive(Clone, Copy)]
 pub enum PopupType {
@@ -27,7 +28,7 @@ pub enum PopupType {
 pub struct GuiInfo {
     pub fps: u32,
     pub notes_collected: u32,
-    pub popup_text: &'static str,
+    pub popup_text: String,
     pub popup_type: PopupType,
 }
 
@@ -49,7 +50,8 @@ impl Gui {
         output_depth_format: Option<TextureFormat>,
         msaa_samples: u32,
         window: Arc<Window>,
-    ) -> Self {
+        asset_server: &mut asset::AssetServer,
+    ) -> Result<Self> {
         let egui_context = Context::default();
         let id = egui_context.viewport_id();
 
@@ -74,37 +76,31 @@ impl Gui {
             msaa_samples,
             false,
         );
-        let scroll_image = {
-            let rgba = image::load_from_memory(include_bytes!("../assets/scroll.png"))
-                .unwrap()
-                .to_rgba8()
-                .to_vec();
-            let image = ColorImage::from_rgba_unmultiplied([16, 16], &rgba);
-            egui_context.load_texture("scroll", image, TextureOptions::default())
+        let load_image = |asset_server: &mut asset::AssetServer, name: &str, path: &str| {
+            let image = asset_server.load_image(path)?;
+            let rgba = asset_server.get(image).to_rgba8();
+            let image = ColorImage::from_rgba_unmultiplied(
+                [rgba.width() as usize, rgba.height() as usize],
+                &rgba,
+            );
+            Ok::<_, anyhow::Error>(egui_context.load_texture(
+                name,
+                image,
+                TextureOptions::default(),
+            ))
         };
-
-        let scroll_background_image = {
-            let rgba = image::load_from_memory(include_bytes!("../assets/Pergament9.png"))
-                .unwrap()
-                .to_rgba8()
-                .to_vec();
-            let image = ColorImage::from_rgba_unmultiplied([1160, 965], &rgba);
-            egui_context.load_texture("scroll_background", image, TextureOptions::default())
-        };
-
-        let wood_background_image = {
-            let rgba = image::load_from_memory(include_bytes!("../assets/wood_bg.png"))
-                .unwrap()
-                .to_rgba8()
-                .to_vec();
-            let image = ColorImage::from_rgba_unmultiplied([879, 879], &rgba);
-            egui_context.load_texture("wood_background", image, TextureOptions::default())
-        };
-
+        let scroll_image = load_image(asset_server, "scroll", "assets/scroll.png")?;
+        let scroll_background_image =
+            load_image(asset_server, "scroll_background", "assets/Pergament9.png")?;
+        let wood_background_image =
+            load_image(asset_server, "wood_background", "assets/wood_bg.png")?;
+
+        let font = asset_server.load_font("assets/Geo-Regular.ttf")?;
+        let font = asset_server.get(font);
         let mut fonts = egui::FontDefinitions::default();
         fonts.font_data.insert(
             "Geo-Regular".to_owned(),
-            egui::FontData::from_static(include_bytes!("../assets/Geo-Regular.ttf")),
+            egui::FontData::from_owned(font.data.clone()),
         );
 
         fonts.families.insert(
@@ -114,7 +110,7 @@ impl Gui {
 
         egui_context.set_fonts(fonts);
 
-        Self {
+        Ok(Self {
             context: egui_context,
             state: egui_state,
             renderer: egui_renderer,
@@ -123,7 +119,7 @@ impl Gui {
             wood_background_image,
             scroll_offset: egui::Vec2::zeroed(),
             scroll_content_size: None,
-        }
+        })
     }
 
     // pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
@@ -276,7 +272,7 @@ impl Gui {
 
                                     let output = scroll_area.show(ui, |ui| {
                                         ui.vertical_centered(|ui| {
-                                            ui.label(RichText::new(info.popup_text).font(FontId {
+                                            ui.label(RichText::new(&info.popup_text).font(FontId {
                                                 size: 25.0,
                                                 family: egui::epaint::FontFamily::Name(
                                                     "Geo-Regular".into(),
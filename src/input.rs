@@ -1,6 +1,8 @@
 use crate::{
+    camera,
     component::{self, PositionComponent},
-    game, gui, utils,
+    game, gui, snapshot, tilemap, uniform,
+    world::World,
 };
 
 use winit::{
@@ -27,53 +29,36 @@ impl InputHandler {
             mouse_position: PhysicalPosition::new(0., 0.),
         }
     }
-    pub fn handle_key_state(
-        &mut self,
-        event: &KeyEvent,
-        position_components: &mut component::EntityMap<PositionComponent>,
-        character_state_components: &mut component::EntityMap<component::CharacterStateComponent>,
-        vertex_array_components: &mut component::EntityMap<component::VertexArrayComponent>,
-        metadata_components: &mut component::EntityMap<component::MetadataComponent>,
-        sign_components: &mut component::EntityMap<component::SignComponent>,
-        game_mode: &mut game::GameMode,
-        gui_info: &mut gui::GuiInfo,
-    ) {
-        let mut update_state = |state: component::CharacterState, is_flipped: Option<bool>| {
-            utils::zip4_entities_mut(
-                position_components,
-                character_state_components,
-                vertex_array_components,
-                metadata_components,
-            )
-            .for_each(
-                |(
-                    _,
-                    position_component,
-                    character_state_component,
-                    vertex_array_component,
-                    metadata_component,
-                )| {
-                    let metadata_component = metadata_component.as_ref().unwrap();
-                    match position_component {
-                        Some(position_component) if metadata_component.is_controllable() => {
-                            if let Some(character_state_component) =
-                                character_state_component.as_mut()
-                            {
-                                if metadata_component.can_jump() {
-                                    character_state_component.character_state = state.clone();
-                                }
+    pub fn handle_key_state(&mut self, event: &KeyEvent, world: &World) {
+        let mut game_mode = world.resource_mut::<game::GameMode>();
+        let update_state = |state: component::CharacterState, is_flipped: Option<bool>| {
+            world
+                .query::<(
+                    &PositionComponent,
+                    &component::MetadataComponent,
+                    Option<&mut component::CharacterStateComponent>,
+                    Option<&mut component::VertexArrayComponent>,
+                )>()
+                .iter()
+                .filter(|(_, (_, metadata_component, _, _))| metadata_component.is_controllable())
+                .for_each(
+                    |(
+                        _,
+                        (_, metadata_component, character_state_component, vertex_array_component),
+                    )| {
+                        if let Some(character_state_component) = character_state_component {
+                            if metadata_component.can_jump() {
+                                character_state_component.character_state = state.clone();
                             }
+                        }
 
-                            if let (Some(is_flipped), Some(vertex_array_component)) =
-                                (is_flipped, vertex_array_component)
-                            {
-                                vertex_array_component.is_flipped = is_flipped;
-                            }
+                        if let (Some(is_flipped), Some(vertex_array_component)) =
+                            (is_flipped, vertex_array_component)
+                        {
+                            vertex_array_component.is_flipped = is_flipped;
                         }
-                        _ => (),
-                    }
-                },
-            );
+                    },
+                );
             // let position = state.sprite.get_position();
 
             // state.sprite.update_position(position + delta)
@@ -89,7 +74,7 @@ impl InputHandler {
 
                 match event.state {
                     ElementState::Pressed => match event.physical_key {
-                        PhysicalKey::Code(KeyCode::Escape) => *game_mode = game::GameMode::STANDARD,
+                        PhysicalKey::Code(KeyCode::Escape) => world.send(game::DialogueFinished),
                         _ => (),
                     },
                     _ => (),
@@ -114,16 +99,23 @@ impl InputHandler {
                             self.right_pressed = true;
                             update_state(component::CharacterState::MOVE, Some(true));
                         }
+                        PhysicalKey::Code(KeyCode::F5) => {
+                            world.send(snapshot::SnapshotRequest::Save)
+                        }
+                        PhysicalKey::Code(KeyCode::F9) => {
+                            world.send(snapshot::SnapshotRequest::Load)
+                        }
                         PhysicalKey::Code(KeyCode::KeyX) => {
-                            sign_components.iter_mut().for_each(|(_, sign)| {
-                                sign.as_mut().map(|sign| {
+                            world.query::<&component::SignComponent>().iter().for_each(
+                                |(_, sign)| {
                                     if sign.in_range {
-                                        gui_info.popup_text = sign.popup_text;
+                                        let mut gui_info = world.resource_mut::<gui::GuiInfo>();
+                                        gui_info.popup_text = sign.popup_text.clone();
                                         gui_info.popup_type = gui::PopupType::WOOD;
                                         *game_mode = game::GameMode::POPUP;
                                     }
-                                });
-                            });
+                                },
+                            );
                         }
                         _ => (),
                     },
@@ -165,4 +157,37 @@ impl InputHandler {
     pub fn set_position(&mut self, position: PhysicalPosition<f64>) {
         self.mouse_position = position
     }
+
+    // with the editor feature, left click paints ground under the cursor and right click erases
+    // it. games change tiles through TilemapComponent::set instead
+    pub fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton, world: &World) {
+        if !cfg!(feature = "editor")
+            || *world.resource::<game::GameMode>() != game::GameMode::STANDARD
+            || state != ElementState::Pressed
+        {
+            return;
+        }
+        let is_ground = match button {
+            MouseButton::Left => true,
+            MouseButton::Right => false,
+            _ => return,
+        };
+
+        let screen_position = world
+            .resource::<camera::OrthographicCamera>()
+            .window_to_screen(cgmath::Vector2::new(
+                self.mouse_position.x as f32,
+                self.mouse_position.y as f32,
+            ));
+        let screen_to_world: cgmath::Matrix4<f32> = world
+            .resource::<uniform::WorldUniform>()
+            .screen_to_world
+            .into();
+        let position =
+            screen_to_world * cgmath::Vector4::new(screen_position.x, screen_position.y, 1.0, 1.0);
+        world.send(tilemap::PaintGround {
+            position: cgmath::Vector2::new(position.x, position.y),
+            is_ground,
+        });
+    }
 }
@@ -1,19 +1,36 @@
 mod animation;
+mod aseprite;
+mod asset;
+mod atlas;
+// public for benches/broad_phase.rs
+pub mod broad_phase;
+mod bundle;
 mod camera;
+mod commands;
 mod component;
 mod context;
+mod event;
 mod game;
 mod gui;
 mod input;
 mod model;
 mod physics;
+mod query;
 mod render_system;
+mod resources;
+mod scene;
+mod schedule;
+mod snapshot;
 mod sprite;
 mod state;
 mod texture;
+mod texture_registry;
+mod tiled;
+mod tilemap;
+mod transform;
 mod uniform;
-mod utils;
 mod wgsl_preprocessor;
+mod world;
 
 use egui_winit::winit;
 use egui_winit::winit::{
@@ -25,7 +42,7 @@ use egui_winit::winit::{
 use instant::Instant;
 use log::debug;
 
-use render_system::RenderOptions;
+use schedule::Schedule;
 use state::State;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -49,15 +66,13 @@ pub struct App {
     window: Option<Arc<Window>>,
     state: Option<state::State<'static>>,
     event_loop_proxy: EventLoopProxy<UserEvent>,
-    player: Option<component::Entity>,
-    render_options: RenderOptions,
+    schedule: Schedule<state::State<'static>>,
 
     last_fps: u32,
     frames: u32,
     start_time: Instant,
     seconds_elapsed: u64,
     last_frame_time: Duration,
-    ticks_elapsed: Duration,
     #[cfg(target_arch = "wasm32")]
     resources: Vec<Box<dyn std::fmt::Debug>>,
 }
@@ -69,36 +84,26 @@ impl App {
         let seconds_elapsed = 0;
         let last_frame_time = Duration::new(0, 0);
 
-        let ticks_elapsed = Duration::new(0, 0);
-
-        let render_options = RenderOptions {
-            finalize_to_stencil: false,
-            render_outline: false,
-            render_wireframe: false,
-            render_lights: false,
-        };
         Self {
-            render_options,
             window: None,
             state: None,
             event_loop_proxy: event_loop.create_proxy(),
-            player: None,
+            schedule: State::schedule(),
             start_time,
             last_fps: 0,
             frames,
             seconds_elapsed,
             last_frame_time,
-            ticks_elapsed,
             #[cfg(target_arch = "wasm32")]
             resources: vec![],
         }
     }
 
-    fn init_state(&mut self, mut state: State<'static>, window: Arc<Window>) {
-        let player = state.init();
+    fn init_state(&mut self, mut state: State<'static>, window: Arc<Window>) -> anyhow::Result<()> {
+        state.init()?;
         self.window = Some(window);
         self.state = Some(state);
-        self.player = Some(player);
+        Ok(())
     }
 }
 
@@ -170,7 +175,10 @@ impl ApplicationHandler<UserEvent> for App {
             let state = async move {
                 let _ = window.request_inner_size(PhysicalSize::new(1024, 768));
                 TimeoutFuture::new(500).await;
-                let state = state_future.await;
+                let state = match state_future.await {
+                    Ok(state) => state,
+                    Err(e) => return log::error!("{:#}", e),
+                };
                 assert!(event_loop_proxy
                     .send_event(UserEvent::StateReady(state, window.clone()))
                     .is_ok());
@@ -181,7 +189,13 @@ impl ApplicationHandler<UserEvent> for App {
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let state = pollster::block_on(State::new(window.clone()));
+            let state = match pollster::block_on(State::new(window.clone())) {
+                Ok(state) => state,
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    return event_loop.exit();
+                }
+            };
             // self.init_state(state, window);
             assert!(self
                 .event_loop_proxy
@@ -190,9 +204,12 @@ impl ApplicationHandler<UserEvent> for App {
         }
     }
 
-    fn user_event(&mut self, _: &ActiveEventLoop, event: UserEvent) {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
         let UserEvent::StateReady(state, window) = event;
-        self.init_state(state, window);
+        if let Err(e) = self.init_state(state, window) {
+            log::error!("{:#}", e);
+            event_loop.exit();
+        }
     }
 
     fn window_event(
@@ -201,65 +218,7 @@ impl ApplicationHandler<UserEvent> for App {
         window_id: WindowId,
         event: WindowEvent,
     ) {
-        if let (Some(ref mut state), Some(player)) = (&mut self.state, self.player) {
-            self.frames += 1;
-            let current_time = self.start_time.elapsed();
-            let delta_time = current_time - self.last_frame_time;
-            if current_time > Duration::new(self.seconds_elapsed + 1, 0) {
-                self.last_fps = self.frames;
-                #[cfg(not(target_arch = "wasm32"))]
-                debug!("FPS {:?}", self.last_fps);
-                self.frames = 0;
-                self.seconds_elapsed += 1;
-            }
-            self.last_frame_time = current_time;
-
-            self.ticks_elapsed += delta_time;
-            while self.ticks_elapsed > state::State::FIXED_UPDATE_DURATION {
-                state.physics_system.update(
-                    &state.input_handler,
-                    &mut state.position_components,
-                    &mut state.collider_box_components,
-                    &mut state.metadata_components,
-                    &mut state.physics_components,
-                    &mut state.collectible_components,
-                    &mut state.sign_components,
-                    &mut state.moving_platform_components,
-                    &mut state.character_state_components,
-                    current_time,
-                    &state.game_mode,
-                );
-
-                self.ticks_elapsed -= state::State::FIXED_UPDATE_DURATION;
-            }
-
-            sprite::SpriteSheetSystem::update(
-                &mut state.vertex_array_components,
-                &state.sheet_position_components,
-            );
-            animation::AnimationSystem::update_animations(
-                &mut state.sprite_animation_controller_components,
-                &mut state.sheet_position_components,
-                &mut state.character_state_components,
-                delta_time,
-            );
-
-            let player_position = state.position_components.get(player);
-            assert!(player_position.is_some() && player_position.unwrap().is_some());
-            camera::CameraController::update(
-                // &state.context,
-                player_position.unwrap().as_ref().unwrap().position,
-                &mut state.camera,
-                &state.world_uniform,
-                &mut state.parallax_components,
-                &mut state.vertex_array_components,
-                &mut state.position_components,
-            );
-
-            state.update_platformer_game_state();
-
-            state.gui_info.fps = self.last_fps as u32;
-
+        if let Some(ref mut state) = &mut self.state {
             if state
                 .gui
                 .state
@@ -285,52 +244,48 @@ impl ApplicationHandler<UserEvent> for App {
                         state.resize(physical_size)
                     }
                     WindowEvent::RedrawRequested => {
-                        let render_result = state.render_system.render(
-                            &mut self.render_options,
-                            &state.position_components,
-                            &state.vertex_array_components,
-                            &state.light_components,
-                            &state.metadata_components,
-                            &state.context,
-                            &mut state.gui,
-                            state.window.clone(),
-                            current_time,
-                            &state.world_uniform,
-                            &state.camera,
-                            &mut state.gui_info,
-                            &mut state.game_mode,
-                        );
-                        match render_result {
-                            Ok(_) => {}
-                            // Reconfigure the surface if lost
-                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                                state.resize(state.size)
-                            }
-                            // The system is out of memory, we should probably quit
-                            Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
-                            // All other errors (Outdated, Timeout) should be resolved by the next frame
-                            Err(e) => eprintln!("{:?}", e),
+                        self.frames += 1;
+                        let current_time = self.start_time.elapsed();
+                        let delta_time = current_time - self.last_frame_time;
+                        if current_time > Duration::new(self.seconds_elapsed + 1, 0) {
+                            self.last_fps = self.frames;
+                            #[cfg(not(target_arch = "wasm32"))]
+                            debug!("FPS {:?}", self.last_fps);
+                            self.frames = 0;
+                            self.seconds_elapsed += 1;
                         }
+                        self.last_frame_time = current_time;
+
+                        state.current_time = current_time;
+                        state.delta_time = delta_time;
+                        state.world.resource_mut::<gui::GuiInfo>().fps = self.last_fps;
+
+                        self.schedule.run_frame(state, delta_time);
                     }
 
                     WindowEvent::CursorMoved {
                         device_id: _,
                         position,
-                    } => state.input_handler.set_position(position),
+                    } => state
+                        .world
+                        .resource_mut::<input::InputHandler>()
+                        .set_position(position),
                     WindowEvent::KeyboardInput {
                         device_id: _,
                         event,
                         is_synthetic: _,
-                    } => state.input_handler.handle_key_state(
-                        &event,
-                        &mut state.position_components,
-                        &mut state.character_state_components,
-                        &mut state.vertex_array_components,
-                        &mut state.metadata_components,
-                        &mut state.sign_components,
-                        &mut state.game_mode,
-                        &mut state.gui_info,
-                    ),
+                    } => state
+                        .world
+                        .resource_mut::<input::InputHandler>()
+                        .handle_key_state(&event, &state.world),
+                    WindowEvent::MouseInput {
+                        device_id: _,
+                        state: element_state,
+                        button,
+                    } => state
+                        .world
+                        .resource_mut::<input::InputHandler>()
+                        .handle_mouse_input(element_state, button, &state.world),
 
                     _ => {}
                 }
@@ -1,18 +1,25 @@
+use std::collections::HashSet;
 use std::time::Duration;
 
-use cgmath::{Vector2, Zero};
+use cgmath::{InnerSpace, Rotation, Rotation2, Vector2, Zero};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    component::{self, Component, EntityMap, PositionComponent},
+    broad_phase::SpatialGrid,
+    component::{self, Component, Entity, PositionComponent},
+    event::EngineEvent,
     game,
     input::InputHandler,
-    utils,
+    world::World,
 };
 
 pub struct BoundingBox {
     pub position: Vector2<f32>,
     pub bottom_left_offset: Vector2<f32>,
     pub top_right_offset: Vector2<f32>,
+    // radians around position + pivot, the offsets are in the box's unrotated space
+    pub rotation: f32,
+    pub pivot: Vector2<f32>,
 }
 
 impl BoundingBox {
@@ -20,6 +27,36 @@ impl BoundingBox {
         self.position = position;
     }
 
+    fn is_axis_aligned(&self) -> bool {
+        self.rotation == 0.
+    }
+
+    fn basis(&self) -> cgmath::Basis2<f32> {
+        cgmath::Basis2::from_angle(cgmath::Rad(self.rotation))
+    }
+
+    fn to_world(&self, offset: Vector2<f32>) -> Vector2<f32> {
+        self.position + self.pivot + self.basis().rotate_vector(offset - self.pivot)
+    }
+
+    fn center(&self) -> Vector2<f32> {
+        self.to_world((self.bottom_left_offset + self.top_right_offset) / 2.)
+    }
+
+    fn corners(&self) -> [Vector2<f32>; 4] {
+        let (bl, tr) = (self.bottom_left_offset, self.top_right_offset);
+        [bl, Vector2::new(tr.x, bl.y), tr, Vector2::new(bl.x, tr.y)]
+            .map(|corner| self.to_world(corner))
+    }
+
+    fn axes(&self) -> [Vector2<f32>; 2] {
+        let basis = self.basis();
+        [
+            basis.rotate_vector(Vector2::unit_x()),
+            basis.rotate_vector(Vector2::unit_y()),
+        ]
+    }
+
     fn top_right(&self) -> Vector2<f32> {
         self.position + self.top_right_offset
     }
@@ -27,10 +64,89 @@ impl BoundingBox {
     fn bottom_left(&self) -> Vector2<f32> {
         self.position + self.bottom_left_offset
     }
+
+    // the smallest axis aligned box around it, as its bottom left and top right corners
+    fn bounds(&self) -> (Vector2<f32>, Vector2<f32>) {
+        if self.is_axis_aligned() {
+            return (self.bottom_left(), self.top_right());
+        }
+        self.corners().iter().fold(
+            (
+                Vector2::new(f32::MAX, f32::MAX),
+                Vector2::new(f32::MIN, f32::MIN),
+            ),
+            |(min, max), corner| {
+                (
+                    Vector2::new(min.x.min(corner.x), min.y.min(corner.y)),
+                    Vector2::new(max.x.max(corner.x), max.y.max(corner.y)),
+                )
+            },
+        )
+    }
+}
+
+// what a collider is, for deciding what it collides with. there's room for 32 of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionLayer {
+    Terrain,
+    Player,
+    Enemy,
+    PlayerProjectile,
+    EnemyProjectile,
+}
+
+impl CollisionLayer {
+    pub const ALL: [CollisionLayer; 5] = [
+        CollisionLayer::Terrain,
+        CollisionLayer::Player,
+        CollisionLayer::Enemy,
+        CollisionLayer::PlayerProjectile,
+        CollisionLayer::EnemyProjectile,
+    ];
+
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    // no layers is no bits
+    pub fn bits(layers: &[CollisionLayer]) -> u32 {
+        layers.iter().fold(0, |bits, layer| bits | layer.bit())
+    }
+}
+
+// the layers a collider is on and the layers it collides with, as bits. two colliders only
+// collide if each one is on a layer the other collides with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionFilter {
+    pub layers: u32,
+    pub mask: u32,
+}
+
+impl CollisionFilter {
+    // on every layer and colliding with all of them
+    pub fn all() -> Self {
+        Self {
+            layers: u32::MAX,
+            mask: u32::MAX,
+        }
+    }
+
+    // the slices are taken as they are, so colliding with &[] is colliding with nothing
+    pub fn new(layers: &[CollisionLayer], collides_with: &[CollisionLayer]) -> Self {
+        Self {
+            layers: CollisionLayer::bits(layers),
+            mask: CollisionLayer::bits(collides_with),
+        }
+    }
+
+    pub fn collides(&self, other: &Self) -> bool {
+        self.layers & other.mask != 0 && other.layers & self.mask != 0
+    }
 }
 
 pub struct ColliderBoxComponent {
     pub bounding_box: BoundingBox,
+    pub filter: CollisionFilter,
 }
 
 impl Component for ColliderBoxComponent {
@@ -39,6 +155,41 @@ impl Component for ColliderBoxComponent {
     }
 }
 
+// a box that reports the bodies in it instead of blocking them. bodies are anything with physics
+// and a collider box on a layer in the mask. triggers don't block anything, so they aren't on a
+// layer themselves
+pub struct TriggerComponent {
+    pub bounding_box: BoundingBox,
+    pub mask: u32,
+    // the bodies in it as of the last tick, sorted
+    pub overlapping: Vec<Entity>,
+}
+
+impl TriggerComponent {
+    pub fn new(bounding_box: BoundingBox, notices: &[CollisionLayer]) -> Self {
+        Self {
+            bounding_box,
+            mask: CollisionLayer::bits(notices),
+            overlapping: Vec::new(),
+        }
+    }
+}
+
+impl Component for TriggerComponent {
+    fn name(&self) -> String {
+        "Trigger".to_string()
+    }
+}
+
+// sent every tick for each body in a trigger, Enter on the first one. Exit is sent on the tick it
+// leaves, or is despawned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerEvent {
+    Enter { trigger: Entity, body: Entity },
+    Stay { trigger: Entity, body: Entity },
+    Exit { trigger: Entity, body: Entity },
+}
+
 #[derive(PartialEq)]
 pub struct PhysicsComponent {
     pub velocity: Vector2<f32>,
@@ -64,7 +215,7 @@ impl PhysicsComponent {
 
 pub struct PhysicsSystem {
     tick_duration: Duration,
-    ticks_elapsed: u64,
+    broad_phase: SpatialGrid,
 }
 
 impl PhysicsSystem {
@@ -75,14 +226,109 @@ impl PhysicsSystem {
 
     const COYOTE_TIME: Duration = Duration::from_millis(300);
 
+    // cos of the steepest slope that still counts as ground
+    const MAX_GROUND_SLOPE_COS: f32 = 0.7;
+
+    // a few tiles, about the size of a character
+    const BROAD_PHASE_CELL_SIZE: f32 = 128.;
+
+    // how many surfaces a body can hit and slide along in one tick
+    const MAX_SWEEPS: usize = 4;
+    // gaps this small count as touching, so a body resting on the ground still hits it
+    const CONTACT_TOLERANCE: f32 = 0.01;
+
     pub fn new(tick_duration: Duration) -> Self {
         Self {
             tick_duration,
-            ticks_elapsed: 0,
+            broad_phase: SpatialGrid::new(Self::BROAD_PHASE_CELL_SIZE),
         }
     }
 
+    // every box a body can run into goes into the broad phase, once per tick before anything
+    // moves. boxes that didn't move since stay in their cells
+    fn update_broad_phase(&mut self, world: &World) {
+        let mut with_boxes = HashSet::new();
+        world
+            .query::<&ColliderBoxComponent>()
+            .iter()
+            .for_each(|(entity, collider_box)| {
+                let (min, max) = collider_box.bounding_box.bounds();
+                self.broad_phase
+                    .update(entity, min, max, collider_box.filter.layers);
+                with_boxes.insert(entity);
+            });
+        self.broad_phase
+            .retain(|entity| with_boxes.contains(&entity));
+    }
+
+    // runs after the bodies have moved, so the events are about where they ended up. triggers
+    // follow their entity's position
+    fn update_triggers(&self, world: &World) {
+        let collider_boxes = world.components::<ColliderBoxComponent>();
+        let bodies = world.components::<PhysicsComponent>();
+        let parents = world.components::<component::ParentComponent>();
+
+        world
+            .query::<(&mut TriggerComponent, Option<&PositionComponent>)>()
+            .iter()
+            .for_each(|(trigger, (trigger_component, position_component))| {
+                if let Some(position_component) = position_component {
+                    trigger_component
+                        .bounding_box
+                        .update(position_component.position);
+                }
+                let is_attached = |body: Entity| {
+                    parents
+                        .get(trigger)
+                        .is_some_and(|parent| parent.parent == body)
+                };
+
+                let (min, max) = trigger_component.bounding_box.bounds();
+                let overlapping: Vec<Entity> = self
+                    .broad_phase
+                    .query(min, max, trigger_component.mask)
+                    .into_iter()
+                    .filter(|body| {
+                        *body != trigger && bodies.contains_key(*body) && !is_attached(*body)
+                    })
+                    .filter(|body| {
+                        collider_boxes.get(*body).is_some_and(|collider_box| {
+                            Self::is_colliding(
+                                &trigger_component.bounding_box,
+                                &collider_box.bounding_box,
+                            )
+                        })
+                    })
+                    .collect();
+
+                trigger_component
+                    .overlapping
+                    .iter()
+                    .filter(|body| overlapping.binary_search(body).is_err())
+                    .for_each(|body| {
+                        world.send(TriggerEvent::Exit {
+                            trigger,
+                            body: *body,
+                        })
+                    });
+                overlapping.iter().for_each(|body| {
+                    let body = *body;
+                    let was_in = trigger_component.overlapping.binary_search(&body).is_ok();
+                    world.send(if was_in {
+                        TriggerEvent::Stay { trigger, body }
+                    } else {
+                        TriggerEvent::Enter { trigger, body }
+                    });
+                });
+                trigger_component.overlapping = overlapping;
+            });
+    }
+
     fn is_colliding(a: &BoundingBox, b: &BoundingBox) -> bool {
+        if !a.is_axis_aligned() || !b.is_axis_aligned() {
+            return Self::get_oriented_collision_delta(a, b).1 > 0.;
+        }
+
         !(a.top_right().x <= b.bottom_left().x
             || a.bottom_left().x >= b.top_right().x
             || a.top_right().y <= b.bottom_left().y
@@ -90,6 +336,10 @@ impl PhysicsSystem {
     }
 
     fn is_touching(a: &BoundingBox, b: &BoundingBox) -> bool {
+        if !a.is_axis_aligned() || !b.is_axis_aligned() {
+            return Self::get_oriented_collision_delta(a, b).1 >= 0.;
+        }
+
         !(a.top_right().x < b.bottom_left().x
             || a.bottom_left().x > b.top_right().x
             || a.top_right().y < b.bottom_left().y
@@ -97,6 +347,10 @@ impl PhysicsSystem {
     }
 
     fn get_collision_delta(a: &BoundingBox, b: &BoundingBox) -> (Vector2<f32>, f32) {
+        if !a.is_axis_aligned() || !b.is_axis_aligned() {
+            return Self::get_oriented_collision_delta(a, b);
+        }
+
         let horizontal_depth = f32::min(
             a.top_right().x - b.bottom_left().x,
             b.top_right().x - a.bottom_left().x,
@@ -122,36 +376,145 @@ impl PhysicsSystem {
         // return Vector2::new(0.0, 0.0);
     }
 
-    pub fn update(
-        &mut self,
-        input_handler: &InputHandler,
-        position_components: &mut EntityMap<PositionComponent>,
-        collider_box_components: &mut EntityMap<ColliderBoxComponent>,
-        metadata_components: &mut EntityMap<component::MetadataComponent>,
-        physics_components: &mut EntityMap<PhysicsComponent>,
-        collectible_components: &mut EntityMap<component::CollectibleComponent>,
-        sign_components: &mut EntityMap<component::SignComponent>,
-        moving_platform_components: &mut EntityMap<component::MovingPlatformComponent>,
-        character_state_components: &mut EntityMap<component::CharacterStateComponent>,
-        current_time: Duration,
-        game_mode: &game::GameMode,
-    ) {
-        if *game_mode == game::GameMode::POPUP {
+    // separating axis test for when either box is rotated. returns the axis of least overlap
+    // pointing from a towards b and the overlap along it, negative if the boxes are apart
+    fn get_oriented_collision_delta(a: &BoundingBox, b: &BoundingBox) -> (Vector2<f32>, f32) {
+        let (a_corners, b_corners) = (a.corners(), b.corners());
+        let project = |corners: &[Vector2<f32>; 4], axis: Vector2<f32>| {
+            corners
+                .iter()
+                .map(|corner| corner.dot(axis))
+                .fold((f32::MAX, f32::MIN), |(min, max), p| {
+                    (min.min(p), max.max(p))
+                })
+        };
+
+        let (axis, depth) = a
+            .axes()
+            .into_iter()
+            .chain(b.axes())
+            .map(|axis| {
+                let (a_min, a_max) = project(&a_corners, axis);
+                let (b_min, b_max) = project(&b_corners, axis);
+                (axis, a_max.min(b_max) - a_min.max(b_min))
+            })
+            .fold((Vector2::unit_x(), f32::MAX), |least, (axis, depth)| {
+                if depth < least.1 {
+                    (axis, depth)
+                } else {
+                    least
+                }
+            });
+
+        if (b.center() - a.center()).dot(axis) < 0. {
+            (axis * -1., depth)
+        } else {
+            (axis, depth)
+        }
+    }
+
+    // when a moving by delta first touches b, as a fraction of delta, and the normal of the
+    // surface of b it touches. only for axis aligned boxes that aren't overlapping already,
+    // sliding along a surface they're touching doesn't count
+    fn time_of_impact(
+        a: &BoundingBox,
+        b: &BoundingBox,
+        delta: Vector2<f32>,
+    ) -> Option<(f32, Vector2<f32>)> {
+        let ((a_min, a_max), (b_min, b_max)) = (a.bounds(), b.bounds());
+        let axis = |a_min: f32, a_max: f32, b_min: f32, b_max: f32, delta: f32| {
+            let gap = |gap: f32| {
+                if gap < 0. && gap > -Self::CONTACT_TOLERANCE {
+                    0.
+                } else {
+                    gap
+                }
+            };
+            if delta > 0. {
+                Some((gap(b_min - a_max) / delta, (b_max - a_min) / delta))
+            } else if delta < 0. {
+                Some((gap(a_min - b_max) / -delta, (a_max - b_min) / -delta))
+            } else if a_max <= b_min || a_min >= b_max {
+                None
+            } else {
+                Some((f32::NEG_INFINITY, f32::INFINITY))
+            }
+        };
+        let (x_entry, x_exit) = axis(a_min.x, a_max.x, b_min.x, b_max.x, delta.x)?;
+        let (y_entry, y_exit) = axis(a_min.y, a_max.y, b_min.y, b_max.y, delta.y)?;
+
+        let (entry, exit) = (x_entry.max(y_entry), x_exit.min(y_exit));
+        if entry >= exit || !(0. ..=1.).contains(&entry) {
+            return None;
+        }
+        let normal = if x_entry > y_entry {
+            Vector2::new(-delta.x.signum(), 0.)
+        } else {
+            Vector2::new(0., -delta.y.signum())
+        };
+        Some((entry, normal))
+    }
+
+    // moves the box by delta up to the first surface it hits, then slides it along that surface
+    // for the rest of delta, and so on. returns how far it got and the surfaces it hit, so fast
+    // bodies can't pass through thin platforms between ticks. only axis aligned boxes are swept,
+    // against axis aligned obstacles
+    fn sweep(
+        moving: &BoundingBox,
+        delta: Vector2<f32>,
+        obstacles: &[(Entity, &BoundingBox)],
+    ) -> (Vector2<f32>, Vec<(Entity, Vector2<f32>)>) {
+        let mut moved = BoundingBox {
+            position: moving.position,
+            bottom_left_offset: moving.bottom_left_offset,
+            top_right_offset: moving.top_right_offset,
+            rotation: moving.rotation,
+            pivot: moving.pivot,
+        };
+        let mut remaining = delta;
+        let mut contacts = Vec::new();
+        for _ in 0..Self::MAX_SWEEPS {
+            if remaining.is_zero() {
+                break;
+            }
+            let first_contact = obstacles
+                .iter()
+                .filter_map(|(entity, obstacle)| {
+                    Self::time_of_impact(&moved, obstacle, remaining)
+                        .map(|(time, normal)| (time, normal, *entity))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let Some((time, normal, entity)) = first_contact else {
+                moved.position += remaining;
+                break;
+            };
+            moved.position += remaining * time;
+            remaining *= 1. - time;
+            remaining -= normal * remaining.dot(normal);
+            contacts.push((entity, normal));
+        }
+        (moved.position - moving.position, contacts)
+    }
+
+    pub fn update(&mut self, world: &World, current_time: Duration) {
+        if *world.resource::<game::GameMode>() == game::GameMode::POPUP {
             return;
         }
+        let input_handler = world.resource::<InputHandler>();
 
         let tick_secs = self.tick_duration.as_secs_f32();
-        self.ticks_elapsed += 1;
         // let position_delta = cgmath::Vector2::new(x, y) * Self::MOVEMENT_SPEED * tick_secs;
 
-        utils::zip3_entities_mut(
-            moving_platform_components,
-            position_components,
-            collider_box_components,
-        )
-        .for_each(|(_, moving_platform, position_component, collider_box)| {
-            if let (Some(moving_platform), Some(pos)) = (moving_platform, position_component) {
-                let change = (tick_secs * self.ticks_elapsed as f32 * 2.0 * std::f32::consts::PI
+        world
+            .query::<(
+                &mut component::MovingPlatformComponent,
+                &mut PositionComponent,
+                Option<&mut ColliderBoxComponent>,
+            )>()
+            .iter()
+            .for_each(|(_, (moving_platform, pos, collider_box))| {
+                moving_platform.elapsed_secs += tick_secs;
+                let change = (moving_platform.elapsed_secs * 2.0 * std::f32::consts::PI
                     / moving_platform.period_secs)
                     .sin()
                     * moving_platform.amplitude;
@@ -169,227 +532,507 @@ impl PhysicsSystem {
                 if let Some(collider_box) = collider_box {
                     collider_box.bounding_box.update(pos.position);
                 }
-            }
-        });
+            });
 
-        let collider_deltas = utils::zip5_entities_1immut(
-            position_components,
-            physics_components,
-            metadata_components,
-            character_state_components,
-            collider_box_components,
-        )
-        .map(
-            |(
-                e1,
-                position_component,
-                physics_component,
-                metadata_component,
-                character_state_component,
-                collider_box_component1,
-            )| {
-                let Some(position_component) = position_component else {
-                    return cgmath::Vector2::zero();
-                };
-                let Some(physics_component) = physics_component else {
-                    return cgmath::Vector2::zero();
-                };
+        self.update_broad_phase(world);
+        let broad_phase = &self.broad_phase;
 
-                let metadata_component = metadata_component.as_mut().unwrap();
+        let moving_platforms = world.components::<component::MovingPlatformComponent>();
+        let collider_boxes = world.components::<ColliderBoxComponent>();
+        let parents = world.components::<component::ParentComponent>();
 
-                if metadata_component.is_controllable() {
-                    if input_handler.up_pressed {
-                        if metadata_component.can_jump() {
-                            metadata_component.set_jump(false);
-                            physics_component.velocity.y = Self::JUMP_VELOCITY;
-                            physics_component.acceleration.y = -1. * Self::JUMP_ACCELERATION;
-                            if let Some(character_state_component) = character_state_component {
-                                character_state_component.character_state =
-                                    component::CharacterState::JUMPUP;
+        let collider_deltas = world
+            .query::<(
+                &mut PositionComponent,
+                &mut PhysicsComponent,
+                &mut component::MetadataComponent,
+                Option<&mut component::CharacterStateComponent>,
+                Option<&ColliderBoxComponent>,
+            )>()
+            .iter()
+            .map(
+                |(
+                    e1,
+                    (
+                        position_component,
+                        physics_component,
+                        metadata_component,
+                        mut character_state_component,
+                        collider_box_component1,
+                    ),
+                )| {
+                    if metadata_component.is_controllable() {
+                        if input_handler.up_pressed {
+                            if metadata_component.can_jump() {
+                                metadata_component.set_jump(false);
+                                physics_component.velocity.y = Self::JUMP_VELOCITY;
+                                physics_component.acceleration.y = -1. * Self::JUMP_ACCELERATION;
+                                if let Some(character_state_component) =
+                                    &mut character_state_component
+                                {
+                                    character_state_component.character_state =
+                                        component::CharacterState::JUMPUP;
+                                }
                             }
                         }
+                        // if input_handler.down_pressed {
+                        //     update_position(0., -1.)
+                        // }
+                        if input_handler.left_pressed {
+                            physics_component.velocity.x = -1. * Self::MOVEMENT_SPEED;
+                            physics_component.acceleration.x = -1. * Self::MOVEMENT_SPEED;
+                        } else if input_handler.right_pressed {
+                            physics_component.velocity.x = Self::MOVEMENT_SPEED;
+                            physics_component.acceleration.x = Self::MOVEMENT_SPEED;
+                        } else {
+                            physics_component.velocity.x = 0.;
+                            physics_component.acceleration.x = 0.;
+                        }
+                    };
+
+                    physics_component.velocity += physics_component.acceleration * tick_secs;
+                    let delta = physics_component.velocity * tick_secs;
+                    let mut delta_add = delta;
+
+                    if *physics_component == PhysicsComponent::new()
+                        && !metadata_component.is_controllable()
+                    {
+                        return (e1, cgmath::Vector2::zero());
                     }
-                    // if input_handler.down_pressed {
-                    //     update_position(0., -1.)
-                    // }
-                    if input_handler.left_pressed {
-                        physics_component.velocity.x = -1. * Self::MOVEMENT_SPEED;
-                        physics_component.acceleration.x = -1. * Self::MOVEMENT_SPEED;
-                    } else if input_handler.right_pressed {
-                        physics_component.velocity.x = Self::MOVEMENT_SPEED;
-                        physics_component.acceleration.x = Self::MOVEMENT_SPEED;
-                    } else {
-                        physics_component.velocity.x = 0.;
-                        physics_component.acceleration.x = 0.;
-                    }
-                };
 
-                physics_component.velocity += physics_component.acceleration * tick_secs;
-                let delta = physics_component.velocity * tick_secs;
-                let mut delta_add = delta;
+                    let Some(collider_box_component1) = collider_box_component1 else {
+                        return (e1, cgmath::Vector2::zero());
+                    };
 
-                if *physics_component == PhysicsComponent::new()
-                    && !metadata_component.is_controllable()
-                {
-                    return cgmath::Vector2::zero();
-                }
+                    // anything attached to the body moves with it, so it can't collide with it
+                    let is_attached =
+                        |e2: Entity| parents.get(e2).is_some_and(|parent| parent.parent == e1);
+                    let filter = collider_box_component1.filter;
+                    let mut is_grounded: bool = false;
+                    let mut moving_platform_to_add = 0.;
+                    let mut moving_platform_is_horizontal = true;
 
-                let Some(collider_box_component1) = collider_box_component1 else {
-                    return cgmath::Vector2::zero();
-                };
-                let new_collision_box = ColliderBoxComponent {
-                    bounding_box: BoundingBox {
-                        position: collider_box_component1.bounding_box.position + delta,
-                        bottom_left_offset: collider_box_component1.bounding_box.bottom_left_offset,
-                        top_right_offset: collider_box_component1.bounding_box.top_right_offset,
-                    },
-                };
+                    // obstacles the sweep already stopped the body at, so the push out below
+                    // leaves them alone. rotated boxes aren't swept, so fast ones can still pass
+                    // through thin colliders, they only get pushed out of what they overlap after
+                    // moving. so do bodies that overlapped something before moving
+                    let mut resolved: Vec<Entity> = Vec::new();
+                    if collider_box_component1.bounding_box.is_axis_aligned() {
+                        let start = collider_box_component1.bounding_box.bounds();
+                        let (min, max) = (
+                            Vector2::new(start.0.x + delta.x.min(0.), start.0.y + delta.y.min(0.)),
+                            Vector2::new(start.1.x + delta.x.max(0.), start.1.y + delta.y.max(0.)),
+                        );
+                        let obstacles: Vec<(Entity, &BoundingBox)> = broad_phase
+                            .query(min, max, filter.mask)
+                            .into_iter()
+                            .filter(|e2| *e2 != e1 && !is_attached(*e2))
+                            .filter_map(|e2| collider_boxes.get(e2).map(|box2| (e2, box2)))
+                            .filter(|(_, box2)| {
+                                filter.collides(&box2.filter) && box2.bounding_box.is_axis_aligned()
+                            })
+                            .map(|(e2, box2)| (e2, &box2.bounding_box))
+                            .collect();
+                        resolved = obstacles
+                            .iter()
+                            .filter(|(_, box2)| {
+                                !Self::is_colliding(&collider_box_component1.bounding_box, box2)
+                            })
+                            .map(|(e2, _)| *e2)
+                            .collect();
+                        let (swept, contacts) =
+                            Self::sweep(&collider_box_component1.bounding_box, delta, &obstacles);
+                        delta_add = swept;
 
-                let mut is_grounded: bool = false;
-                let mut moving_platform_to_add = 0.;
-                let mut moving_platform_is_horizontal = true;
-                // TODO: implement better collision detection, this is O(N^2) lol
-                let collision_detected = utils::zip4_entities_1immut(
-                    collectible_components,
-                    sign_components,
-                    moving_platform_components,
-                    collider_box_components,
-                )
-                .fold(
-                    Vector2::zero(),
-                    |mut collision_dir,
-                     (e2, collectible, sign_component, moving_platform, box2)| {
-                        if e1 != e2 {
-                            box2.as_ref().map(|box2| {
-                                let (direction, scale) = Self::get_collision_delta(
-                                    &new_collision_box.bounding_box,
-                                    &box2.bounding_box,
-                                );
-                                if Self::is_colliding(
-                                    &new_collision_box.bounding_box,
-                                    &box2.bounding_box,
-                                ) {
-                                    collision_dir += direction * scale;
+                        contacts.iter().for_each(|(e2, normal)| {
+                            // stop moving into what was hit
+                            let into_surface = physics_component.velocity.dot(*normal);
+                            if into_surface < 0. {
+                                physics_component.velocity -= *normal * into_surface;
+                            }
+
+                            if normal.y >= Self::MAX_GROUND_SLOPE_COS {
+                                is_grounded = true;
+                                if let Some(moving_platform) = moving_platforms.get(*e2) {
+                                    moving_platform_to_add = moving_platform.prev_change;
+                                    moving_platform_is_horizontal = moving_platform.horizontal;
                                 }
+                            }
+                        });
+                    }
+
+                    let new_collision_box = ColliderBoxComponent {
+                        bounding_box: BoundingBox {
+                            position: collider_box_component1.bounding_box.position + delta_add,
+                            bottom_left_offset: collider_box_component1
+                                .bounding_box
+                                .bottom_left_offset,
+                            top_right_offset: collider_box_component1.bounding_box.top_right_offset,
+                            rotation: collider_box_component1.bounding_box.rotation,
+                            pivot: collider_box_component1.bounding_box.pivot,
+                        },
+                        filter,
+                    };
+
+                    let (min, max) = new_collision_box.bounding_box.bounds();
+                    let nearby = broad_phase.query(min, max, filter.mask);
+                    let collision_detected =
+                        nearby
+                            .iter()
+                            .fold(Vector2::zero(), |mut collision_dir, &e2| {
+                                let (moving_platform, box2) =
+                                    (moving_platforms.get(e2), collider_boxes.get(e2));
+                                if e1 != e2 && !is_attached(e2) {
+                                    if let Some(box2) =
+                                        box2.filter(|box2| filter.collides(&box2.filter))
+                                    {
+                                        let (direction, scale) = Self::get_collision_delta(
+                                            &new_collision_box.bounding_box,
+                                            &box2.bounding_box,
+                                        );
+                                        if resolved.binary_search(&e2).is_err()
+                                            && Self::is_colliding(
+                                                &new_collision_box.bounding_box,
+                                                &box2.bounding_box,
+                                            )
+                                        {
+                                            collision_dir += direction * scale;
+                                        }
+
+                                        if Self::is_touching(
+                                            &new_collision_box.bounding_box,
+                                            &box2.bounding_box,
+                                        ) {
+                                            if -direction.y >= Self::MAX_GROUND_SLOPE_COS {
+                                                is_grounded = true;
 
-                                if Self::is_touching(
-                                    &new_collision_box.bounding_box,
-                                    &box2.bounding_box,
-                                ) {
-                                    if direction == (Vector2::unit_y() * -1.) {
-                                        is_grounded = true;
-
-                                        if let Some(moving_platform) = moving_platform {
-                                            moving_platform_to_add = moving_platform.prev_change;
-                                            moving_platform_is_horizontal =
-                                                moving_platform.horizontal;
+                                                if let Some(moving_platform) = moving_platform {
+                                                    moving_platform_to_add =
+                                                        moving_platform.prev_change;
+                                                    moving_platform_is_horizontal =
+                                                        moving_platform.horizontal;
+                                                }
+                                            }
                                         }
                                     }
                                 }
+                                collision_dir
                             });
 
-                            collectible.as_mut().map(|collectible| {
-                                if Self::is_colliding(
-                                    &new_collision_box.bounding_box,
-                                    &collectible.bounding_box,
-                                ) {
-                                    collectible.is_collected = true;
-                                }
-                            });
+                    if is_grounded {
+                        if physics_component.last_grounded_time.is_some() {
+                            physics_component.last_grounded_time = None;
+                            world.send_event(EngineEvent::Grounded(e1));
+                        }
 
-                            sign_component.as_mut().map(|sign_component| {
-                                sign_component.in_range = Self::is_colliding(
-                                    &new_collision_box.bounding_box,
-                                    &sign_component.bounding_box,
-                                );
-                            });
+                        metadata_component.set_jump(true);
+                        physics_component.acceleration.y = 0.;
+                        physics_component.velocity.y = 0.;
+                    } else {
+                        if physics_component.last_grounded_time.is_none() {
+                            physics_component.last_grounded_time = Some(current_time);
+                            world.send_event(EngineEvent::Ungrounded(e1));
                         }
-                        collision_dir
-                    },
-                );
 
-                if is_grounded {
-                    if physics_component.last_grounded_time.is_some() {
-                        physics_component.last_grounded_time = None;
+                        physics_component.acceleration.y = -1. * Self::JUMP_ACCELERATION;
+
+                        assert!(physics_component.last_grounded_time.is_some());
+                        let grounded_start_time = physics_component.last_grounded_time.unwrap();
+
+                        if current_time - grounded_start_time > Self::COYOTE_TIME {
+                            metadata_component.set_jump(false);
+                        }
                     }
 
-                    metadata_component.set_jump(true);
-                    physics_component.acceleration.y = 0.;
-                    physics_component.velocity.y = 0.;
-                } else {
-                    if physics_component.last_grounded_time.is_none() {
+                    if physics_component.last_grounded_time.is_none() && !is_grounded {
                         physics_component.last_grounded_time = Some(current_time);
+                    } else if physics_component.last_grounded_time.is_some() && is_grounded {
+                        physics_component.last_grounded_time = None;
                     }
 
-                    physics_component.acceleration.y = -1. * Self::JUMP_ACCELERATION;
+                    if !collision_detected.is_zero() {
+                        if collision_detected.y != 0. {
+                            delta_add.y -= collision_detected.y;
 
-                    assert!(physics_component.last_grounded_time.is_some());
-                    let grounded_start_time = physics_component.last_grounded_time.unwrap();
+                            // if collision_detected.y < 0. {
+                            //     metadata_component.set_jump(true);
+                            //     physics_component.acceleration.y = 0.;
+                            //     physics_component.velocity.y = 0.;
+                            // }
+                        }
 
-                    if current_time - grounded_start_time > Self::COYOTE_TIME {
-                        metadata_component.set_jump(false);
+                        if collision_detected.x != 0. {
+                            delta_add.x -= collision_detected.x;
+                        }
                     }
-                }
-
-                if physics_component.last_grounded_time.is_none() && !is_grounded {
-                    physics_component.last_grounded_time = Some(current_time);
-                } else if physics_component.last_grounded_time.is_some() && is_grounded {
-                    physics_component.last_grounded_time = None;
-                }
 
-                if !collision_detected.is_zero() {
-                    if collision_detected.y != 0. {
-                        delta_add.y -= collision_detected.y;
-
-                        // if collision_detected.y < 0. {
-                        //     metadata_component.set_jump(true);
-                        //     physics_component.acceleration.y = 0.;
-                        //     physics_component.velocity.y = 0.;
-                        // }
+                    if let Some(character_state_component) = character_state_component {
+                        if !metadata_component.can_jump() && delta_add.y < 0. {
+                            character_state_component.character_state =
+                                component::CharacterState::JUMPDOWN;
+                        } else if !metadata_component.can_jump() && delta_add.y > 0. {
+                            character_state_component.character_state =
+                                component::CharacterState::JUMPUP;
+                        } else if delta_add == cgmath::Vector2::zero() {
+                            character_state_component.character_state =
+                                component::CharacterState::IDLE;
+                        } else if delta_add.x != 0. {
+                            character_state_component.character_state =
+                                component::CharacterState::MOVE;
+                        }
                     }
 
-                    if collision_detected.x != 0. {
-                        delta_add.x -= collision_detected.x;
+                    if moving_platform_is_horizontal {
+                        delta_add.x += moving_platform_to_add;
+                    } else {
+                        delta_add.y -= moving_platform_to_add;
                     }
-                }
 
-                if let Some(character_state_component) = character_state_component {
-                    if !metadata_component.can_jump() && delta_add.y < 0. {
-                        character_state_component.character_state =
-                            component::CharacterState::JUMPDOWN;
-                    } else if !metadata_component.can_jump() && delta_add.y > 0. {
-                        character_state_component.character_state =
-                            component::CharacterState::JUMPUP;
-                    } else if delta_add == cgmath::Vector2::zero() {
-                        character_state_component.character_state = component::CharacterState::IDLE;
-                    } else if delta_add.x != 0. {
-                        character_state_component.character_state = component::CharacterState::MOVE;
-                    }
-                }
+                    position_component.position += delta_add;
 
-                if moving_platform_is_horizontal {
-                    delta_add.x += moving_platform_to_add;
-                } else {
-                    delta_add.y -= moving_platform_to_add;
+                    (e1, delta_add)
+                },
+            )
+            .collect::<Vec<(Entity, Vector2<f32>)>>();
+        drop((moving_platforms, collider_boxes, parents));
+
+        // colliders are borrowed immutably by both queries above, so the deltas get applied after.
+        // only the bodies that moved change cells, the rest of the broad phase is still current
+        collider_deltas.iter().for_each(|(entity, delta)| {
+            if let Some(mut collider_box_component) = world.get_mut::<ColliderBoxComponent>(*entity)
+            {
+                let position = collider_box_component.bounding_box.position + *delta;
+                collider_box_component.bounding_box.update(position);
+                if !delta.is_zero() {
+                    let (min, max) = collider_box_component.bounding_box.bounds();
+                    self.broad_phase.update(
+                        *entity,
+                        min,
+                        max,
+                        collider_box_component.filter.layers,
+                    );
                 }
+            }
+        });
 
-                position_component.position += delta_add;
+        self.update_triggers(world);
+    }
+}
 
-                delta_add
-            },
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slotmap::SlotMap;
+
+    fn box_at(x: f32, y: f32, width: f32, height: f32) -> BoundingBox {
+        BoundingBox {
+            position: Vector2::new(x, y),
+            bottom_left_offset: Vector2::new(0., 0.),
+            top_right_offset: Vector2::new(width, height),
+            rotation: 0.,
+            pivot: Vector2::new(0., 0.),
+        }
+    }
+
+    fn assert_near(a: Vector2<f32>, b: Vector2<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn time_of_impact_finds_the_first_contact() {
+        let a = box_at(0., 0., 1., 1.);
+        let (time, normal) =
+            PhysicsSystem::time_of_impact(&a, &box_at(3., 0., 1., 1.), Vector2::new(4., 0.))
+                .unwrap();
+        assert_eq!(time, 0.5);
+        assert_eq!(normal, Vector2::new(-1., 0.));
+
+        let (time, normal) =
+            PhysicsSystem::time_of_impact(&a, &box_at(-1., -3., 3., 1.), Vector2::new(1., -4.))
+                .unwrap();
+        assert_eq!(time, 0.5);
+        assert_eq!(normal, Vector2::new(0., 1.));
+    }
+
+    #[test]
+    fn time_of_impact_misses() {
+        let a = box_at(0., 0., 1., 1.);
+        // passes above it
+        assert!(
+            PhysicsSystem::time_of_impact(&a, &box_at(3., 5., 1., 1.), Vector2::new(4., 0.))
+                .is_none()
+        );
+        // stops short of it
+        assert!(
+            PhysicsSystem::time_of_impact(&a, &box_at(3., 0., 1., 1.), Vector2::new(1., 0.))
+                .is_none()
+        );
+        // moves away from it
+        assert!(
+            PhysicsSystem::time_of_impact(&a, &box_at(3., 0., 1., 1.), Vector2::new(-4., 0.))
+                .is_none()
+        );
+        // already overlapping it
+        assert!(
+            PhysicsSystem::time_of_impact(&a, &box_at(0.5, 0., 1., 1.), Vector2::new(4., 0.))
+                .is_none()
+        );
+        // slides along the ground it's resting on
+        assert!(PhysicsSystem::time_of_impact(
+            &a,
+            &box_at(-5., -1., 10., 1.),
+            Vector2::new(4., 0.)
         )
-        .collect::<Vec<Vector2<f32>>>();
+        .is_none());
+    }
 
-        // rust limitation of mutable references so I need to do this :(( maybe I can use itertools?
-        collider_deltas
-            .iter()
-            .zip(collider_box_components.iter_mut())
-            .for_each(|(delta, (_, collider_box_component))| {
-                if let Some(collider_box_component) = collider_box_component {
-                    collider_box_component
-                        .bounding_box
-                        .update(collider_box_component.bounding_box.position + *delta);
-                }
-            });
+    #[test]
+    fn time_of_impact_counts_sinking_into_the_ground_as_touching() {
+        let a = box_at(0., -0.005, 1., 1.);
+        let (time, normal) =
+            PhysicsSystem::time_of_impact(&a, &box_at(-5., -1., 10., 1.), Vector2::new(0., -1.))
+                .unwrap();
+        assert_eq!(time, 0.);
+        assert_eq!(normal, Vector2::new(0., 1.));
+    }
+
+    #[test]
+    fn sweep_stops_fast_bodies_at_thin_platforms() {
+        let platform = SlotMap::<Entity, ()>::with_key().insert(());
+        let thin = box_at(-5., -5., 10., 0.1);
+        let (moved, contacts) = PhysicsSystem::sweep(
+            &box_at(0., 0., 1., 1.),
+            Vector2::new(0., -20.),
+            &[(platform, &thin)],
+        );
+        assert_near(moved, Vector2::new(0., -4.9));
+        assert_eq!(contacts, vec![(platform, Vector2::new(0., 1.))]);
+    }
+
+    #[test]
+    fn sweep_slides_along_what_it_hits() {
+        let mut entities = SlotMap::<Entity, ()>::with_key();
+        let (ground, wall) = (entities.insert(()), entities.insert(()));
+        let (ground_box, wall_box) = (box_at(-10., -2., 20., 1.), box_at(5., -1., 1., 10.));
+        let obstacles = [(ground, &ground_box), (wall, &wall_box)];
+
+        let (moved, contacts) =
+            PhysicsSystem::sweep(&box_at(0., 0., 1., 1.), Vector2::new(3., -3.), &obstacles);
+        assert_near(moved, Vector2::new(3., -1.));
+        assert_eq!(contacts, vec![(ground, Vector2::new(0., 1.))]);
+
+        // slides along the ground into the wall, and stops there
+        let (moved, contacts) =
+            PhysicsSystem::sweep(&box_at(0., 0., 1., 1.), Vector2::new(6., -3.), &obstacles);
+        assert_near(moved, Vector2::new(4., -1.));
+        assert_eq!(
+            contacts,
+            vec![
+                (ground, Vector2::new(0., 1.)),
+                (wall, Vector2::new(-1., 0.))
+            ]
+        );
+    }
+
+    #[test]
+    fn sweep_moves_freely_without_obstacles() {
+        let (moved, contacts) =
+            PhysicsSystem::sweep(&box_at(0., 0., 1., 1.), Vector2::new(2., 7.), &[]);
+        assert_eq!(moved, Vector2::new(2., 7.));
+        assert!(contacts.is_empty());
+    }
+
+    #[test]
+    fn filters_collide_when_each_is_in_the_others_mask() {
+        let player = CollisionFilter::new(&[CollisionLayer::Player], &CollisionLayer::ALL);
+        let terrain = CollisionFilter::new(&[CollisionLayer::Terrain], &[CollisionLayer::Player]);
+        let enemy = CollisionFilter::new(&[CollisionLayer::Enemy], &[CollisionLayer::Terrain]);
+        assert!(player.collides(&terrain) && terrain.collides(&player));
+        // enemies collide with terrain, but terrain only with the player
+        assert!(!enemy.collides(&terrain) && !terrain.collides(&enemy));
+        // the player collides with enemies, but enemies only with terrain
+        assert!(!player.collides(&enemy) && !enemy.collides(&player));
+        assert!(CollisionFilter::all().collides(&player));
+    }
+
+    #[test]
+    fn filters_with_an_empty_mask_collide_with_nothing() {
+        let ghost = CollisionFilter::new(&[CollisionLayer::Player], &[]);
+        assert_eq!(ghost.mask, 0);
+        assert!(!ghost.collides(&CollisionFilter::all()));
+        assert!(!CollisionFilter::all().collides(&ghost));
+        assert!(!ghost.collides(&ghost));
+    }
+
+    #[test]
+    fn triggers_report_bodies_entering_staying_and_leaving() {
+        let mut world = World::new();
+        world.register::<PhysicsComponent>();
+        world.register::<component::ParentComponent>();
+        world.add_events::<TriggerEvent>();
+        let mut physics_system = PhysicsSystem::new(Duration::from_millis(10));
+        let mut reader = world.events::<TriggerEvent>().reader();
+
+        let trigger = world.spawn();
+        world.insert(
+            trigger,
+            TriggerComponent::new(box_at(0., 0., 4., 4.), &[CollisionLayer::Player]),
+        );
+        let body = world.spawn();
+        world.insert(body, PhysicsComponent::new());
+        world.insert(
+            body,
+            ColliderBoxComponent {
+                bounding_box: box_at(-3., 0., 1., 1.),
+                filter: CollisionFilter::new(&[CollisionLayer::Player], &CollisionLayer::ALL),
+            },
+        );
+        // neither a body on a layer it doesn't notice nor a collider that isn't a body counts
+        let enemy = world.spawn();
+        world.insert(enemy, PhysicsComponent::new());
+        world.insert(
+            enemy,
+            ColliderBoxComponent {
+                bounding_box: box_at(1., 1., 1., 1.),
+                filter: CollisionFilter::new(&[CollisionLayer::Enemy], &CollisionLayer::ALL),
+            },
+        );
+        let ground = world.spawn();
+        world.insert(
+            ground,
+            ColliderBoxComponent {
+                bounding_box: box_at(0., 0., 4., 1.),
+                filter: CollisionFilter::all(),
+            },
+        );
+
+        // moves the body's box to x and runs the triggers for a tick, returning what they sent
+        let mut tick = |world: &mut World, x: Option<f32>| {
+            world.update_events();
+            if let Some(x) = x {
+                world
+                    .get_mut::<ColliderBoxComponent>(body)
+                    .unwrap()
+                    .bounding_box
+                    .update(Vector2::new(x, 0.));
+            }
+            physics_system.update_broad_phase(world);
+            physics_system.update_triggers(world);
+            let events = world.events::<TriggerEvent>();
+            reader.read(&events).copied().collect::<Vec<_>>()
+        };
+
+        use TriggerEvent::*;
+        assert_eq!(tick(&mut world, Some(-3.)), []);
+        assert_eq!(tick(&mut world, Some(-0.5)), [Enter { trigger, body }]);
+        assert_eq!(tick(&mut world, Some(1.)), [Stay { trigger, body }]);
+        assert_eq!(tick(&mut world, Some(3.5)), [Stay { trigger, body }]);
+        assert_eq!(tick(&mut world, Some(5.)), [Exit { trigger, body }]);
+        assert_eq!(tick(&mut world, Some(6.)), []);
+
+        assert_eq!(tick(&mut world, Some(2.)), [Enter { trigger, body }]);
+        world.remove_entity(body);
+        assert_eq!(tick(&mut world, None), [Exit { trigger, body }]);
+        assert_eq!(tick(&mut world, None), []);
     }
 }
@@ -0,0 +1,303 @@
+use std::cell::{Ref, RefMut};
+use std::iter::Peekable;
+
+use slotmap::{basic, secondary};
+
+use crate::component::{Entity, EntityMap};
+use crate::world::World;
+
+// a query is any tuple of `&T`, `&mut T` and `Option<..>` of those, e.g.
+// `world.query::<(&mut PositionComponent, &PhysicsComponent, Option<&MetadataComponent>)>()`.
+// the component storages are borrowed through the world's RefCells for as long as the
+// QueryBorrow lives, so conflicting borrows like `(&mut A, &A)` panic instead of aliasing.
+pub trait Query {
+    type Borrow<'w>;
+    type Fetch<'q>: Fetch;
+
+    fn borrow(world: &World) -> Self::Borrow<'_>;
+    fn fetch<'q>(borrow: &'q mut Self::Borrow<'_>) -> Self::Fetch<'q>;
+}
+
+pub trait Fetch {
+    type Item;
+
+    // entities are always visited in ascending key order, so this only ever has to move
+    // forward. returns None if the entity doesn't match the query.
+    fn seek(&mut self, entity: Entity) -> Option<Self::Item>;
+}
+
+// storages iterate in slot order, same as the world's entity list, so a column is just a
+// peekable iterator that gets advanced up to the entity being visited
+pub struct Column<I: Iterator> {
+    iter: Option<Peekable<I>>,
+}
+
+impl<I: Iterator> Column<I> {
+    fn new(iter: Option<I>) -> Self {
+        Self {
+            iter: iter.map(|iter| iter.peekable()),
+        }
+    }
+}
+
+impl<I, T> Fetch for Column<I>
+where
+    I: Iterator<Item = (Entity, T)>,
+{
+    type Item = T;
+
+    fn seek(&mut self, entity: Entity) -> Option<T> {
+        let iter = self.iter.as_mut()?;
+        while iter.next_if(|(next, _)| *next < entity).is_some() {}
+        iter.next_if(|(next, _)| *next == entity)
+            .map(|(_, component)| component)
+    }
+}
+
+pub struct OptionalFetch<F>(F);
+
+impl<F: Fetch> Fetch for OptionalFetch<F> {
+    type Item = Option<F::Item>;
+
+    fn seek(&mut self, entity: Entity) -> Option<Self::Item> {
+        Some(self.0.seek(entity))
+    }
+}
+
+impl<T: 'static> Query for &T {
+    type Borrow<'w> = Option<Ref<'w, EntityMap<T>>>;
+    type Fetch<'q> = Column<secondary::Iter<'q, Entity, T>>;
+
+    fn borrow(world: &World) -> Self::Borrow<'_> {
+        world.try_components::<T>()
+    }
+
+    fn fetch<'q>(borrow: &'q mut Self::Borrow<'_>) -> Self::Fetch<'q> {
+        Column::new(borrow.as_deref().map(|components| components.iter()))
+    }
+}
+
+impl<T: 'static> Query for &mut T {
+    type Borrow<'w> = Option<RefMut<'w, EntityMap<T>>>;
+    type Fetch<'q> = Column<secondary::IterMut<'q, Entity, T>>;
+
+    fn borrow(world: &World) -> Self::Borrow<'_> {
+        world.try_components_mut::<T>()
+    }
+
+    fn fetch<'q>(borrow: &'q mut Self::Borrow<'_>) -> Self::Fetch<'q> {
+        Column::new(
+            borrow
+                .as_deref_mut()
+                .map(|components| components.iter_mut()),
+        )
+    }
+}
+
+impl<Q: Query> Query for Option<Q> {
+    type Borrow<'w> = Q::Borrow<'w>;
+    type Fetch<'q> = OptionalFetch<Q::Fetch<'q>>;
+
+    fn borrow(world: &World) -> Self::Borrow<'_> {
+        Q::borrow(world)
+    }
+
+    fn fetch<'q>(borrow: &'q mut Self::Borrow<'_>) -> Self::Fetch<'q> {
+        OptionalFetch(Q::fetch(borrow))
+    }
+}
+
+macro_rules! impl_query_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Query),+> Query for ($($name,)+) {
+            type Borrow<'w> = ($($name::Borrow<'w>,)+);
+            type Fetch<'q> = ($($name::Fetch<'q>,)+);
+
+            fn borrow(world: &World) -> Self::Borrow<'_> {
+                ($($name::borrow(world),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'q>(borrow: &'q mut Self::Borrow<'_>) -> Self::Fetch<'q> {
+                let ($($name,)+) = borrow;
+                ($($name::fetch($name),)+)
+            }
+        }
+
+        impl<$($name: Fetch),+> Fetch for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+
+            #[allow(non_snake_case)]
+            fn seek(&mut self, entity: Entity) -> Option<Self::Item> {
+                let ($($name,)+) = self;
+                Some(($($name.seek(entity)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_for_tuple!(A);
+impl_query_for_tuple!(A, B);
+impl_query_for_tuple!(A, B, C);
+impl_query_for_tuple!(A, B, C, D);
+impl_query_for_tuple!(A, B, C, D, E);
+impl_query_for_tuple!(A, B, C, D, E, F);
+impl_query_for_tuple!(A, B, C, D, E, F, G);
+impl_query_for_tuple!(A, B, C, D, E, F, G, H);
+
+pub struct QueryBorrow<'w, Q: Query> {
+    world: &'w World,
+    borrow: Q::Borrow<'w>,
+}
+
+impl<'w, Q: Query> QueryBorrow<'w, Q> {
+    pub fn new(world: &'w World) -> Self {
+        Self {
+            world,
+            borrow: Q::borrow(world),
+        }
+    }
+
+    pub fn iter(&mut self) -> QueryIter<'_, Q> {
+        QueryIter {
+            entities: self.world.entities(),
+            fetch: Q::fetch(&mut self.borrow),
+        }
+    }
+}
+
+impl<'q, 'w, Q: Query> IntoIterator for &'q mut QueryBorrow<'w, Q> {
+    type Item = (Entity, <Q::Fetch<'q> as Fetch>::Item);
+    type IntoIter = QueryIter<'q, Q>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct QueryIter<'q, Q: Query> {
+    entities: basic::Keys<'q, Entity, ()>,
+    fetch: Q::Fetch<'q>,
+}
+
+impl<'q, Q: Query> Iterator for QueryIter<'q, Q> {
+    type Item = (Entity, <Q::Fetch<'q> as Fetch>::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entity = self.entities.next()?;
+            if let Some(item) = self.fetch.seek(entity) {
+                return Some((entity, item));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct A(u32);
+    #[derive(Debug, PartialEq)]
+    struct B(u32);
+
+    // every entity gets an A, every second one a B, every third one nothing
+    fn world() -> (World, Vec<Entity>) {
+        let mut world = World::new();
+        world.register::<A>();
+        world.register::<B>();
+        let entities = (0..9)
+            .map(|i| {
+                let mut builder = world.builder();
+                if i % 3 != 2 {
+                    builder = builder.with(A(i));
+                }
+                if i % 2 == 0 {
+                    builder = builder.with(B(i));
+                }
+                builder.build()
+            })
+            .collect();
+        (world, entities)
+    }
+
+    #[test]
+    fn joins_only_entities_with_every_component() {
+        let (world, entities) = world();
+        let joined: Vec<_> = world
+            .query::<(&A, &B)>()
+            .iter()
+            .map(|(entity, (a, b))| (entity, a.0, b.0))
+            .collect();
+        assert_eq!(
+            joined,
+            [0, 4, 6]
+                .iter()
+                .map(|i| (entities[*i as usize], *i, *i))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn optional_components_dont_filter() {
+        let (world, entities) = world();
+        let joined: Vec<_> = world
+            .query::<(&A, Option<&B>)>()
+            .iter()
+            .map(|(entity, (a, b))| (entity, a.0, b.map(|b| b.0)))
+            .collect();
+        assert_eq!(
+            joined,
+            [0, 1, 3, 4, 6, 7]
+                .iter()
+                .map(|i| (
+                    entities[*i as usize],
+                    *i,
+                    if i % 2 == 0 { Some(*i) } else { None }
+                ))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn writes_through_mutable_components() {
+        let (world, entities) = world();
+        world
+            .query::<(&mut A, &B)>()
+            .iter()
+            .for_each(|(_, (a, b))| a.0 += b.0);
+        assert_eq!(*world.get::<A>(entities[4]).unwrap(), A(8));
+        assert_eq!(*world.get::<A>(entities[1]).unwrap(), A(1));
+    }
+
+    #[test]
+    fn follows_reused_slots() {
+        let (mut world, entities) = world();
+        world.remove_entity(entities[4]);
+        world.remove::<B>(entities[6]);
+        let reused = world.builder().with(A(10)).with(B(10)).build();
+
+        let joined: Vec<_> = world
+            .query::<(&A, &B)>()
+            .iter()
+            .map(|(entity, (a, _))| (entity, a.0))
+            .collect();
+        assert_eq!(joined, vec![(entities[0], 0), (reused, 10)]);
+    }
+
+    #[test]
+    fn unregistered_components_match_nothing() {
+        struct C;
+        let (world, _) = world();
+        assert_eq!(world.query::<(&A, Option<&C>)>().iter().count(), 6);
+        assert_eq!(world.query::<(&A, &C)>().iter().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn conflicting_borrows_panic() {
+        let (world, _) = world();
+        world.query::<(&mut A, &A)>();
+    }
+}
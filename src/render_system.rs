@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -10,13 +11,13 @@ use crate::model;
 use crate::model::ModelVertex2d;
 use crate::model::Vertex;
 use crate::texture;
+use crate::texture_registry;
 use crate::uniform;
 use crate::uniform::LightUniform;
-use crate::utils;
+use crate::world::World;
 use crate::wgsl_preprocessor;
 use cgmath::num_traits::clamp;
 use cgmath::num_traits::clamp_min;
-use cgmath::ElementWise;
 
 use cgmath::Vector2;
 use egui_winit::winit::window::Window;
@@ -46,7 +47,10 @@ pub struct RenderSystem {
     post_standard_bind_group_layout: wgpu::BindGroupLayout,
     post_popup_bind_group_layout: wgpu::BindGroupLayout,
     light_bind_group_layout: wgpu::BindGroupLayout,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
     texture_bind_group: wgpu::BindGroup,
+    texture_regions: Vec<texture_registry::TextureRegion>,
+    render_pipeline_layout: wgpu::PipelineLayout,
     uniform_bind_group_layout: wgpu::BindGroupLayout,
     stencil_compute_pipeline: wgpu::ComputePipeline,
     stencil_compute_bind_group_layout: wgpu::BindGroupLayout,
@@ -62,7 +66,7 @@ struct ModelBuffer {
 struct PipelineInfo<'a> {
 pos: &'a component::PositionComponent,
 v_arr: &'a component::VertexArrayComponent,
-light: &'a Option<uniform::LightComponent>,
+light: Option<&'a uniform::LightComponent>,
 metadata: &'a component::MetadataComponent,
 }
 
@@ -88,7 +92,7 @@ impl RenderSystem {
     const AMBIENT_LIGHT_INTENSITY : f32 = 0.5;
 
 
-    pub fn new(textures: &Vec<Arc<texture::Texture>>, context: &context::Context, wgsl_preprocessor: &wgsl_preprocessor::WgslPreprocessor) -> Self {
+    pub fn new(texture_registry: &texture_registry::TextureRegistry, context: &context::Context, wgsl_preprocessor: &wgsl_preprocessor::WgslPreprocessor) -> Self {
 
         let uniform_bind_group_layout =
             context
@@ -179,7 +183,7 @@ impl RenderSystem {
                 });
 
         let (texture_bind_group_layout, texture_bind_group) =
-            Self::create_texture_bindings(textures, context);
+            Self::create_texture_bindings(texture_registry, context);
         let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = vec![
             &uniform_bind_group_layout,
             &texture_bind_group_layout,
@@ -188,21 +192,11 @@ impl RenderSystem {
 
         // bind_group_layouts.extend(textures.iter().map(|texture| &texture.bind_group_layout));
 
-        let standard_shader: wgpu::ShaderModule =
-            context
-                .device
-                .create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: Some("standard shader"),
-                    source: wgpu::ShaderSource::Wgsl(wgsl_preprocessor.get_code("standard.wgsl".to_string()).into()),
-                });
+        let standard_shader =
+            Self::create_shader(context, wgsl_preprocessor, "standard shader", "standard.wgsl");
         
-        let collectible_shader: wgpu::ShaderModule =
-            context
-                .device
-                .create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: Some("hover shader"),
-                    source: wgpu::ShaderSource::Wgsl(wgsl_preprocessor.get_code("hover.wgsl".to_string()).into()),
-                });
+        let collectible_shader =
+            Self::create_shader(context, wgsl_preprocessor, "hover shader", "hover.wgsl");
 
         let render_pipeline_layout =
             context
@@ -243,20 +237,12 @@ impl RenderSystem {
             Self::create_wireframe_pipeline(context, &uniform_bind_group_layout, wgsl_preprocessor);
 
 
-        let post_standard_shader = context
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("post standard shader"),
-                source: wgpu::ShaderSource::Wgsl(wgsl_preprocessor.get_code("post_standard.wgsl".to_string()).into()),
-            });
+        let post_standard_shader =
+            Self::create_shader(context, wgsl_preprocessor, "post standard shader", "post_standard.wgsl");
         let (post_standard_render_pipeline, post_standard_bind_group_layout) = Self::create_post_pipeline(context, &post_standard_shader);
 
-        let post_popup_shader = context
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("post popup shader"),
-                source: wgpu::ShaderSource::Wgsl(wgsl_preprocessor.get_code("post_popup.wgsl".to_string()).into()),
-            });
+        let post_popup_shader =
+            Self::create_shader(context, wgsl_preprocessor, "post popup shader", "post_popup.wgsl");
         let (post_popup_render_pipeline, post_popup_bind_group_layout) = Self::create_post_pipeline(context, &post_popup_shader);
 
         let outline_render_pipeline = Self::create_outline_pipeline(
@@ -282,7 +268,10 @@ impl RenderSystem {
             post_popup_bind_group_layout,
             uniform_bind_group_layout,
             light_bind_group_layout,
+            texture_bind_group_layout,
             texture_bind_group,
+            texture_regions: texture_registry.regions(),
+            render_pipeline_layout,
             stencil_compute_pipeline,
             stencil_compute_bind_group_layout,
             depth_stencil,
@@ -290,10 +279,8 @@ impl RenderSystem {
         }
     }
 
-    pub fn resize(&mut self, textures: &Vec<Arc<texture::Texture>>, context: &context::Context) {
-        let (_, texture_bind_group) = Self::create_texture_bindings(textures, context);
-
-        self.texture_bind_group = texture_bind_group;
+    pub fn resize(&mut self, texture_registry: &texture_registry::TextureRegistry, context: &context::Context) {
+        self.set_textures(texture_registry, context);
 
         self.depth_stencil = texture::TextureBasic::create_depth_texture(
             &context.device,
@@ -302,8 +289,122 @@ impl RenderSystem {
         );
     }
 
+    // rebinds the textures, e.g. after one is reloaded
+    pub fn set_textures(&mut self, texture_registry: &texture_registry::TextureRegistry, context: &context::Context) {
+        let (_, texture_bind_group) = Self::create_texture_bindings(texture_registry, context);
+
+        self.texture_bind_group = texture_bind_group;
+        self.texture_regions = texture_registry.regions();
+    }
+
+    // recreates only the pipelines whose shaders are, or include, one of the changed files. if
+    // any of them fails to compile, all of the old pipelines are kept
+    pub fn reload_shaders(
+        &mut self,
+        changed: &HashSet<String>,
+        context: &context::Context,
+        wgsl_preprocessor: &wgsl_preprocessor::WgslPreprocessor,
+    ) {
+        let is_changed = |file_name: &str| wgsl_preprocessor.depends_on(file_name, changed);
+        context.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let orig_render_pipeline = is_changed("standard.wgsl").then(|| {
+            Self::create_pipeline(
+                "Original Render Pipeline",
+                context,
+                &self.render_pipeline_layout,
+                &Self::create_shader(context, wgsl_preprocessor, "standard shader", "standard.wgsl"),
+                Some(Self::STANDARD_STENCIL_STATE),
+                &[model::ModelVertex2d::desc()],
+                None,
+            )
+        });
+        let collectible_render_pipeline = is_changed("hover.wgsl").then(|| {
+            Self::create_pipeline(
+                "Collectible Render Pipeline",
+                context,
+                &self.render_pipeline_layout,
+                &Self::create_shader(context, wgsl_preprocessor, "hover shader", "hover.wgsl"),
+                Some(Self::STANDARD_STENCIL_STATE),
+                &[model::ModelVertex2d::desc()],
+                None,
+            )
+        });
+        let outline_render_pipeline = is_changed("outline.wgsl").then(|| {
+            Self::create_outline_pipeline(
+                context,
+                &self.uniform_bind_group_layout,
+                &self.texture_bind_group_layout,
+                wgsl_preprocessor,
+            )
+        });
+        let wireframe = is_changed("wireframe.wgsl").then(|| {
+            Self::create_wireframe_pipeline(context, &self.uniform_bind_group_layout, wgsl_preprocessor)
+        });
+        let post_standard = is_changed("post_standard.wgsl").then(|| {
+            Self::create_post_pipeline(
+                context,
+                &Self::create_shader(context, wgsl_preprocessor, "post standard shader", "post_standard.wgsl"),
+            )
+        });
+        let post_popup = is_changed("post_popup.wgsl").then(|| {
+            Self::create_post_pipeline(
+                context,
+                &Self::create_shader(context, wgsl_preprocessor, "post popup shader", "post_popup.wgsl"),
+            )
+        });
+        let stencil_compute = is_changed("compute.wgsl")
+            .then(|| Self::create_stencil_compute_pipeline(context, wgsl_preprocessor));
+
+        if let Some(error) = pollster::block_on(context.device.pop_error_scope()) {
+            log::error!("failed to reload shaders, keeping the old pipelines: {}", error);
+            return;
+        }
+
+        if let Some(pipeline) = orig_render_pipeline {
+            self.orig_render_pipeline = pipeline;
+        }
+        if let Some(pipeline) = collectible_render_pipeline {
+            self.collectible_render_pipeline = pipeline;
+        }
+        if let Some(pipeline) = outline_render_pipeline {
+            self.outline_render_pipeline = pipeline;
+        }
+        if let Some((pipeline, bind_group_layout)) = wireframe {
+            self.wireframe_render_pipeline = pipeline;
+            self.wireframe_bind_group_layout = bind_group_layout;
+        }
+        if let Some((pipeline, bind_group_layout)) = post_standard {
+            self.post_standard_render_pipeline = pipeline;
+            self.post_standard_bind_group_layout = bind_group_layout;
+        }
+        if let Some((pipeline, bind_group_layout)) = post_popup {
+            self.post_popup_render_pipeline = pipeline;
+            self.post_popup_bind_group_layout = bind_group_layout;
+        }
+        if let Some((pipeline, bind_group_layout)) = stencil_compute {
+            self.stencil_compute_pipeline = pipeline;
+            self.stencil_compute_bind_group_layout = bind_group_layout;
+        }
+    }
+
+    fn create_shader(
+        context: &context::Context,
+        wgsl_preprocessor: &wgsl_preprocessor::WgslPreprocessor,
+        label: &str,
+        file_name: &str,
+    ) -> wgpu::ShaderModule {
+        context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(wgsl_preprocessor.get_code(file_name.to_string()).into()),
+            })
+    }
+
+    // binding numbers follow TextureRegistry::views, same as the generated texture.wgsl
     pub fn create_texture_bindings(
-        textures: &Vec<Arc<texture::Texture>>,
+        texture_registry: &texture_registry::TextureRegistry,
         context: &context::Context,
     ) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
         let mut texture_bind_group_layout_entries = vec![wgpu::BindGroupLayoutEntry {
@@ -322,8 +423,7 @@ impl RenderSystem {
             resource: wgpu::BindingResource::Sampler(&sampler),
         }];
 
-        for i in 0..textures.len() {
-            let texture = &textures[i];
+        for view in texture_registry.views() {
             let cur_len = texture_bind_group_layout_entries.len() as u32;
 
             texture_bind_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
@@ -340,26 +440,8 @@ impl RenderSystem {
 
             texture_bind_group_entries.push(wgpu::BindGroupEntry {
                 binding: cur_len,
-                resource: wgpu::BindingResource::TextureView(&texture.view),
+                resource: wgpu::BindingResource::TextureView(view),
             });
-
-            if let Some((_, normal_view)) = &texture.normal_info {
-                texture_bind_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
-                    binding: cur_len + 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                });
-
-                texture_bind_group_entries.push(wgpu::BindGroupEntry {
-                    binding: cur_len + 1,
-                    resource: wgpu::BindingResource::TextureView(&normal_view),
-                });
-            }
         }
 
         let texture_bind_group_layout =
@@ -381,9 +463,12 @@ impl RenderSystem {
         (texture_bind_group_layout, texture_bind_group)
     }
 
+    // texture coordinates are relative to the texture_index's region, they're mapped onto the
+    // page it's on here
     fn get_vertices_for_entity<'a>(
         pos: &'a component::PositionComponent,
         vertex_array: &'a component::VertexArrayComponent,
+        texture_regions: &'a [texture_registry::TextureRegion],
         cur_len: usize,
     ) -> (
         impl Iterator<Item = ModelVertex2d> + 'a,
@@ -391,6 +476,8 @@ impl RenderSystem {
     ) {
         let max_x = vertex_array.tex_coords.iter().map(|tex| tex.x).fold(f32::MIN, |a, b| a.max(b));
         let min_x = vertex_array.tex_coords.iter().map(|tex| tex.x).fold(f32::MAX, |a, b| a.min(b));
+        let region = texture_regions.get(vertex_array.texture_index as usize);
+        let page = region.map_or(vertex_array.texture_index, |region| region.page);
         (
             vertex_array
                 .vertices
@@ -404,8 +491,9 @@ impl RenderSystem {
                     } else {
                         tex_coord
                     };
+                    let final_tex_coord = region.map_or(final_tex_coord, |region| region.map(final_tex_coord));
 
-                    let twod_coords = (vertex_pos.mul_element_wise(pos.scale)) + pos.position;
+                    let twod_coords = pos.transform_point(*vertex_pos);
 
                     model::ModelVertex2d {
                         position: cgmath::Vector3::new(
@@ -416,7 +504,7 @@ impl RenderSystem {
                         .into(),
                         tex_coords: final_tex_coord.into(),
                         normal_coords: final_tex_coord.into(), // TODO: maybe have to flip something here?
-                        extra_info: (vertex_array.texture_index
+                        extra_info: (page
                             + vertex_array.is_flipped as u32 * 256),
                     }
                 }),
@@ -430,6 +518,7 @@ impl RenderSystem {
     fn get_vertex_and_lighting_data(
 pipeline_infos: Vec<PipelineInfo>,
 render_options: &RenderOptions,
+texture_regions: &[texture_registry::TextureRegion],
     ) -> (ModelBuffer, Vec<LightUniform>, usize) {
         let (vertices, indices, light_uniforms, len) = 
         
@@ -440,7 +529,7 @@ render_options: &RenderOptions,
                 PipelineInfo {pos,  v_arr, light, metadata: _}| {
                     let cur_len = vertices.len();
                     let (entity_vertices, entity_indices) =
-                        Self::get_vertices_for_entity(pos, v_arr,  cur_len);
+                        Self::get_vertices_for_entity(pos, v_arr, texture_regions, cur_len);
                     vertices.extend(entity_vertices);
                     indices.extend(entity_indices);
 
@@ -473,19 +562,16 @@ render_options: &RenderOptions,
     pub fn render(
         &mut self,
         render_options: &mut RenderOptions,
-        positions: &component::EntityMap<component::PositionComponent>,
-        vertex_arrays: &component::EntityMap<component::VertexArrayComponent>,
-        lights: &component::EntityMap<uniform::LightComponent>,
-        metadata_components: &component::EntityMap<component::MetadataComponent>,
+        world: &World,
         context: &context::Context,
         gui: &mut gui::Gui,
         window: Arc<Window>,
         time_elapsed: Duration,
-        world_uniform: &uniform::WorldUniform,
-        camera: &camera::OrthographicCamera,
-        gui_info: &mut gui::GuiInfo,
-        game_mode: &mut game::GameMode
     ) -> Result<(), wgpu::SurfaceError> {
+        let world_uniform = world.resource::<uniform::WorldUniform>();
+        let camera = world.resource::<camera::OrthographicCamera>();
+        let mut gui_info = world.resource_mut::<gui::GuiInfo>();
+        let mut game_mode = world.resource_mut::<game::GameMode>();
 
         let camera_buffer = camera.get_buffer(&context.device);
         let world_buffer = world_uniform.get_buffer(&context.device);
@@ -544,15 +630,17 @@ render_options: &RenderOptions,
             });
 
 
+        let mut renderables = world.query::<(
+            &component::PositionComponent,
+            &component::VertexArrayComponent,
+            Option<&uniform::LightComponent>,
+            &component::MetadataComponent,
+        )>();
+
         let (standard_pipeline_infos, collectible_pipeline_infos ): (Vec<_>, Vec<_>) = 
-        utils::zip4_entities(positions, vertex_arrays, lights, metadata_components)
-        .filter_map(|(_, pos, v_arr, light, metadata)| {
-            match (pos, v_arr, metadata ){
-                (Some(pos), Some(v_arr), Some(metadata)) => {
-                    Some(PipelineInfo {pos: pos, v_arr: v_arr, light: light, metadata: metadata})
-                }
-                _ => None
-            }
+        renderables.iter()
+        .map(|(_, (pos, v_arr, light, metadata))| {
+            PipelineInfo {pos, v_arr, light, metadata}
         })
         .partition(|pipeline_info| {
            pipeline_info.v_arr.shader_type == component::ShaderType::STANDARD 
@@ -560,12 +648,14 @@ render_options: &RenderOptions,
 
         let (standard_model_buffer, standard_light_uniforms, _) = Self::get_vertex_and_lighting_data(
             standard_pipeline_infos,
-            render_options
+            render_options,
+            &self.texture_regions,
         );
 
         let (collectible_model_buffer, collectible_light_uniforms, _) = Self::get_vertex_and_lighting_data(
             collectible_pipeline_infos,
-            &render_options
+            &render_options,
+            &self.texture_regions,
         );
 
 
@@ -785,12 +875,12 @@ render_options: &RenderOptions,
                 timestamp_writes: None,
             });
 
-            let post_bind_group_layout = match game_mode {
+            let post_bind_group_layout = match *game_mode {
                 game::GameMode::STANDARD => &self.post_standard_bind_group_layout,
                 game::GameMode::POPUP => &self.post_popup_bind_group_layout,
             };
 
-            let post_render_pipeline = match game_mode {
+            let post_render_pipeline = match *game_mode {
                 game::GameMode::STANDARD => &self.post_standard_render_pipeline,
                 game::GameMode::POPUP => &self.post_popup_render_pipeline,
             };
@@ -823,26 +913,21 @@ render_options: &RenderOptions,
 
         if render_options.render_outline {
             let (outline_vertices, outline_indices) =
-                utils::zip3_entities(positions, vertex_arrays, metadata_components)
-                    .filter_map(|(_, pos, vertex_array, metadata)| {
-                        pos.as_ref().and_then(|pos| {
-                            vertex_array.as_ref().and_then(|vertex_array| {
-                                metadata.as_ref().unwrap().should_outline().then(|| {
-                                    let mut new_pos = pos.clone();
-                                    new_pos.scale_outward(cgmath::Vector2::new(
-                                        Self::OUTLINE_SCALE_FACTOR,
-                                        Self::OUTLINE_SCALE_FACTOR,
-                                    ));
-                                    (new_pos, vertex_array)
-                                })
-                            })
-                        })
+                renderables.iter()
+                    .filter(|(_, (_, _, _, metadata))| metadata.should_outline())
+                    .map(|(_, (pos, vertex_array, _, _))| {
+                        let mut new_pos = pos.clone();
+                        new_pos.scale_outward(cgmath::Vector2::new(
+                            Self::OUTLINE_SCALE_FACTOR,
+                            Self::OUTLINE_SCALE_FACTOR,
+                        ));
+                        (new_pos, vertex_array)
                     })
                     .fold(
                         (Vec::new(), Vec::new()),
                         |(mut vertices, mut indices), (pos, vertex_array)| {
                             let cur_len = vertices.len();
-                            let (v, i) = Self::get_vertices_for_entity(&pos, vertex_array,  cur_len);
+                            let (v, i) = Self::get_vertices_for_entity(&pos, vertex_array, &self.texture_regions, cur_len);
                             vertices.extend(v);
                             indices.extend(i);
                             (vertices, indices)
@@ -936,7 +1021,7 @@ render_options: &RenderOptions,
         };
 
 
-        gui.draw(&context, &mut encoder, window, &surface_view, gui_info, render_options, game_mode, self.last_stencil_count);
+        gui.draw(&context, &mut encoder, window, &surface_view, &mut gui_info, render_options, &mut game_mode, self.last_stencil_count);
 
         context.queue.submit(std::iter::once(encoder.finish()));
         output.present();
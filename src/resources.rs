@@ -0,0 +1,56 @@
+use std::any::{type_name, Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+
+// singletons keyed by type. like component storages these are borrow checked at runtime, so
+// a system can hold several different resources mutably at once but not the same one twice
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+}
+
+impl Resources {
+    pub fn insert<T: 'static>(&mut self, resource: T) {
+        self.resources
+            .insert(TypeId::of::<T>(), RefCell::new(Box::new(resource)));
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .and_then(|resource| resource.into_inner().downcast().ok())
+            .map(|resource| *resource)
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get<T: 'static>(&self) -> Ref<'_, T> {
+        self.try_get::<T>()
+            .unwrap_or_else(|| panic!("resource {} does not exist", type_name::<T>()))
+    }
+
+    pub fn get_mut<T: 'static>(&self) -> RefMut<'_, T> {
+        self.try_get_mut::<T>()
+            .unwrap_or_else(|| panic!("resource {} does not exist", type_name::<T>()))
+    }
+
+    pub fn try_get<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        self.resources.get(&TypeId::of::<T>()).map(|resource| {
+            let resource = resource.try_borrow().unwrap_or_else(|_| {
+                panic!("resource {} is already borrowed mutably", type_name::<T>())
+            });
+            Ref::map(resource, |resource| resource.downcast_ref().unwrap())
+        })
+    }
+
+    pub fn try_get_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        self.resources.get(&TypeId::of::<T>()).map(|resource| {
+            let resource = resource
+                .try_borrow_mut()
+                .unwrap_or_else(|_| panic!("resource {} is already borrowed", type_name::<T>()));
+            RefMut::map(resource, |resource| resource.downcast_mut().unwrap())
+        })
+    }
+}
@@ -0,0 +1,727 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::asset;
+use crate::bundle;
+use crate::component::{self, Entity};
+use crate::physics;
+use crate::sprite;
+use crate::tiled;
+use crate::tilemap;
+use crate::uniform;
+use crate::world::{Bundle, World};
+
+// a level on disk. every entity is a list of components, some of which (ParallaxLayer,
+// PlatformTile, Signpost, CollectibleScroll) expand into the matching bundle. anything that
+// needs a position takes it from the entity's Position, so that has to be present for those.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneEntity {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub components: Vec<SceneComponent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Depth {
+    Background(u32),
+    Foreground,
+    Object,
+}
+
+impl Depth {
+    fn z_value(self) -> f32 {
+        match self {
+            Depth::Background(layer) => {
+                component::VertexArrayComponent::BACKGROUND_Z * layer as f32
+            }
+            Depth::Foreground => component::VertexArrayComponent::FOREGROUND_Z,
+            Depth::Object => component::VertexArrayComponent::OBJECT_Z,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SceneComponent {
+    Position {
+        position: (f32, f32),
+        scale: (f32, f32),
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default)]
+        pivot: (f32, f32),
+    },
+    Sprite {
+        sheet: String,
+        depth: Depth,
+        #[serde(default)]
+        sheet_position: Option<(u32, u32)>,
+        #[serde(default)]
+        flipped: bool,
+    },
+    Circle {
+        depth: Depth,
+    },
+    // defaults to the entity's quad, on the Terrain layer and colliding with every layer. an
+    // empty list of layers is none of them
+    Collider {
+        #[serde(default)]
+        bottom_left_offset: Option<(f32, f32)>,
+        #[serde(default)]
+        top_right_offset: Option<(f32, f32)>,
+        #[serde(default)]
+        layers: Option<Vec<physics::CollisionLayer>>,
+        #[serde(default)]
+        collides_with: Option<Vec<physics::CollisionLayer>>,
+    },
+    // like a Collider, but reports the bodies in it instead of blocking them. it notices bodies
+    // on every layer unless it's given the ones to collide with
+    Trigger {
+        #[serde(default)]
+        bottom_left_offset: Option<(f32, f32)>,
+        #[serde(default)]
+        top_right_offset: Option<(f32, f32)>,
+        #[serde(default)]
+        collides_with: Option<Vec<physics::CollisionLayer>>,
+    },
+    // these need a Trigger to be walked into
+    Checkpoint,
+    KillZone,
+    // leads to the scene at the path
+    LevelExit {
+        scene: String,
+    },
+    Light {
+        color: (f32, f32, f32),
+        linear_dropoff: f32,
+        quadratic_dropoff: f32,
+        ambient_strength: f32,
+        diffuse_strength: f32,
+    },
+    Metadata {
+        outline: bool,
+        controllable: bool,
+    },
+    Physics,
+    // oscillates around the entity's position
+    MovingPlatform {
+        amplitude: f32,
+        period_secs: f32,
+        horizontal: bool,
+    },
+    ParallaxLayer {
+        sheet: String,
+        tex_coord_scale: (f32, f32),
+        layer: u32,
+        move_speed: f32,
+    },
+    PlatformTile {
+        sheet: String,
+        sheet_position: (u32, u32),
+    },
+    Signpost {
+        sheet: String,
+        popup_text: String,
+    },
+    CollectibleScroll {
+        sheet: String,
+        popup_text: String,
+    },
+    // the Position is the map's bottom left corner, tiles are row major from the top row
+    Tilemap {
+        sheet: String,
+        depth: Depth,
+        tile_size: (f32, f32),
+        width: u32,
+        tiles: Vec<Option<tilemap::Tile>>,
+        #[serde(default)]
+        solid_tiles: Vec<u32>,
+        // picks every tile's index from its neighbours, so tiles only need to be Some
+        #[serde(default)]
+        auto_tile: Option<tilemap::AutoTileRules>,
+        // how many tiles a row of the sheet the indices count in has, if not the sprite sheet's
+        // own, e.g. a tiled tileset's. they're moved to the tile at the same spot in the sheet
+        #[serde(default)]
+        columns: Option<u32>,
+    },
+}
+
+type Insert = Box<dyn FnOnce(&mut World, Entity)>;
+
+// the components of one scene entity, resolved against the loaded sprite sheets
+struct SceneEntityBundle {
+    inserts: Vec<Insert>,
+}
+
+impl Bundle for SceneEntityBundle {
+    fn insert(self, world: &mut World, entity: Entity) {
+        self.inserts
+            .into_iter()
+            .for_each(|insert| insert(world, entity));
+    }
+}
+
+fn vec2<T>((x, y): (T, T)) -> cgmath::Vector2<T> {
+    cgmath::Vector2::new(x, y)
+}
+
+impl Scene {
+    pub fn from_ron(source: &str) -> Result<Self> {
+        ron::from_str(source).map_err(|e| anyhow!("invalid scene: {}", e))
+    }
+
+    // tiled maps (.tmj) are imported, anything else is read as ron
+    pub fn load(asset_server: &mut asset::AssetServer, path: &str) -> Result<Self> {
+        let source = asset_server.load_text(path)?;
+        let source = asset_server.get(source);
+        if Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension == "tmj")
+        {
+            tiled::import(&source)
+        } else {
+            Self::from_ron(&source)
+        }
+        .with_context(|| format!("in {}", path))
+    }
+
+    // nothing is spawned unless every entity in the scene resolves
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        sprite_sheets: &[Rc<RefCell<sprite::SpriteSheet>>],
+    ) -> Result<Vec<Entity>> {
+        let bundles = self
+            .entities
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| {
+                Self::resolve(entity, sprite_sheets).with_context(|| match &entity.name {
+                    Some(name) => format!("in scene entity {}", name),
+                    None => format!("in scene entity #{}", index),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(bundles
+            .into_iter()
+            .map(|bundle| world.builder().with_bundle(bundle).build())
+            .collect())
+    }
+
+    fn resolve(
+        entity: &SceneEntity,
+        sprite_sheets: &[Rc<RefCell<sprite::SpriteSheet>>],
+    ) -> Result<SceneEntityBundle> {
+        let sheet = |label: &str| {
+            sprite_sheets
+                .iter()
+                .position(|sprite_sheet| sprite_sheet.borrow().label() == label)
+                .map(|index| (index as u32, sprite_sheets[index].clone()))
+                .ok_or_else(|| anyhow!("unknown sprite sheet {}", label))
+        };
+
+        let position_component =
+            entity
+                .components
+                .iter()
+                .find_map(|scene_component| match scene_component {
+                    SceneComponent::Position {
+                        position,
+                        scale,
+                        rotation,
+                        pivot,
+                    } => {
+                        let mut position_component =
+                            component::PositionComponent::new(vec2(*position), vec2(*scale));
+                        position_component.rotation = *rotation;
+                        position_component.pivot = vec2(*pivot);
+                        Some(position_component)
+                    }
+                    _ => None,
+                });
+        let position = || {
+            position_component
+                .clone()
+                .ok_or_else(|| anyhow!("missing a Position component"))
+        };
+
+        // the entity's quad, unless the offsets say otherwise
+        let bounding_box = |bottom_left_offset: Option<(f32, f32)>,
+                            top_right_offset: Option<(f32, f32)>|
+         -> Result<physics::BoundingBox> {
+            let mut bounding_box = bundle::bounding_box_for(&position()?);
+            if let Some(offset) = bottom_left_offset {
+                bounding_box.bottom_left_offset = vec2(offset);
+            }
+            if let Some(offset) = top_right_offset {
+                bounding_box.top_right_offset = vec2(offset);
+            }
+            Ok(bounding_box)
+        };
+
+        let has_bundle = entity.components.iter().any(|scene_component| {
+            matches!(
+                scene_component,
+                SceneComponent::ParallaxLayer { .. }
+                    | SceneComponent::PlatformTile { .. }
+                    | SceneComponent::Signpost { .. }
+                    | SceneComponent::CollectibleScroll { .. }
+            )
+        });
+
+        let mut inserts: Vec<Insert> = Vec::new();
+        if let Some(name) = entity.name.clone() {
+            inserts.push(Box::new(move |world, entity| {
+                world.insert(entity, component::NameComponent { name })
+            }));
+        }
+        for scene_component in entity.components.iter().cloned() {
+            let insert: Insert = match scene_component {
+                // the bundles insert the position themselves
+                SceneComponent::Position { .. } if has_bundle => continue,
+                SceneComponent::Position { .. } => {
+                    let position_component = position()?;
+                    Box::new(move |world, entity| world.insert(entity, position_component))
+                }
+                SceneComponent::Sprite {
+                    sheet: label,
+                    depth,
+                    sheet_position,
+                    flipped,
+                } => {
+                    let (texture_index, sprite_sheet) = sheet(&label)?;
+                    Box::new(move |world, entity| {
+                        let mut vertex_array_component =
+                            component::VertexArrayComponent::textured_quad(
+                                texture_index,
+                                depth.z_value(),
+                            );
+                        vertex_array_component.is_flipped = flipped;
+                        world.insert(entity, vertex_array_component);
+                        if let Some(sheet_position) = sheet_position {
+                            world.insert(
+                                entity,
+                                sprite::SheetPositionComponent {
+                                    sprite_sheet,
+                                    sheet_position: vec2(sheet_position),
+                                },
+                            );
+                        }
+                    })
+                }
+                SceneComponent::Circle { depth } => Box::new(move |world, entity| {
+                    world.insert(
+                        entity,
+                        component::VertexArrayComponent::circle(depth.z_value()),
+                    )
+                }),
+                SceneComponent::Collider {
+                    bottom_left_offset,
+                    top_right_offset,
+                    layers,
+                    collides_with,
+                } => {
+                    let bounding_box = bounding_box(bottom_left_offset, top_right_offset)?;
+                    let filter = physics::CollisionFilter::new(
+                        layers
+                            .as_deref()
+                            .unwrap_or(&[physics::CollisionLayer::Terrain]),
+                        collides_with
+                            .as_deref()
+                            .unwrap_or(&physics::CollisionLayer::ALL),
+                    );
+                    Box::new(move |world, entity| {
+                        world.insert(
+                            entity,
+                            physics::ColliderBoxComponent {
+                                bounding_box,
+                                filter,
+                            },
+                        )
+                    })
+                }
+                SceneComponent::Trigger {
+                    bottom_left_offset,
+                    top_right_offset,
+                    collides_with,
+                } => {
+                    let trigger = physics::TriggerComponent::new(
+                        bounding_box(bottom_left_offset, top_right_offset)?,
+                        collides_with
+                            .as_deref()
+                            .unwrap_or(&physics::CollisionLayer::ALL),
+                    );
+                    Box::new(move |world, entity| world.insert(entity, trigger))
+                }
+                SceneComponent::Checkpoint => {
+                    Box::new(|world, entity| world.insert(entity, component::CheckpointComponent))
+                }
+                SceneComponent::KillZone => {
+                    Box::new(|world, entity| world.insert(entity, component::KillZoneComponent))
+                }
+                SceneComponent::LevelExit { scene } => Box::new(move |world, entity| {
+                    world.insert(entity, component::LevelExitComponent { scene })
+                }),
+                SceneComponent::Light {
+                    color: (r, g, b),
+                    linear_dropoff,
+                    quadratic_dropoff,
+                    ambient_strength,
+                    diffuse_strength,
+                } => Box::new(move |world, entity| {
+                    world.insert(
+                        entity,
+                        uniform::LightComponent {
+                            color: cgmath::Vector3::new(r, g, b),
+                            linear_dropoff,
+                            quadratic_dropoff,
+                            ambient_strength,
+                            diffuse_strength,
+                        },
+                    )
+                }),
+                SceneComponent::Metadata {
+                    outline,
+                    controllable,
+                } => Box::new(move |world, entity| {
+                    world.insert(
+                        entity,
+                        component::MetadataComponent::new(outline, controllable),
+                    )
+                }),
+                SceneComponent::Tilemap {
+                    sheet: label,
+                    depth,
+                    tile_size,
+                    width,
+                    tiles,
+                    solid_tiles,
+                    auto_tile,
+                    columns,
+                } => {
+                    // the chunks and colliders are placed relative to it
+                    position()?;
+                    if width == 0 || tiles.len() % width as usize != 0 {
+                        return Err(anyhow!(
+                            "tilemap has {} tiles, which doesn't fit a width of {}",
+                            tiles.len(),
+                            width
+                        ));
+                    }
+                    let (texture_index, sprite_sheet) = sheet(&label)?;
+                    let (tiles, solid_tiles) = match columns {
+                        Some(columns) => {
+                            let sprite_sheet = sprite_sheet.borrow();
+                            let sheet_columns =
+                                sprite_sheet.dimensions.0 / sprite_sheet.sprite_size().0;
+                            if columns == 0 {
+                                return Err(anyhow!("tilemap has a sheet without columns"));
+                            }
+                            let index = |index: u32| {
+                                let position =
+                                    cgmath::Vector2::new(index % columns, index / columns);
+                                if position.x >= sheet_columns {
+                                    return Err(anyhow!(
+                                        "tile {} is in column {}, past the {} columns of {}",
+                                        index,
+                                        position.x,
+                                        sheet_columns,
+                                        label
+                                    ));
+                                }
+                                Ok(sprite_sheet.get_index_by_position(position))
+                            };
+                            (
+                                tiles
+                                    .into_iter()
+                                    .map(|tile| {
+                                        tile.map(|tile| {
+                                            Ok(tilemap::Tile {
+                                                index: index(tile.index)?,
+                                                ..tile
+                                            })
+                                        })
+                                        .transpose()
+                                    })
+                                    .collect::<Result<Vec<_>>>()?,
+                                solid_tiles
+                                    .into_iter()
+                                    .map(index)
+                                    .collect::<Result<Vec<_>>>()?,
+                            )
+                        }
+                        None => (tiles, solid_tiles),
+                    };
+                    Box::new(move |world, entity| {
+                        let mut tilemap_component = tilemap::TilemapComponent::new(
+                            sprite_sheet,
+                            texture_index,
+                            vec2(tile_size),
+                            depth.z_value(),
+                            width,
+                            tiles,
+                        );
+                        tilemap_component.set_solid_tiles(solid_tiles.into_iter().collect());
+                        if let Some(auto_tile) = auto_tile {
+                            tilemap_component.set_auto_tile(auto_tile);
+                        }
+                        world.insert(entity, tilemap_component)
+                    })
+                }
+                SceneComponent::Physics => {
+                    Box::new(|world, entity| world.insert(entity, physics::PhysicsComponent::new()))
+                }
+                SceneComponent::MovingPlatform {
+                    amplitude,
+                    period_secs,
+                    horizontal,
+                } => {
+                    let original_position = position()?.position;
+                    Box::new(move |world, entity| {
+                        world.insert(
+                            entity,
+                            component::MovingPlatformComponent {
+                                amplitude,
+                                period_secs,
+                                original_position,
+                                horizontal,
+                                prev_change: 0.,
+                                elapsed_secs: 0.,
+                            },
+                        )
+                    })
+                }
+                SceneComponent::ParallaxLayer {
+                    sheet: label,
+                    tex_coord_scale,
+                    layer,
+                    move_speed,
+                } => {
+                    let bundle = bundle::ParallaxLayerBundle {
+                        position: position()?,
+                        texture_index: sheet(&label)?.0,
+                        tex_coord_scale: vec2(tex_coord_scale),
+                        layer,
+                        move_speed,
+                    };
+                    Box::new(move |world, entity| bundle.insert(world, entity))
+                }
+                SceneComponent::PlatformTile {
+                    sheet: label,
+                    sheet_position,
+                } => {
+                    let (texture_index, sprite_sheet) = sheet(&label)?;
+                    let bundle = bundle::PlatformTileBundle {
+                        position: position()?,
+                        texture_index,
+                        sprite_sheet,
+                        sheet_position: vec2(sheet_position),
+                    };
+                    Box::new(move |world, entity| bundle.insert(world, entity))
+                }
+                SceneComponent::Signpost {
+                    sheet: label,
+                    popup_text,
+                } => {
+                    let bundle = bundle::SignpostBundle {
+                        position: position()?,
+                        texture_index: sheet(&label)?.0,
+                        popup_text,
+                    };
+                    Box::new(move |world, entity| bundle.insert(world, entity))
+                }
+                SceneComponent::CollectibleScroll {
+                    sheet: label,
+                    popup_text,
+                } => {
+                    let bundle = bundle::CollectibleScrollBundle {
+                        position: position()?,
+                        texture_index: sheet(&label)?.0,
+                        popup_text,
+                    };
+                    Box::new(move |world, entity| bundle.insert(world, entity))
+                }
+            };
+            inserts.push(insert);
+        }
+
+        Ok(SceneEntityBundle { inserts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::context;
+    use crate::texture;
+
+    const SCENE: &str = r#"Scene(
+        entities: [
+            (
+                name: Some("ledge"),
+                components: [
+                    Position(position: (10.0, 20.0), scale: (8.0, 2.0)),
+                    Collider(top_right_offset: Some((4.0, 3.0)), collides_with: Some([Player])),
+                    MovingPlatform(amplitude: 5.0, period_secs: 2.0, horizontal: true),
+                ],
+            ),
+            (
+                components: [
+                    Position(position: (0.0, 0.0), scale: (4.0, 4.0), rotation: 0.5),
+                    Trigger(),
+                    Checkpoint,
+                    Physics,
+                ],
+            ),
+        ],
+    )"#;
+
+    #[test]
+    fn spawns_every_entity_with_its_components() {
+        let scene = Scene::from_ron(SCENE).unwrap();
+        let mut world = World::new();
+        let entities = scene.spawn(&mut world, &[]).unwrap();
+        assert_eq!(entities.len(), 2);
+
+        let ledge = entities[0];
+        assert_eq!(
+            world.get::<component::NameComponent>(ledge).unwrap().name,
+            "ledge"
+        );
+        let position = world.get::<component::PositionComponent>(ledge).unwrap();
+        assert_eq!(position.position, cgmath::Vector2::new(10., 20.));
+        assert_eq!(position.scale, cgmath::Vector2::new(8., 2.));
+        let collider = world.get::<physics::ColliderBoxComponent>(ledge).unwrap();
+        // only the offset that's given replaces the quad's
+        assert_eq!(
+            collider.bounding_box.bottom_left_offset,
+            cgmath::Vector2::new(-4., -1.)
+        );
+        assert_eq!(
+            collider.bounding_box.top_right_offset,
+            cgmath::Vector2::new(4., 3.)
+        );
+        assert_eq!(
+            collider.filter,
+            physics::CollisionFilter::new(
+                &[physics::CollisionLayer::Terrain],
+                &[physics::CollisionLayer::Player]
+            )
+        );
+        let moving_platform = world
+            .get::<component::MovingPlatformComponent>(ledge)
+            .unwrap();
+        assert_eq!(
+            moving_platform.original_position,
+            cgmath::Vector2::new(10., 20.)
+        );
+        assert!(world.get::<physics::PhysicsComponent>(ledge).is_none());
+
+        let checkpoint = entities[1];
+        assert!(world.get::<component::NameComponent>(checkpoint).is_none());
+        assert!(world
+            .get::<component::CheckpointComponent>(checkpoint)
+            .is_some());
+        assert!(world.get::<physics::PhysicsComponent>(checkpoint).is_some());
+        assert_eq!(
+            world
+                .get::<physics::TriggerComponent>(checkpoint)
+                .unwrap()
+                .bounding_box
+                .rotation,
+            0.5
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_malformed_components() {
+        let unknown = SCENE.replace("Checkpoint,", "Teleporter(to: \"ledge\"),");
+        assert!(Scene::from_ron(&unknown)
+            .unwrap_err()
+            .to_string()
+            .contains("Teleporter"));
+
+        // MovingPlatform without its period
+        let malformed = SCENE.replace(" period_secs: 2.0,", "");
+        assert!(Scene::from_ron(&malformed)
+            .unwrap_err()
+            .to_string()
+            .contains("period_secs"));
+    }
+
+    #[test]
+    fn spawns_nothing_unless_everything_resolves() {
+        let mut world = World::new();
+
+        let unknown_sheet = Scene::from_ron(
+            &SCENE.replace("Physics,", "Sprite(sheet: \"missing\", depth: Object),"),
+        )
+        .unwrap();
+        let error = unknown_sheet.spawn(&mut world, &[]).unwrap_err();
+        assert!(format!("{:#}", error).contains("unknown sprite sheet missing"));
+
+        let no_position = Scene::from_ron(
+            &SCENE.replace("Position(position: (10.0, 20.0), scale: (8.0, 2.0)),", ""),
+        )
+        .unwrap();
+        let error = no_position.spawn(&mut world, &[]).unwrap_err();
+        assert!(format!("{:#}", error).contains("in scene entity ledge"));
+
+        assert_eq!(world.entities().count(), 0);
+    }
+
+    #[test]
+    fn tilemap_indices_move_to_the_same_spot_in_the_sheet() {
+        let Some((device, queue)) = context::headless_device() else {
+            eprintln!("no graphics adapter, skipping");
+            return;
+        };
+        // 4 columns and 2 rows of 8px tiles
+        let image = image::DynamicImage::new_rgba8(32, 16);
+        let texture =
+            texture::Texture::from_image(&device, &queue, &image, None, None, false).unwrap();
+        let sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
+            "tiles",
+            Arc::new(texture),
+            8,
+            8,
+        )));
+        let tilemap = |columns: u32, index: u32| {
+            Scene::from_ron(&format!(
+                r#"Scene(entities: [(components: [
+                    Position(position: (0.0, 0.0), scale: (1.0, 1.0)),
+                    Tilemap(sheet: "tiles", depth: Object, tile_size: (8.0, 8.0), width: 2,
+                        tiles: [Some((index: 1)), Some((index: {}))], columns: Some({})),
+                ])])"#,
+                index, columns
+            ))
+            .unwrap()
+        };
+
+        let mut world = World::new();
+        // the tileset has 2 columns, so 3 is the second tile of its second row
+        let entities = tilemap(2, 3)
+            .spawn(&mut world, std::slice::from_ref(&sheet))
+            .unwrap();
+        let tilemap_component = world.get::<tilemap::TilemapComponent>(entities[0]).unwrap();
+        assert_eq!(tilemap_component.get(0, 0).unwrap().index, 1);
+        assert_eq!(tilemap_component.get(1, 0).unwrap().index, 5);
+        drop(tilemap_component);
+
+        let error = tilemap(8, 5)
+            .spawn(&mut world, std::slice::from_ref(&sheet))
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("tile 5 is in column 5, past the 4 columns"));
+    }
+}
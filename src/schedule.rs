@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Stage {
+    FixedUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::FixedUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+}
+
+pub type System<T> = fn(&mut T);
+
+struct SystemDescriptor<T> {
+    name: &'static str,
+    stage: Stage,
+    system: System<T>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+}
+
+pub struct SystemConfig<'s, T> {
+    descriptor: &'s mut SystemDescriptor<T>,
+}
+
+impl<'s, T> SystemConfig<'s, T> {
+    pub fn before(self, name: &'static str) -> Self {
+        self.descriptor.before.push(name);
+        self
+    }
+
+    pub fn after(self, name: &'static str) -> Self {
+        self.descriptor.after.push(name);
+        self
+    }
+}
+
+// systems run in the order they were added unless a before/after constraint says otherwise.
+// FixedUpdate runs zero or more times per frame depending on how much time has built up,
+// every other stage runs exactly once per call to run_frame. the sync point runs once per
+// frame between PostUpdate and Render, so everything queued during the update stages is
+// applied before anything gets drawn. the time built up is capped at MAX_FIXED_STEPS steps and
+// the rest dropped, so a long stall (a breakpoint, a dragged window) slows the game down for a
+// frame instead of snowballing into longer frames.
+pub struct Schedule<T> {
+    systems: Vec<SystemDescriptor<T>>,
+    order: Option<HashMap<Stage, Vec<usize>>>,
+    sync_point: System<T>,
+    fixed_timestep: Duration,
+    accumulator: Duration,
+    fixed_clock: Option<Clock<T>>,
+}
+
+// the context's current time. each fixed step sees it as the time that step ends at, a
+// fixed_timestep after the one before, and it's put back to the frame's time afterwards
+pub type Clock<T> = fn(&mut T) -> &mut Duration;
+
+impl<T> Schedule<T> {
+    pub const MAX_FIXED_STEPS: u32 = 8;
+
+    pub fn new(fixed_timestep: Duration, sync_point: System<T>) -> Self {
+        Self {
+            systems: Vec::new(),
+            order: None,
+            sync_point,
+            fixed_timestep,
+            accumulator: Duration::new(0, 0),
+            fixed_clock: None,
+        }
+    }
+
+    pub fn set_fixed_clock(&mut self, clock: Clock<T>) {
+        self.fixed_clock = Some(clock);
+    }
+
+    pub fn add_system(
+        &mut self,
+        stage: Stage,
+        name: &'static str,
+        system: System<T>,
+    ) -> SystemConfig<'_, T> {
+        assert!(
+            self.systems
+                .iter()
+                .all(|descriptor| descriptor.name != name),
+            "system {} is already in the schedule",
+            name
+        );
+        self.order = None;
+        self.systems.push(SystemDescriptor {
+            name,
+            stage,
+            system,
+            before: Vec::new(),
+            after: Vec::new(),
+        });
+        SystemConfig {
+            descriptor: self.systems.last_mut().unwrap(),
+        }
+    }
+
+    pub fn run_frame(&mut self, context: &mut T, delta_time: Duration) {
+        self.accumulator =
+            (self.accumulator + delta_time).min(self.fixed_timestep * Self::MAX_FIXED_STEPS);
+        let frame_time = self.fixed_clock.map(|clock| *clock(context));
+        while self.accumulator >= self.fixed_timestep {
+            self.accumulator -= self.fixed_timestep;
+            if let (Some(clock), Some(frame_time)) = (self.fixed_clock, frame_time) {
+                *clock(context) = frame_time.saturating_sub(self.accumulator);
+            }
+            self.run_stage(Stage::FixedUpdate, context);
+        }
+        if let (Some(clock), Some(frame_time)) = (self.fixed_clock, frame_time) {
+            *clock(context) = frame_time;
+        }
+
+        self.run_stage(Stage::Update, context);
+        self.run_stage(Stage::PostUpdate, context);
+        (self.sync_point)(context);
+        self.run_stage(Stage::Render, context);
+    }
+
+    pub fn run_stage(&mut self, stage: Stage, context: &mut T) {
+        if self.order.is_none() {
+            self.order = Some(self.sort());
+        }
+
+        let systems = &self.systems;
+        self.order.as_ref().unwrap()[&stage]
+            .iter()
+            .for_each(|index| (systems[*index].system)(context));
+    }
+
+    fn index_of(&self, name: &str, stage: Stage) -> usize {
+        let index = self
+            .systems
+            .iter()
+            .position(|descriptor| descriptor.name == name)
+            .unwrap_or_else(|| panic!("system {} is not in the schedule", name));
+        assert!(
+            self.systems[index].stage == stage,
+            "system {} is not in stage {:?}, it can't be ordered against",
+            name,
+            stage
+        );
+        index
+    }
+
+    // kahn's algorithm, always picking the earliest added system that's ready so that
+    // unconstrained systems keep their insertion order
+    fn sort(&self) -> HashMap<Stage, Vec<usize>> {
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); self.systems.len()];
+        let mut in_degree = vec![0; self.systems.len()];
+
+        self.systems
+            .iter()
+            .enumerate()
+            .for_each(|(index, descriptor)| {
+                descriptor.before.iter().for_each(|name| {
+                    let other = self.index_of(name, descriptor.stage);
+                    edges[index].push(other);
+                    in_degree[other] += 1;
+                });
+                descriptor.after.iter().for_each(|name| {
+                    let other = self.index_of(name, descriptor.stage);
+                    edges[other].push(index);
+                    in_degree[index] += 1;
+                });
+            });
+
+        Stage::ALL
+            .iter()
+            .map(|stage| {
+                let mut remaining: Vec<usize> = (0..self.systems.len())
+                    .filter(|index| self.systems[*index].stage == *stage)
+                    .collect();
+                let mut sorted = Vec::with_capacity(remaining.len());
+
+                while !remaining.is_empty() {
+                    let next = remaining
+                        .iter()
+                        .position(|index| in_degree[*index] == 0)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "cycle in {:?} system ordering between {:?}",
+                                stage,
+                                remaining
+                                    .iter()
+                                    .map(|index| self.systems[*index].name)
+                                    .collect::<Vec<_>>()
+                            )
+                        });
+                    let index = remaining.remove(next);
+                    edges[index].iter().for_each(|other| in_degree[*other] -= 1);
+                    sorted.push(index);
+                }
+
+                (*stage, sorted)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Vec<&'static str>;
+
+    fn schedule() -> Schedule<Log> {
+        Schedule::new(Duration::from_millis(10), |log| log.push("sync"))
+    }
+
+    #[test]
+    fn unconstrained_systems_keep_insertion_order() {
+        let mut schedule = schedule();
+        schedule.add_system(Stage::Render, "render", |log| log.push("render"));
+        schedule.add_system(Stage::Update, "a", |log| log.push("a"));
+        schedule.add_system(Stage::PostUpdate, "post", |log| log.push("post"));
+        schedule.add_system(Stage::Update, "b", |log| log.push("b"));
+
+        let mut log = Log::new();
+        schedule.run_frame(&mut log, Duration::ZERO);
+        assert_eq!(log, vec!["a", "b", "post", "sync", "render"]);
+    }
+
+    #[test]
+    fn before_and_after_reorder_systems() {
+        let mut schedule = schedule();
+        schedule
+            .add_system(Stage::Update, "a", |log| log.push("a"))
+            .after("c");
+        schedule.add_system(Stage::Update, "b", |log| log.push("b"));
+        schedule
+            .add_system(Stage::Update, "c", |log| log.push("c"))
+            .before("b");
+
+        let mut log = Log::new();
+        schedule.run_stage(Stage::Update, &mut log);
+        assert_eq!(log, vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle in Update system ordering")]
+    fn cycles_panic() {
+        let mut schedule = schedule();
+        schedule
+            .add_system(Stage::Update, "a", |log| log.push("a"))
+            .before("b");
+        schedule
+            .add_system(Stage::Update, "b", |log| log.push("b"))
+            .before("a");
+        schedule.run_stage(Stage::Update, &mut Log::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "system a is not in stage Update")]
+    fn ordering_across_stages_panics() {
+        let mut schedule = schedule();
+        schedule.add_system(Stage::FixedUpdate, "a", |log| log.push("a"));
+        schedule
+            .add_system(Stage::Update, "b", |log| log.push("b"))
+            .after("a");
+        schedule.run_stage(Stage::Update, &mut Log::new());
+    }
+
+    #[test]
+    fn fixed_update_runs_once_per_timestep() {
+        let mut schedule = schedule();
+        schedule.add_system(Stage::FixedUpdate, "fixed", |log| log.push("fixed"));
+
+        let mut log = Log::new();
+        schedule.run_frame(&mut log, Duration::from_millis(25));
+        assert_eq!(log, vec!["fixed", "fixed", "sync"]);
+        // the leftover 5ms carries over
+        log.clear();
+        schedule.run_frame(&mut log, Duration::from_millis(6));
+        assert_eq!(log, vec!["fixed", "sync"]);
+    }
+
+    #[test]
+    fn a_frame_of_one_timestep_runs_one_fixed_update() {
+        let mut schedule = schedule();
+        schedule.add_system(Stage::FixedUpdate, "fixed", |log| log.push("fixed"));
+
+        let mut log = Log::new();
+        schedule.run_frame(&mut log, Duration::from_millis(10));
+        assert_eq!(log, vec!["fixed", "sync"]);
+    }
+
+    #[test]
+    fn fixed_steps_see_their_own_time() {
+        struct Clocked {
+            now: Duration,
+            seen: Vec<Duration>,
+        }
+        let mut schedule: Schedule<Clocked> = Schedule::new(Duration::from_millis(10), |clocked| {
+            let now = clocked.now;
+            clocked.seen.push(now)
+        });
+        schedule.set_fixed_clock(|clocked| &mut clocked.now);
+        schedule.add_system(Stage::FixedUpdate, "fixed", |clocked| {
+            let now = clocked.now;
+            clocked.seen.push(now)
+        });
+
+        let mut clocked = Clocked {
+            now: Duration::from_millis(100),
+            seen: Vec::new(),
+        };
+        schedule.run_frame(&mut clocked, Duration::from_millis(35));
+        assert_eq!(
+            clocked.seen,
+            [75, 85, 95, 100].map(Duration::from_millis).to_vec()
+        );
+        // the 5ms left over goes to the next frame
+        clocked.now = Duration::from_millis(110);
+        clocked.seen.clear();
+        schedule.run_frame(&mut clocked, Duration::from_millis(10));
+        assert_eq!(clocked.seen, [105, 110].map(Duration::from_millis).to_vec());
+    }
+
+    #[test]
+    fn fixed_update_steps_are_capped() {
+        let mut schedule = schedule();
+        schedule.add_system(Stage::FixedUpdate, "fixed", |log| log.push("fixed"));
+
+        let mut log = Log::new();
+        schedule.run_frame(&mut log, Duration::from_secs(10));
+        assert_eq!(log.len(), Schedule::<Log>::MAX_FIXED_STEPS as usize + 1);
+        // and the rest of the stall is dropped
+        log.clear();
+        schedule.run_frame(&mut log, Duration::from_millis(5));
+        assert_eq!(log, vec!["sync"]);
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::component::{self, Entity};
+use crate::game;
+use crate::gui;
+use crate::physics;
+use crate::transform::TransformSystem;
+use crate::world::World;
+
+// sent to save the world to, or restore it from, the snapshot file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotRequest {
+    Save,
+    Load,
+}
+
+// the parts of the world that change while playing. it's restored on top of a world spawned
+// from the same scene, matching entities up by NameComponent, so unnamed entities aren't saved.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub notes_collected: u32,
+    // where the player respawns, moved by checkpoints. older snapshots don't have it
+    #[serde(default)]
+    pub respawn_position: Option<(f32, f32)>,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub name: String,
+    #[serde(default)]
+    pub position: Option<PositionSnapshot>,
+    #[serde(default)]
+    pub physics: Option<PhysicsSnapshot>,
+    #[serde(default)]
+    pub is_collected: Option<bool>,
+    #[serde(default)]
+    pub moving_platform: Option<MovingPlatformSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    pub position: (f32, f32),
+    pub scale: (f32, f32),
+    pub rotation: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsSnapshot {
+    pub velocity: (f32, f32),
+    pub acceleration: (f32, f32),
+    // the frame clock restarts with the app, so this is kept relative to when it was saved
+    pub grounded_secs_ago: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MovingPlatformSnapshot {
+    pub elapsed_secs: f32,
+    pub prev_change: f32,
+}
+
+impl WorldSnapshot {
+    pub fn capture(world: &World, current_time: Duration) -> Self {
+        let entities = world
+            .query::<(
+                &component::NameComponent,
+                Option<&component::PositionComponent>,
+                Option<&physics::PhysicsComponent>,
+                Option<&component::CollectibleComponent>,
+                Option<&component::MovingPlatformComponent>,
+            )>()
+            .iter()
+            .map(
+                |(
+                    _,
+                    (name, position_component, physics_component, collectible, moving_platform),
+                )| {
+                    EntitySnapshot {
+                        name: name.name.clone(),
+                        position: position_component.map(|position_component| PositionSnapshot {
+                            position: position_component.position.into(),
+                            scale: position_component.scale.into(),
+                            rotation: position_component.rotation,
+                        }),
+                        physics: physics_component.map(|physics_component| PhysicsSnapshot {
+                            velocity: physics_component.velocity.into(),
+                            acceleration: physics_component.acceleration.into(),
+                            grounded_secs_ago: physics_component.last_grounded_time.map(
+                                |last_grounded_time| {
+                                    current_time
+                                        .saturating_sub(last_grounded_time)
+                                        .as_secs_f32()
+                                },
+                            ),
+                        }),
+                        is_collected: collectible.map(|collectible| collectible.is_collected),
+                        moving_platform: moving_platform.map(|moving_platform| {
+                            MovingPlatformSnapshot {
+                                elapsed_secs: moving_platform.elapsed_secs,
+                                prev_change: moving_platform.prev_change,
+                            }
+                        }),
+                    }
+                },
+            )
+            .collect();
+
+        let platformer_game_state = world.resource::<game::PlatformerGameState>();
+        Self {
+            notes_collected: platformer_game_state.notes_collected,
+            respawn_position: Some(platformer_game_state.character_init_position.into()),
+            entities,
+        }
+    }
+
+    // named entities that aren't in the snapshot had been despawned when it was taken (e.g.
+    // collected scrolls), so they're despawned here too
+    pub fn restore(&self, world: &mut World, current_time: Duration) -> Result<()> {
+        let named = Self::named(world);
+
+        if let Some(missing) = self
+            .entities
+            .iter()
+            .find(|entity_snapshot| !named.contains_key(&entity_snapshot.name))
+        {
+            return Err(anyhow!("no entity named {} in the world", missing.name));
+        }
+
+        self.apply_to(world, &named, current_time, |_| true);
+        Ok(())
+    }
+
+    // restores on top of a world spawned from an edited scene, where entities may have been
+    // added, renamed or removed. entities the scene no longer has are skipped, and only
+    // entities the scene had when the snapshot was taken can be despawned, so new ones stay
+    pub fn reapply(
+        &self,
+        world: &mut World,
+        current_time: Duration,
+        scene_names: &HashSet<String>,
+    ) {
+        let named = Self::named(world);
+        self.apply_to(world, &named, current_time, |name| {
+            scene_names.contains(name)
+        });
+    }
+
+    fn named(world: &World) -> HashMap<String, Entity> {
+        world
+            .query::<&component::NameComponent>()
+            .iter()
+            .map(|(entity, name)| (name.name.clone(), entity))
+            .collect()
+    }
+
+    fn apply_to(
+        &self,
+        world: &mut World,
+        named: &HashMap<String, Entity>,
+        current_time: Duration,
+        can_despawn: impl Fn(&str) -> bool,
+    ) {
+        named
+            .iter()
+            .filter(|(name, _)| {
+                can_despawn(name)
+                    && !self
+                        .entities
+                        .iter()
+                        .any(|entity_snapshot| &entity_snapshot.name == *name)
+            })
+            .for_each(|(_, entity)| TransformSystem::despawn_recursive(world, *entity));
+
+        self.entities.iter().for_each(|entity_snapshot| {
+            if let Some(entity) = named.get(&entity_snapshot.name) {
+                entity_snapshot.apply(world, *entity, current_time)
+            }
+        });
+
+        let mut platformer_game_state = world.resource_mut::<game::PlatformerGameState>();
+        platformer_game_state.notes_collected = self.notes_collected;
+        if let Some(respawn_position) = self.respawn_position {
+            platformer_game_state.character_init_position = respawn_position.into();
+        }
+        drop(platformer_game_state);
+        world.resource_mut::<gui::GuiInfo>().notes_collected = self.notes_collected;
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| anyhow!("failed to serialize snapshot: {}", e))
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self> {
+        ron::from_str(source).map_err(|e| anyhow!("invalid snapshot: {}", e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_ron()?)
+            .with_context(|| format!("failed to write snapshot {}", path.display()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read snapshot {}", path.display()))?;
+        Self::from_ron(&source).with_context(|| format!("in {}", path.display()))
+    }
+}
+
+impl EntitySnapshot {
+    fn apply(&self, world: &World, entity: Entity, current_time: Duration) {
+        if let (Some(snapshot), Some(mut position_component)) = (
+            &self.position,
+            world.get_mut::<component::PositionComponent>(entity),
+        ) {
+            position_component.position = snapshot.position.into();
+            position_component.scale = snapshot.scale.into();
+            position_component.rotation = snapshot.rotation;
+
+            if let Some(mut collider_box_component) =
+                world.get_mut::<physics::ColliderBoxComponent>(entity)
+            {
+                collider_box_component
+                    .bounding_box
+                    .update(position_component.position);
+                collider_box_component.bounding_box.rotation = position_component.rotation;
+            }
+        }
+
+        if let (Some(snapshot), Some(mut physics_component)) = (
+            &self.physics,
+            world.get_mut::<physics::PhysicsComponent>(entity),
+        ) {
+            physics_component.velocity = snapshot.velocity.into();
+            physics_component.acceleration = snapshot.acceleration.into();
+            physics_component.last_grounded_time = snapshot
+                .grounded_secs_ago
+                .map(|secs| current_time.saturating_sub(Duration::from_secs_f32(secs)));
+        }
+
+        if let (Some(is_collected), Some(mut collectible)) = (
+            self.is_collected,
+            world.get_mut::<component::CollectibleComponent>(entity),
+        ) {
+            collectible.is_collected = is_collected;
+        }
+
+        if let (Some(snapshot), Some(mut moving_platform)) = (
+            &self.moving_platform,
+            world.get_mut::<component::MovingPlatformComponent>(entity),
+        ) {
+            moving_platform.elapsed_secs = snapshot.elapsed_secs;
+            moving_platform.prev_change = snapshot.prev_change;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector2;
+
+    use super::*;
+    use crate::bundle;
+
+    // a player, a platform, a scroll and a note, as a level's scene spawns them
+    fn spawn_level(world: &mut World) {
+        world.insert_resource(game::PlatformerGameState::new(Vector2::new(0., 0.)));
+        world.insert_resource(gui::GuiInfo {
+            fps: 0,
+            notes_collected: 0,
+            popup_text: String::new(),
+            popup_type: gui::PopupType::SCROLL,
+        });
+        let named = |world: &mut World, name: &str| {
+            let entity = world.spawn();
+            world.insert(
+                entity,
+                component::NameComponent {
+                    name: name.to_string(),
+                },
+            );
+            entity
+        };
+
+        let player = named(world, "player");
+        let position =
+            component::PositionComponent::new(Vector2::new(0., 0.), Vector2::new(1., 2.));
+        world.insert(
+            player,
+            physics::ColliderBoxComponent {
+                bounding_box: bundle::bounding_box_for(&position),
+                filter: physics::CollisionFilter::all(),
+            },
+        );
+        world.insert(player, position);
+        world.insert(player, physics::PhysicsComponent::new());
+
+        let platform = named(world, "platform");
+        world.insert(
+            platform,
+            component::PositionComponent::new(Vector2::new(10., 0.), Vector2::new(4., 1.)),
+        );
+        world.insert(
+            platform,
+            component::MovingPlatformComponent {
+                amplitude: 2.,
+                period_secs: 4.,
+                original_position: Vector2::new(10., 0.),
+                horizontal: true,
+                prev_change: 0.,
+                elapsed_secs: 0.,
+            },
+        );
+
+        for name in ["scroll", "note"] {
+            let collectible = named(world, name);
+            world.insert(
+                collectible,
+                component::CollectibleComponent {
+                    is_collected: false,
+                    popup_text: String::new(),
+                },
+            );
+        }
+    }
+
+    fn named(world: &World, name: &str) -> Option<Entity> {
+        WorldSnapshot::named(world).get(name).copied()
+    }
+
+    #[test]
+    fn restoring_a_saved_world_gives_it_back() {
+        let mut world = World::new();
+        spawn_level(&mut world);
+        let player = named(&world, "player").unwrap();
+        {
+            let mut position = world
+                .get_mut::<component::PositionComponent>(player)
+                .unwrap();
+            position.position = Vector2::new(3., 5.);
+            position.rotation = 0.25;
+            let mut physics_component = world.get_mut::<physics::PhysicsComponent>(player).unwrap();
+            physics_component.velocity = Vector2::new(-1., 2.);
+            physics_component.acceleration = Vector2::new(0., -9.);
+            physics_component.last_grounded_time = Some(Duration::from_millis(9500));
+        }
+        world
+            .get_mut::<component::MovingPlatformComponent>(named(&world, "platform").unwrap())
+            .unwrap()
+            .elapsed_secs = 1.5;
+        world
+            .get_mut::<component::CollectibleComponent>(named(&world, "scroll").unwrap())
+            .unwrap()
+            .is_collected = true;
+        let note = named(&world, "note").unwrap();
+        world.remove_entity(note);
+        {
+            let mut platformer_game_state = world.resource_mut::<game::PlatformerGameState>();
+            platformer_game_state.notes_collected = 3;
+            platformer_game_state.character_init_position = Vector2::new(8., 1.);
+        }
+
+        let saved = WorldSnapshot::capture(&world, Duration::from_secs(10));
+        let loaded = WorldSnapshot::from_ron(&saved.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded, saved);
+
+        // a new session's clock starts over, so the player was grounded just as long ago
+        let mut restored = World::new();
+        spawn_level(&mut restored);
+        loaded
+            .restore(&mut restored, Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(
+            WorldSnapshot::capture(&restored, Duration::from_secs(2)),
+            saved
+        );
+
+        assert!(named(&restored, "note").is_none());
+        let player = named(&restored, "player").unwrap();
+        let collider_box = restored
+            .get::<physics::ColliderBoxComponent>(player)
+            .unwrap();
+        assert_eq!(collider_box.bounding_box.position, Vector2::new(3., 5.));
+        assert_eq!(collider_box.bounding_box.rotation, 0.25);
+        assert_eq!(
+            restored
+                .get::<physics::PhysicsComponent>(player)
+                .unwrap()
+                .last_grounded_time,
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(restored.resource::<gui::GuiInfo>().notes_collected, 3);
+    }
+
+    #[test]
+    fn restoring_needs_every_saved_entity() {
+        let mut world = World::new();
+        spawn_level(&mut world);
+        let saved = WorldSnapshot::capture(&world, Duration::ZERO);
+
+        let mut restored = World::new();
+        spawn_level(&mut restored);
+        let platform = named(&restored, "platform").unwrap();
+        restored.remove_entity(platform);
+        assert!(saved.restore(&mut restored, Duration::ZERO).is_err());
+    }
+}
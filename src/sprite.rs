@@ -2,11 +2,12 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use crate::atlas;
 use crate::component;
-use crate::component::EntityMap;
-use crate::context;
 use crate::texture;
+use crate::world::World;
 
+use anyhow::{ensure, Result};
 use cgmath::Vector2;
 use log::debug;
 // pub struct Sprite {
@@ -115,65 +116,59 @@ impl component::Component for SheetPositionComponent {
 pub struct SpriteSheetSystem {}
 
 impl SpriteSheetSystem {
-    pub fn update(
-        vertex_array_components: &mut EntityMap<component::VertexArrayComponent>,
-        sheet_position_components: &EntityMap<SheetPositionComponent>,
-    ) {
-        vertex_array_components
-            .iter_mut()
-            .zip(sheet_position_components.iter())
-            .for_each(
-                |((_, vertex_array_component), (_, sheet_position_component))| {
-                    if let (Some(vertex_array_component), Some(sheet_position_component)) =
-                        (vertex_array_component, sheet_position_component)
-                    {
-                        sheet_position_component
-                            .sprite_sheet
-                            .borrow()
-                            .adjust_tex_coords(
-                                vertex_array_component,
-                                sheet_position_component.sheet_position,
-                            )
-                    }
-                },
-            );
+    pub fn update(world: &World) {
+        world
+            .query::<(
+                &mut component::VertexArrayComponent,
+                &SheetPositionComponent,
+            )>()
+            .iter()
+            .for_each(|(_, (vertex_array_component, sheet_position_component))| {
+                sheet_position_component
+                    .sprite_sheet
+                    .borrow()
+                    .adjust_vertex_array(
+                        vertex_array_component,
+                        sheet_position_component.sheet_position,
+                    )
+            });
     }
 }
 
+// a frame of a sheet that isn't a grid, in pixels from the top left. rect is where it is on the
+// sheet image, and can be trimmed down to the part that isn't transparent: offset is where the
+// rect was in the untrimmed frame of source_size. the pivot is the point of the untrimmed frame
+// that stays put from frame to frame, like a character's feet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteFrame {
+    pub rect: atlas::Rect,
+    pub offset: (u32, u32),
+    pub source_size: (u32, u32),
+    pub pivot: Vector2<f32>,
+}
+
 pub struct SpriteSheet {
     sprite_width: u32,
     sprite_height: u32,
     pub dimensions: (u32, u32),
     texture: Arc<texture::Texture>,
+    // where on the texture the sheet is, for sheets packed into an atlas. texture coordinates
+    // stay relative to the sheet, the renderer maps them onto the texture
+    uv_rect: (Vector2<f32>, Vector2<f32>),
+    // empty for sheets that are a grid of sprite_width x sprite_height sprites. otherwise the
+    // sprite size is the box an entity's quad stands for, with the first frame's pivot in it
+    frames: Vec<SpriteFrame>,
 
     label: &'static str,
-    texture_bytes: &'static [u8],
-    normal_bytes: Option<&'static [u8]>,
-    manual_premultiply: bool,
 }
 
 impl SpriteSheet {
     pub fn new(
-        context: &context::Context,
         label: &'static str,
-        texture_bytes: &'static [u8],
-        normal_bytes: Option<&'static [u8]>,
+        texture: Arc<texture::Texture>,
         sprite_width: u32,
         sprite_height: u32,
-        manual_premultiply: bool,
     ) -> Self {
-        let texture = Arc::new(
-            crate::texture::Texture::from_bytes(
-                &context.device,
-                &context.queue,
-                texture_bytes,
-                normal_bytes,
-                label,
-                manual_premultiply,
-            )
-            .unwrap(),
-        );
-
         let dimensions = texture.dimensions;
 
         Self {
@@ -181,55 +176,304 @@ impl SpriteSheet {
             sprite_height,
             dimensions,
             texture,
+            uv_rect: (Vector2::new(0., 0.), Vector2::new(1., 1.)),
+            frames: Vec::new(),
+            label,
+        }
+    }
+
+    pub fn from_atlas(
+        label: &'static str,
+        page: Arc<texture::Texture>,
+        uv_rect: (Vector2<f32>, Vector2<f32>),
+        dimensions: (u32, u32),
+        sprite_width: u32,
+        sprite_height: u32,
+    ) -> Self {
+        Self {
+            sprite_width,
+            sprite_height,
+            dimensions,
+            texture: page,
+            uv_rect,
+            frames: Vec::new(),
             label,
-            texture_bytes,
-            normal_bytes,
-            manual_premultiply,
         }
     }
 
+    // the frames of a sheet that isn't a grid are in one long row
     pub fn get_position_by_index(&self, index: u32) -> Vector2<u32> {
+        if !self.frames.is_empty() {
+            return Vector2::new(index, 0);
+        }
         let num_sprites = self.dimensions.0 / self.sprite_width;
         Vector2::new(index % num_sprites, index / num_sprites)
     }
 
+    pub fn get_index_by_position(&self, position: Vector2<u32>) -> u32 {
+        if !self.frames.is_empty() {
+            return position.x;
+        }
+        let num_sprites = self.dimensions.0 / self.sprite_width;
+        position.y * num_sprites + position.x
+    }
+
+    pub fn sprite_size(&self) -> (u32, u32) {
+        (self.sprite_width, self.sprite_height)
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
     pub fn texture(&self) -> Arc<texture::Texture> {
         return self.texture.clone();
     }
 
-    pub fn resize(&mut self, context: &context::Context) {
-        self.texture = Arc::new(
-            crate::texture::Texture::from_bytes(
-                &context.device,
-                &context.queue,
-                self.texture_bytes,
-                self.normal_bytes,
-                self.label,
-                self.manual_premultiply,
+    // the sprite size stays the same, so a resized image just has more or fewer sprites
+    pub fn set_texture(&mut self, texture: Arc<texture::Texture>) {
+        self.dimensions = texture.dimensions;
+        self.texture = texture;
+        self.uv_rect = (Vector2::new(0., 0.), Vector2::new(1., 1.));
+    }
+
+    pub fn uv_rect(&self) -> (Vector2<f32>, Vector2<f32>) {
+        self.uv_rect
+    }
+
+    // for a sheet in an atlas that was packed again. its frames have to still fit on it
+    pub fn set_atlas_region(
+        &mut self,
+        page: Arc<texture::Texture>,
+        uv_rect: (Vector2<f32>, Vector2<f32>),
+        dimensions: (u32, u32),
+    ) -> Result<()> {
+        self.check_frames(&self.frames, dimensions)?;
+        self.texture = page;
+        self.uv_rect = uv_rect;
+        self.dimensions = dimensions;
+        Ok(())
+    }
+
+    pub fn set_frames(&mut self, frames: Vec<SpriteFrame>) -> Result<()> {
+        self.check_frames(&frames, self.dimensions)?;
+        self.frames = frames;
+        Ok(())
+    }
+
+    fn check_frames(&self, frames: &[SpriteFrame], dimensions: (u32, u32)) -> Result<()> {
+        frames.iter().enumerate().try_for_each(|(index, frame)| {
+            ensure!(
+                frame.rect.x + frame.rect.width <= dimensions.0
+                    && frame.rect.y + frame.rect.height <= dimensions.1,
+                "frame {} ({:?}) isn't inside sprite sheet {}",
+                index,
+                frame.rect,
+                self.label
+            );
+            ensure!(
+                frame.offset.0 + frame.rect.width <= frame.source_size.0
+                    && frame.offset.1 + frame.rect.height <= frame.source_size.1,
+                "frame {} of sprite sheet {} is bigger than its source size",
+                index,
+                self.label
+            );
+            Ok(())
+        })
+    }
+
+    pub fn frames(&self) -> &[SpriteFrame] {
+        &self.frames
+    }
+
+    fn frame(&self, sheet_position: Vector2<u32>) -> Option<&SpriteFrame> {
+        self.frames.get(sheet_position.x as usize)
+    }
+
+    // top left and bottom right corner of a frame's quad, in the coordinates of an entity's
+    // unit quad. frames are moved so their pivots line up, and mirrored around the middle of the
+    // quad when flipped, like whole sprites are
+    pub fn frame_quad(
+        &self,
+        sheet_position: Vector2<u32>,
+        is_flipped: bool,
+    ) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let frame = self.frame(sheet_position)?;
+        let reference_pivot = self.frames[0].pivot;
+        let to_quad = |x: f32, y: f32| {
+            Vector2::new(
+                (reference_pivot.x + x - frame.pivot.x) / self.sprite_width as f32 - 0.5,
+                0.5 - (reference_pivot.y + y - frame.pivot.y) / self.sprite_height as f32,
             )
-            .unwrap(),
+        };
+        let top_left = to_quad(frame.offset.0 as f32, frame.offset.1 as f32);
+        let bottom_right = to_quad(
+            (frame.offset.0 + frame.rect.width) as f32,
+            (frame.offset.1 + frame.rect.height) as f32,
+        );
+        if is_flipped {
+            Some((
+                Vector2::new(-bottom_right.x, top_left.y),
+                Vector2::new(-top_left.x, bottom_right.y),
+            ))
+        } else {
+            Some((top_left, bottom_right))
+        }
+    }
+
+    // top left corner and size of a sprite in texture coordinates
+    pub fn tex_rect(&self, sheet_position: Vector2<u32>) -> (Vector2<f32>, Vector2<f32>) {
+        if let Some(frame) = self.frame(sheet_position) {
+            return (
+                Vector2::new(
+                    frame.rect.x as f32 / self.dimensions.0 as f32,
+                    frame.rect.y as f32 / self.dimensions.1 as f32,
+                ),
+                Vector2::new(
+                    frame.rect.width as f32 / self.dimensions.0 as f32,
+                    frame.rect.height as f32 / self.dimensions.1 as f32,
+                ),
+            );
+        }
+        let step = Vector2::new(
+            self.sprite_width as f32 / self.dimensions.0 as f32,
+            self.sprite_height as f32 / self.dimensions.1 as f32,
+        );
+        (
+            Vector2::new(
+                sheet_position.x as f32 * step.x,
+                sheet_position.y as f32 * step.y,
+            ),
+            step,
         )
     }
 
-    pub fn adjust_tex_coords(
+    // the vertices of a frame's quad are placed the same way as its texture coordinates, by the
+    // whole texture coordinates, so they stay the same for sheets that are a grid
+    pub fn adjust_vertex_array(
         &self,
         vertex_array: &mut component::VertexArrayComponent,
         sheet_position: Vector2<u32>,
     ) {
-        let step_x = self.sprite_width as f32 / self.dimensions.0 as f32;
-        let step_y = self.sprite_height as f32 / self.dimensions.1 as f32;
-        let sheet_x = sheet_position.x as f32 * step_x;
-        let sheet_y = sheet_position.y as f32 * step_y;
+        let (sheet, step) = self.tex_rect(sheet_position);
+
+        if let Some((top_left, bottom_right)) =
+            self.frame_quad(sheet_position, vertex_array.is_flipped)
+        {
+            vertex_array.vertices = vertex_array
+                .whole_tex_coords
+                .iter()
+                .map(|whole_tex_coord| {
+                    cgmath::Vector2::new(
+                        top_left.x + whole_tex_coord.x * (bottom_right.x - top_left.x),
+                        top_left.y + whole_tex_coord.y * (bottom_right.y - top_left.y),
+                    )
+                })
+                .collect();
+        }
 
         vertex_array.tex_coords = vertex_array
             .whole_tex_coords
             .iter()
             .map(|whole_tex_coord| {
                 cgmath::Vector2::new(
-                    sheet_x + whole_tex_coord.x * step_x,
-                    sheet_y + whole_tex_coord.y * step_y,
+                    sheet.x + whole_tex_coord.x * step.x,
+                    sheet.y + whole_tex_coord.y * step.y,
                 )
             })
             .collect();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context;
+
+    fn frame(rect: (u32, u32, u32, u32), offset: (u32, u32), pivot: (f32, f32)) -> SpriteFrame {
+        SpriteFrame {
+            rect: atlas::Rect {
+                x: rect.0,
+                y: rect.1,
+                width: rect.2,
+                height: rect.3,
+            },
+            offset,
+            source_size: (16, 16),
+            pivot: Vector2::new(pivot.0, pivot.1),
+        }
+    }
+
+    #[test]
+    fn frames_are_placed_by_their_trim_and_pivot() {
+        let Some((device, queue)) = context::headless_device() else {
+            eprintln!("no graphics adapter, skipping");
+            return;
+        };
+        let image = image::DynamicImage::new_rgba8(64, 32);
+        let texture = Arc::new(
+            texture::Texture::from_image(&device, &queue, &image, None, None, false).unwrap(),
+        );
+        let mut sheet = SpriteSheet::new("hero", texture, 16, 16);
+
+        // a grid has no frames to place
+        assert_eq!(
+            sheet.tex_rect(Vector2::new(1, 1)),
+            (Vector2::new(0.25, 0.5), Vector2::new(0.25, 0.5))
+        );
+        assert_eq!(sheet.frame_quad(Vector2::new(1, 1), false), None);
+
+        sheet
+            .set_frames(vec![
+                frame((0, 0, 16, 16), (0, 0), (8., 16.)),
+                // trimmed to 8x10, 2 pixels from the left and 6 from the top
+                frame((16, 0, 8, 10), (2, 6), (8., 16.)),
+                // pivoted a quarter of the way in
+                frame((32, 0, 16, 16), (0, 0), (4., 16.)),
+            ])
+            .unwrap();
+
+        let untrimmed = Vector2::new(0, 0);
+        assert_eq!(
+            sheet.tex_rect(untrimmed),
+            (Vector2::new(0., 0.), Vector2::new(0.25, 0.5))
+        );
+        assert_eq!(
+            sheet.frame_quad(untrimmed, false),
+            Some((Vector2::new(-0.5, 0.5), Vector2::new(0.5, -0.5)))
+        );
+        assert_eq!(
+            sheet.frame_quad(untrimmed, true),
+            Some((Vector2::new(-0.5, 0.5), Vector2::new(0.5, -0.5)))
+        );
+
+        let trimmed = Vector2::new(1, 0);
+        assert_eq!(
+            sheet.tex_rect(trimmed),
+            (Vector2::new(0.25, 0.), Vector2::new(0.125, 0.3125))
+        );
+        assert_eq!(
+            sheet.frame_quad(trimmed, false),
+            Some((Vector2::new(-0.375, 0.125), Vector2::new(0.125, -0.5)))
+        );
+        assert_eq!(
+            sheet.frame_quad(trimmed, true),
+            Some((Vector2::new(-0.125, 0.125), Vector2::new(0.375, -0.5)))
+        );
+
+        // moved right so its pivot lines up with the first frame's
+        let pivoted = Vector2::new(2, 0);
+        assert_eq!(
+            sheet.tex_rect(pivoted),
+            (Vector2::new(0.5, 0.), Vector2::new(0.25, 0.5))
+        );
+        assert_eq!(
+            sheet.frame_quad(pivoted, false),
+            Some((Vector2::new(-0.25, 0.5), Vector2::new(0.75, -0.5)))
+        );
+        assert_eq!(
+            sheet.frame_quad(pivoted, true),
+            Some((Vector2::new(-0.75, 0.5), Vector2::new(0.25, -0.5)))
+        );
+    }
+}
@@ -1,70 +1,68 @@
 use crate::animation;
+use crate::aseprite;
+use crate::asset;
 use crate::camera;
 use crate::component;
-use crate::component::EntityMap;
 use crate::context;
+use crate::event::{EngineEvent, EventBus, EventReader};
 use crate::game;
 use crate::gui;
 use crate::input;
 use crate::physics;
 use crate::physics::ColliderBoxComponent;
 use crate::render_system;
+use crate::scene;
+use crate::schedule::{Schedule, Stage};
+use crate::snapshot;
 use crate::sprite;
 use crate::texture;
+use crate::texture_registry;
+use crate::tilemap;
+use crate::transform;
 use crate::uniform;
 use crate::wgsl_preprocessor;
+use crate::world;
+use crate::SHOULD_EXIT;
 
-use lazy_static::lazy_static;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use anyhow::{ensure, Context, Result};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 
 use winit::window::Window;
 
-lazy_static! {
-    static ref INTRO_SIGNPOST: &'static str = include_str!("./text/signpost.txt");
-    static ref FIRST_SCROLL: &'static str = include_str!("./text/first.txt");
-    static ref SECOND_SCROLL: &'static str = include_str!("./text/second.txt");
-    static ref THIRD_SCROLL: &'static str = include_str!("./text/third.txt");
-    static ref FOURTH_SCROLL: &'static str = include_str!("./text/fourth.txt");
-}
 pub struct State<'a> {
     pub context: context::Context<'a>,
     pub size: winit::dpi::PhysicalSize<u32>,
     pub window: Arc<Window>,
     pub wgsl_preprocessor: wgsl_preprocessor::WgslPreprocessor,
     pub gui: gui::Gui,
-    pub gui_info: gui::GuiInfo,
-    pub sprite_sheets: Vec<Rc<RefCell<sprite::SpriteSheet>>>,
-    pub camera: camera::OrthographicCamera,
-    pub world_uniform: uniform::WorldUniform,
-    // components
-    pub position_components: component::EntityMap<component::PositionComponent>,
-    pub vertex_array_components: component::EntityMap<component::VertexArrayComponent>, // camera: camera::Camera,
-    pub sprite_animation_controller_components:
-        component::EntityMap<animation::SpriteAnimationControllerComponent>,
-    pub sheet_position_components: component::EntityMap<sprite::SheetPositionComponent>,
-    pub character_state_components: component::EntityMap<component::CharacterStateComponent>,
-    pub collider_box_components: component::EntityMap<physics::ColliderBoxComponent>,
-    pub light_components: component::EntityMap<uniform::LightComponent>,
-    pub metadata_components: component::EntityMap<component::MetadataComponent>,
-    pub physics_components: component::EntityMap<physics::PhysicsComponent>,
-    pub parallax_components: component::EntityMap<component::ParallaxComponent>,
-    pub collectible_components: component::EntityMap<component::CollectibleComponent>,
-    pub sign_components: component::EntityMap<component::SignComponent>,
-    pub moving_platform_components: component::EntityMap<component::MovingPlatformComponent>,
+    pub asset_server: asset::AssetServer,
+    pub texture_registry: texture_registry::TextureRegistry,
+    character_sprite_sheet: asset::Handle<sprite::SpriteSheet>,
+    pub world: world::World,
+    pub event_bus: EventBus<State<'a>>,
+    pub player: Option<component::Entity>,
+    snapshot_reader: EventReader<snapshot::SnapshotRequest>,
+    paint_reader: EventReader<tilemap::PaintGround>,
+    // the level being played, the demo scene until a level exit leads somewhere else
+    scene_path: String,
+    level_exit_reader: EventReader<game::LevelExited>,
+    // names in the scene as it was last spawned, to tell its entities apart from ones an
+    // edit adds when the scene is reloaded
+    scene_names: HashSet<String>,
     // entities: Vec<component::Entity>,
 
     // systems
-    pub input_handler: input::InputHandler,
     pub render_system: render_system::RenderSystem,
     pub physics_system: physics::PhysicsSystem,
+    pub render_options: render_system::RenderOptions,
 
-    // game
-    pub platformer_game_state: game::PlatformerGameState,
-    pub game_mode: game::GameMode,
+    // frame timing, set by the app before the schedule runs
+    pub current_time: Duration,
+    pub delta_time: Duration,
 }
 
 impl<'a> State<'a> {
@@ -74,22 +72,49 @@ impl<'a> State<'a> {
     pub const FIXED_UPDATE_DURATION: Duration =
         Duration::new(0, 1000000000 / Self::FIXED_UPDATES_PER_SECOND);
 
-    pub async fn new(window: Arc<Window>) -> State<'a> {
+    const DEMO_SCENE_PATH: &'static str = "assets/scenes/demo.ron";
+    const CHARACTER_INIT_POSITION: cgmath::Vector2<f32> = cgmath::Vector2 { x: 82., y: 132. };
+    const CHARACTER_ANIMATIONS_PATH: &'static str = "assets/mira.json";
+
+    // texture.wgsl isn't among them, it's generated by the texture registry
+    const SHADERS: [&'static str; 11] = [
+        "src/shaders/compute.wgsl",
+        "src/shaders/hover.wgsl",
+        "src/shaders/light.wgsl",
+        "src/shaders/model_vertex.wgsl",
+        "src/shaders/outline.wgsl",
+        "src/shaders/post_popup.wgsl",
+        "src/shaders/post_standard.wgsl",
+        "src/shaders/post.wgsl",
+        "src/shaders/standard.wgsl",
+        "src/shaders/uniform.wgsl",
+        "src/shaders/wireframe.wgsl",
+    ];
+
+    pub async fn new(window: Arc<Window>) -> Result<State<'a>> {
         let size = window.inner_size();
         let context: context::Context<'a> = context::Context::new(window.clone()).await;
 
+        let mut asset_server = asset::AssetServer::new(".");
+        Self::embed_assets(&mut asset_server);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err(e) = asset_server.watch(&["assets", "src/shaders"]) {
+            log::warn!("hot reloading is off: {:#}", e);
+        }
+
         let gui = gui::Gui::new(
             &context.device,
             context.config.format.add_srgb_suffix(),
             None,
             1,
             window.clone(),
-        );
+            &mut asset_server,
+        )?;
 
         let gui_info = gui::GuiInfo {
             fps: 0,
             notes_collected: 0,
-            popup_text: "",
+            popup_text: String::new(),
             popup_type: gui::PopupType::SCROLL,
         };
 
@@ -102,25 +127,46 @@ impl<'a> State<'a> {
         //     true,
         // )));
 
-        let character_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
-            &context,
-            "character",
-            include_bytes!("../assets/mira.png"),
-            None,
-            80,
-            64,
-            true,
-        )));
+        let character_animations = asset_server.load_text(Self::CHARACTER_ANIMATIONS_PATH)?;
+        let character_animations =
+            aseprite::AsepriteSheet::from_json(&asset_server.get(character_animations))
+                .with_context(|| format!("in {}", Self::CHARACTER_ANIMATIONS_PATH))?;
 
-        let scroll_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
+        // sprite sheets are registered with the renderer in the order they're loaded in. the
+        // small ones share an atlas, the parallax layers are big enough to get their own texture
+        let sprite_atlas = asset_server.load_atlas(
             &context,
-            "scroll",
-            include_bytes!("../assets/scroll.png"),
-            None,
-            16,
-            16,
+            "sprites",
+            &[
+                asset::AtlasSheet {
+                    label: "character",
+                    path: "assets/mira.png",
+                    sprite_size: character_animations.sprite_size,
+                },
+                asset::AtlasSheet {
+                    label: "scroll",
+                    path: "assets/scroll.png",
+                    sprite_size: (16, 16),
+                },
+                asset::AtlasSheet {
+                    label: "signpost",
+                    path: "assets/signpost.png",
+                    sprite_size: (32, 32),
+                },
+                asset::AtlasSheet {
+                    label: "terrain",
+                    path: "assets/tileset.png",
+                    sprite_size: (32, 32),
+                },
+            ],
             true,
-        )));
+        )?;
+        let character_sprite_sheet = sprite_atlas[0];
+        // so mira.png can be exported trimmed
+        asset_server
+            .get(character_sprite_sheet)
+            .borrow_mut()
+            .set_frames(character_animations.sprite_frames())?;
 
         // let minotaur_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
         //     &context,
@@ -140,596 +186,161 @@ impl<'a> State<'a> {
         //     true,
         // )));
 
-        let parallax_1_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
+        asset_server.load_sprite_sheet(
             &context,
             "px1",
-            include_bytes!("../assets/bg/P1.png"),
+            "assets/bg/P1.png",
             None,
-            576,
-            324,
+            (576, 324),
             true,
-        )));
+        )?;
 
-        let parallax_2_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
+        asset_server.load_sprite_sheet(
             &context,
             "px2",
-            include_bytes!("../assets/bg/P2.png"),
+            "assets/bg/P2.png",
             None,
-            576,
-            324,
+            (576, 324),
             true,
-        )));
+        )?;
 
-        let parallax_3_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
+        asset_server.load_sprite_sheet(
             &context,
             "px3",
-            include_bytes!("../assets/bg/P3.png"),
+            "assets/bg/P3.png",
             None,
-            576,
-            324,
+            (576, 324),
             true,
-        )));
+        )?;
 
-        let parallax_4_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
+        asset_server.load_sprite_sheet(
             &context,
             "px4",
-            include_bytes!("../assets/bg/P4.png"),
-            None,
-            576,
-            324,
-            true,
-        )));
-
-        let signpost_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
-            &context,
-            "signpost",
-            include_bytes!("../assets/signpost.png"),
+            "assets/bg/P4.png",
             None,
-            32,
-            32,
+            (576, 324),
             true,
-        )));
-
-        let terrain_sprite_sheet = Rc::new(RefCell::new(sprite::SpriteSheet::new(
-            &context,
-            "terrain",
-            include_bytes!("../assets/tileset.png"),
-            None,
-            32,
-            32,
-            true,
-        )));
-
-        let sprite_sheets = vec![
-            character_sprite_sheet.clone(),
-            scroll_sprite_sheet.clone(),
-            parallax_1_sprite_sheet.clone(),
-            parallax_2_sprite_sheet.clone(),
-            parallax_3_sprite_sheet.clone(),
-            parallax_4_sprite_sheet.clone(),
-            signpost_sprite_sheet.clone(),
-            terrain_sprite_sheet.clone(),
-        ];
+        )?;
 
         // declare shader code for preprocessing
-        let mut shader_code = HashMap::new();
-        shader_code.insert("compute.wgsl", include_str!("./shaders/compute.wgsl"));
-        shader_code.insert("hover.wgsl", include_str!("./shaders/hover.wgsl"));
-        shader_code.insert("light.wgsl", include_str!("./shaders/light.wgsl"));
-        shader_code.insert(
-            "model_vertex.wgsl",
-            include_str!("./shaders/model_vertex.wgsl"),
-        );
-        shader_code.insert("outline.wgsl", include_str!("./shaders/outline.wgsl"));
-        shader_code.insert("post_popup.wgsl", include_str!("./shaders/post_popup.wgsl"));
-        shader_code.insert(
-            "post_standard.wgsl",
-            include_str!("./shaders/post_standard.wgsl"),
-        );
-        shader_code.insert("post.wgsl", include_str!("./shaders/post.wgsl"));
-        shader_code.insert("standard.wgsl", include_str!("./shaders/standard.wgsl"));
-        shader_code.insert("texture.wgsl", include_str!("./shaders/texture.wgsl"));
-        shader_code.insert("uniform.wgsl", include_str!("./shaders/uniform.wgsl"));
-        shader_code.insert("wireframe.wgsl", include_str!("./shaders/wireframe.wgsl"));
-
-        let wgsl_preprocessor = wgsl_preprocessor::WgslPreprocessor::new(shader_code);
+        let shaders = Self::SHADERS
+            .iter()
+            .map(|path| {
+                let shader = asset_server.load_shader(path)?;
+                Ok(asset_server.get(shader))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let texture_registry = Self::register_textures(&asset_server, &context)?;
+        let wgsl_preprocessor = Self::preprocess_shaders(&shaders, &texture_registry);
 
         let camera = camera::OrthographicCamera::new(size.width, size.height, 0.1, 100.0);
 
         let mut world_uniform = uniform::WorldUniform::new();
         world_uniform.resize(size.width, size.height);
 
-        let position_components = EntityMap::new();
-        let vertex_array_components = EntityMap::new();
-        let sprite_animation_controller_components = EntityMap::new();
-        let sheet_position_components = EntityMap::new();
-        let character_state_components = EntityMap::new();
-        let collider_box_components = EntityMap::new();
-        let light_components = EntityMap::new();
-        let metadata_components = EntityMap::new();
-        let physics_components = EntityMap::new();
-        let parallax_components = EntityMap::new();
-        let collectible_components = EntityMap::new();
-        let sign_components = EntityMap::new();
-        let moving_platform_components = EntityMap::new();
-        // let entities = position_components
-        //     .keys()
-        //     .collect::<Vec<component::Entity>>();
-        let input_handler = input::InputHandler::new();
-
-        let textures = sprite_sheets
-            .iter()
-            .map(|sprite_sheet| sprite_sheet.borrow().texture())
-            .collect::<Vec<Arc<texture::Texture>>>();
+        let mut world = world::World::new();
+        world.register::<component::PositionComponent>();
+        world.register::<component::VertexArrayComponent>();
+        world.register::<animation::SpriteAnimationControllerComponent>();
+        world.register::<sprite::SheetPositionComponent>();
+        world.register::<component::CharacterStateComponent>();
+        world.register::<physics::ColliderBoxComponent>();
+        world.register::<uniform::LightComponent>();
+        world.register::<component::MetadataComponent>();
+        world.register::<physics::PhysicsComponent>();
+        world.register::<component::ParallaxComponent>();
+        world.register::<component::CollectibleComponent>();
+        world.register::<component::SignComponent>();
+        world.register::<physics::TriggerComponent>();
+        world.register::<component::CheckpointComponent>();
+        world.register::<component::KillZoneComponent>();
+        world.register::<component::LevelExitComponent>();
+        world.register::<component::MovingPlatformComponent>();
+        world.register::<component::LocalPositionComponent>();
+        world.register::<component::ParentComponent>();
+        world.register::<component::ChildrenComponent>();
+        world.register::<component::NameComponent>();
+        world.register::<tilemap::TilemapComponent>();
+        world.add_events::<game::DialogueFinished>();
+        world.add_events::<game::LevelExited>();
+        world.add_events::<snapshot::SnapshotRequest>();
+        world.add_events::<tilemap::PaintGround>();
+        world.add_events::<physics::TriggerEvent>();
+
+        let mut event_bus = EventBus::default();
+        event_bus.subscribe(Self::show_collected_popup);
 
         let render_system =
-            render_system::RenderSystem::new(&textures, &context, &wgsl_preprocessor);
+            render_system::RenderSystem::new(&texture_registry, &context, &wgsl_preprocessor);
 
         let physics_system = physics::PhysicsSystem::new(Self::FIXED_UPDATE_DURATION);
 
-        let platformer_game = game::PlatformerGameState::new(cgmath::Vector2::new(82., 132.));
+        let render_options = render_system::RenderOptions {
+            finalize_to_stencil: false,
+            render_outline: false,
+            render_wireframe: false,
+            render_lights: false,
+        };
+
+        let platformer_game = game::PlatformerGameState::new(Self::CHARACTER_INIT_POSITION);
+
+        // engine singletons, systems borrow these from the world instead of the state
+        world.insert_resource(input::InputHandler::new());
+        world.insert_resource(camera);
+        world.insert_resource(world_uniform);
+        world.insert_resource(gui_info);
+        world.insert_resource(platformer_game);
+        world.insert_resource(game::GameMode::STANDARD);
 
-        Self {
+        Ok(Self {
             window,
             context,
             size,
             wgsl_preprocessor,
-            position_components,
-            sprite_sheets,
-            camera,
+            asset_server,
+            texture_registry,
+            character_sprite_sheet,
             gui,
-            gui_info,
-            world_uniform,
-            vertex_array_components,
-            sprite_animation_controller_components,
-            sheet_position_components,
-            character_state_components,
-            collider_box_components, // entities,
-            light_components,
-            metadata_components,
-            physics_components,
-            parallax_components,
-            collectible_components,
-            moving_platform_components,
-            sign_components,
-            input_handler,
+            world,
+            event_bus,
+            player: None,
+            snapshot_reader: EventReader::default(),
+            paint_reader: EventReader::default(),
+            scene_path: Self::DEMO_SCENE_PATH.to_string(),
+            level_exit_reader: EventReader::default(),
+            scene_names: HashSet::new(),
             render_system,
             physics_system,
-            platformer_game_state: platformer_game,
-            game_mode: game::GameMode::STANDARD,
-        }
+            render_options,
+            current_time: Duration::new(0, 0),
+            delta_time: Duration::new(0, 0),
+        })
     }
 
-    pub fn init(&mut self) -> component::Entity {
-        let parallax_scale = cgmath::Vector2 {
-            x: 320. / 576.,
-            y: 180. / 324.,
-        };
-
-        #[allow(unused)]
-        let bg1 = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 / 2.0,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32 / 2.0,
-                ),
-                scale: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32,
-                ),
-            };
-            let layer = 1;
-
-            let vertex_array_component: component::VertexArrayComponent =
-                component::VertexArrayComponent::textured_quad_with_coords(
-                    2,
-                    component::VertexArrayComponent::BACKGROUND_Z * layer as f32,
-                    parallax_scale,
-                );
-
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            let parallax_component = component::ParallaxComponent {
-                move_speed: 5.,
-                layer,
-            };
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                None,
-                Some(metadata_component),
-                Some(parallax_component),
-                None,
-                None,
-                None,
-            )
-        };
-
-        #[allow(unused)]
-        let bg2 = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 / 2.0,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32 / 2.0,
-                ),
-                scale: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32,
-                ),
-            };
-            let layer = 2;
-
-            let vertex_array_component: component::VertexArrayComponent =
-                component::VertexArrayComponent::textured_quad_with_coords(
-                    3,
-                    component::VertexArrayComponent::BACKGROUND_Z * layer as f32,
-                    parallax_scale,
-                );
-
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            let parallax_component = component::ParallaxComponent {
-                move_speed: 3.,
-                layer,
-            };
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                None,
-                Some(metadata_component),
-                Some(parallax_component),
-                None,
-                None,
-                None,
+    pub fn init(&mut self) -> Result<()> {
+        let scene = self.scene()?;
+        scene
+            .spawn(
+                &mut self.world,
+                &self.asset_server.all::<sprite::SpriteSheet>(),
             )
-        };
-
-        #[allow(unused)]
-        let bg3 = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 / 2.0,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32 / 2.0,
-                ),
-                scale: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32,
-                ),
-            };
-            let layer = 3;
-
-            let vertex_array_component: component::VertexArrayComponent =
-                component::VertexArrayComponent::textured_quad_with_coords(
-                    4,
-                    component::VertexArrayComponent::BACKGROUND_Z * layer as f32,
-                    parallax_scale,
-                );
-
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            let parallax_component = component::ParallaxComponent {
-                move_speed: 1.,
-                layer,
-            };
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                None,
-                Some(metadata_component),
-                Some(parallax_component),
-                None,
-                None,
-                None,
-            )
-        };
-
-        #[allow(unused)]
-        let bg4 = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 / 2.0,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32 / 2.0,
-                ),
-                scale: cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32,
-                    uniform::WorldUniform::WORLD_SCREEN_HEIGHT as f32,
-                ),
-            };
-            let layer = 4;
-
-            let vertex_array_component: component::VertexArrayComponent =
-                component::VertexArrayComponent::textured_quad_with_coords(
-                    5,
-                    component::VertexArrayComponent::BACKGROUND_Z * layer as f32,
-                    parallax_scale,
-                );
-
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            let parallax_component = component::ParallaxComponent {
-                move_speed: 0.,
-                layer,
-            };
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                None,
-                Some(metadata_component),
-                Some(parallax_component),
-                None,
-                None,
-                None,
-            )
-        };
-
-        #[allow(unused)]
-        let tiles = {
-            let mut create_tile = |position, scale, moving_platform_component| {
-                let position_component = component::PositionComponent { position, scale };
-
-                let texture_index = 7;
-                let vertex_array_component: component::VertexArrayComponent =
-                    component::VertexArrayComponent::textured_quad(
-                        texture_index,
-                        component::VertexArrayComponent::FOREGROUND_Z,
-                    );
-
-                let collider_box_component = ColliderBoxComponent {
-                    bounding_box: physics::BoundingBox {
-                        position: position_component.position,
-                        bottom_left_offset: -1.0 * position_component.scale / 2.0,
-                        top_right_offset: position_component.scale / 2.0,
-                    },
-                };
-
-                let metadata_component = component::MetadataComponent::new(false, false);
-
-                let sheet_position_component = sprite::SheetPositionComponent {
-                    sprite_sheet: self.sprite_sheets[texture_index as usize].clone(),
-                    sheet_position: cgmath::Vector2::new(1, 0),
-                };
-
-                self.add_entity(
-                    Some(position_component),
-                    Some(vertex_array_component),
-                    None,
-                    Some(sheet_position_component),
-                    None,
-                    Some(collider_box_component),
-                    None,
-                    Some(metadata_component),
-                    None,
-                    None,
-                    None,
-                    moving_platform_component,
-                )
-            };
-
-            let main_ground = create_tile(
-                cgmath::Vector2::new(uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 / 2.0, 50.),
-                cgmath::Vector2::new(uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32, 100.),
-                None,
-            );
-
-            let platform_scale = cgmath::Vector2::new(100., 20.);
-
-            let platform_1 = create_tile(
-                cgmath::Vector2::new(uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 60., 150.),
-                platform_scale,
-                None,
-            );
-
-            let platform_2 = create_tile(
-                cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 160.,
-                    200.,
-                ),
-                platform_scale,
-                None,
-            );
-
-            let platform_3 = create_tile(
-                cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 260.,
-                    250.,
-                ),
-                platform_scale,
-                None,
-            );
-
-            let second_scroll_platform = create_tile(
-                cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 600.,
-                    220.,
-                ),
-                platform_scale * 5.,
-                None,
-            );
-
-            let platform_4 = create_tile(
-                cgmath::Vector2::new(uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 50., 300.),
-                platform_scale,
-                None,
-            );
-
-            let platform_5 = create_tile(
-                cgmath::Vector2::new(uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 - 80., 375.),
-                platform_scale,
-                None,
-            );
-
-            let platform_6 = create_tile(
-                cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 - 210.,
-                    450.,
-                ),
-                platform_scale,
-                None,
-            );
-
-            let moving_platform_h_pos = cgmath::Vector2::new(
-                uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 500.,
-                450.,
-            );
-            let moving_platform_h = create_tile(
-                moving_platform_h_pos,
-                platform_scale,
-                Some(component::MovingPlatformComponent {
-                    amplitude: 550.,
-                    period_secs: 8.,
-                    original_position: moving_platform_h_pos,
-                    horizontal: true,
-                    prev_change: 0.,
-                }),
-            );
-
-            let second_scroll_platform = create_tile(
-                cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 1350.,
-                    450.,
-                ),
-                platform_scale * 3.,
-                None,
-            );
-
-            let moving_platform_v_pos = cgmath::Vector2::new(
-                uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 1600.,
-                250.,
-            );
-
-            let moving_platform_v = create_tile(
-                moving_platform_v_pos,
-                platform_scale,
-                Some(component::MovingPlatformComponent {
-                    amplitude: 200.,
-                    period_secs: 5.,
-                    original_position: moving_platform_v_pos,
-                    horizontal: false,
-                    prev_change: 0.,
-                }),
-            );
-
-            let third_scroll_platform = create_tile(
-                cgmath::Vector2::new(
-                    uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 2120.,
-                    50.,
-                ),
-                cgmath::Vector2::new(uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32, 100.),
-                None,
-            );
-        };
-
-        #[allow(unused)]
-        let light = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(100., 200.),
-                scale: cgmath::Vector2::new(30., 30.),
-            };
-
-            let vertex_array_component: component::VertexArrayComponent =
-                component::VertexArrayComponent::circle(
-                    component::VertexArrayComponent::FOREGROUND_Z,
-                );
-
-            let light_component = uniform::LightComponent {
-                linear_dropoff: 0.0007,
-                quadratic_dropoff: 0.0001,
-                ambient_strength: 10.,
-                diffuse_strength: 15.,
-                color: cgmath::Vector3 {
-                    x: 1.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
-            };
-
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                Some(light_component),
-                Some(metadata_component),
-                None,
-                None,
-                None,
-                None,
-            )
-        };
-
-        #[allow(unused)]
-        let light2 = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(500., 200.),
-                scale: cgmath::Vector2::new(30., 30.),
-            };
-
-            let vertex_array_component: component::VertexArrayComponent =
-                component::VertexArrayComponent::circle(
-                    component::VertexArrayComponent::FOREGROUND_Z,
-                );
-
-            let light_component = uniform::LightComponent {
-                linear_dropoff: 0.0007,
-                quadratic_dropoff: 0.0001,
-                ambient_strength: 10.,
-                diffuse_strength: 15.,
-                color: cgmath::Vector3 {
-                    x: 1.0,
-                    y: 1.0,
-                    z: 0.0,
-                },
-            };
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                Some(light_component),
-                Some(metadata_component),
-                None,
-                None,
-                None,
-                None,
-            )
-        };
+            .with_context(|| format!("failed to spawn {}", self.scene_path))?;
+        self.scene_names = scene
+            .entities
+            .iter()
+            .filter_map(|scene_entity| scene_entity.name.clone())
+            .collect();
 
         // entity for player
         let character = {
-            let position_component = component::PositionComponent {
-                position: self.platformer_game_state.character_init_position,
-                scale: cgmath::Vector2::new(100., 100.),
-            };
+            let position_component = component::PositionComponent::new(
+                self.world
+                    .resource::<game::PlatformerGameState>()
+                    .character_init_position,
+                cgmath::Vector2::new(100., 100.),
+            );
 
-            let texture_index = 0; // warrior
+            let texture_index = self.character_sprite_sheet.index();
 
             let mut vertex_array_component = component::VertexArrayComponent::textured_quad(
                 texture_index,
@@ -738,63 +349,22 @@ impl<'a> State<'a> {
 
             vertex_array_component.is_flipped = true;
 
-            let sprite_animation_idle = animation::SpriteAnimation {
-                animation_index: 0,
-                sprite_count: 5,
-                start_index: 0,
-                per_sprite_duration: Duration::new(0, 125000000),
-                current_elapsed_time: Duration::new(0, 0),
-            };
-            let sprite_animation_run = animation::SpriteAnimation {
-                animation_index: 0,
-                sprite_count: 8,
-                start_index: 20,
-                per_sprite_duration: Duration::new(0, 125000000),
-                current_elapsed_time: Duration::new(0, 0),
-            };
-            // let sprite_animation_attack = animation::SpriteAnimation {
-            //     animation_index: 0,
-            //     sprite_count: 10,
-            //     start_index: 30,
-            //     per_sprite_duration: Duration::new(0, 125000000),
-            //     current_elapsed_time: Duration::new(0, 0),
-            // };
-            let sprite_animation_jump_up = animation::SpriteAnimation {
-                animation_index: 0,
-                sprite_count: 4,
-                start_index: 30,
-                per_sprite_duration: Duration::new(0, 125000000),
-                current_elapsed_time: Duration::new(0, 0),
-            };
-            let sprite_animation_jump_down = animation::SpriteAnimation {
-                animation_index: 0,
-                sprite_count: 4,
-                start_index: 40,
-                per_sprite_duration: Duration::new(0, 125000000),
-                current_elapsed_time: Duration::new(0, 0),
-            };
-
-            let mut sprite_animation_controller =
-                animation::SpriteAnimationControllerComponent::new();
-            sprite_animation_controller
-                .animation_map
-                .insert(component::CharacterState::IDLE, sprite_animation_idle);
-            sprite_animation_controller
-                .animation_map
-                .insert(component::CharacterState::MOVE, sprite_animation_run);
-            sprite_animation_controller
-                .animation_map
-                .insert(component::CharacterState::JUMPUP, sprite_animation_jump_up);
-            sprite_animation_controller.animation_map.insert(
-                component::CharacterState::JUMPDOWN,
-                sprite_animation_jump_down,
-            );
-            // sprite_animation_controller
-            //     .animation_map
-            //     .insert(component::CharacterState::ATTACK, sprite_animation_attack);
+            let character_sprite_sheet = self.asset_server.get(self.character_sprite_sheet);
+            let sprite_animation_controller = self
+                .character_animations()?
+                .animation_controller(
+                    &character_sprite_sheet.borrow(),
+                    &[
+                        (component::CharacterState::IDLE, "idle"),
+                        (component::CharacterState::MOVE, "run"),
+                        (component::CharacterState::JUMPUP, "jump_up"),
+                        (component::CharacterState::JUMPDOWN, "jump_down"),
+                    ],
+                )
+                .with_context(|| format!("in {}", Self::CHARACTER_ANIMATIONS_PATH))?;
 
             let sheet_position_component = sprite::SheetPositionComponent {
-                sprite_sheet: self.sprite_sheets[texture_index as usize].clone(),
+                sprite_sheet: character_sprite_sheet,
                 sheet_position: cgmath::Vector2::new(0, 0),
             };
 
@@ -807,149 +377,30 @@ impl<'a> State<'a> {
                     position: position_component.position,
                     bottom_left_offset: cgmath::Vector2 { x: -12.5, y: -50. },
                     top_right_offset: cgmath::Vector2 { x: 12.5, y: 25. },
+                    rotation: 0.,
+                    pivot: cgmath::Vector2::new(0., 0.),
                 },
-            };
-
-            let metadata_component = component::MetadataComponent::new(true, true);
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                Some(sprite_animation_controller),
-                Some(sheet_position_component),
-                Some(character_state_component),
-                Some(collider_box_component),
-                None,
-                Some(metadata_component),
-                None,
-                None,
-                None,
-                None,
-            )
-        };
-
-        #[allow(unused)]
-        let signpost = {
-            let position_component = component::PositionComponent {
-                position: cgmath::Vector2::new(248., 120.),
-                scale: cgmath::Vector2::new(48., 48.),
-            };
-
-            let texture_index = 6; // scroll
-
-            let vertex_array_component = component::VertexArrayComponent::textured_quad(
-                texture_index,
-                component::VertexArrayComponent::OBJECT_Z,
-            );
-
-            let metadata_component = component::MetadataComponent::new(false, false);
-
-            let sign_component = component::SignComponent {
-                in_range: false,
-                bounding_box: physics::BoundingBox {
-                    position: position_component.position,
-                    bottom_left_offset: -1.0 * position_component.scale / 2.0,
-                    top_right_offset: position_component.scale / 2.0,
+                filter: physics::CollisionFilter {
+                    layers: physics::CollisionLayer::Player.bit(),
+                    ..physics::CollisionFilter::all()
                 },
-                popup_text: &INTRO_SIGNPOST,
-            };
-
-            self.add_entity(
-                Some(position_component),
-                Some(vertex_array_component),
-                None,
-                None,
-                None,
-                None,
-                None,
-                Some(metadata_component),
-                None,
-                None,
-                Some(sign_component),
-                None,
-            )
-        };
-
-        #[allow(unused)]
-        let scrolls = {
-            let mut create_scroll = |position_component: component::PositionComponent,
-                                     text: &'static str| {
-                let texture_index = 1; // scroll
-
-                let mut vertex_array_component = component::VertexArrayComponent::textured_quad(
-                    texture_index,
-                    component::VertexArrayComponent::OBJECT_Z,
-                );
-                vertex_array_component.shader_type = component::ShaderType::COLLECTIBLE;
-
-                let metadata_component = component::MetadataComponent::new(false, false);
-
-                let collectible_component = component::CollectibleComponent {
-                    bounding_box: physics::BoundingBox {
-                        position: position_component.position,
-                        bottom_left_offset: -1.0 * position_component.scale / 2.0,
-                        top_right_offset: position_component.scale / 2.0,
-                    },
-                    is_collected: false,
-                    popup_text: &text,
-                };
-
-                self.add_entity(
-                    Some(position_component),
-                    Some(vertex_array_component),
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    Some(metadata_component),
-                    None,
-                    Some(collectible_component),
-                    None,
-                    None,
-                )
             };
 
-            let scroll_1 = create_scroll(
-                component::PositionComponent {
-                    position: cgmath::Vector2::new(332., 132.),
-                    scale: cgmath::Vector2::new(16., 16.),
-                },
-                &FIRST_SCROLL,
-            );
-
-            let scroll_2 = create_scroll(
-                component::PositionComponent {
-                    position: cgmath::Vector2::new(
-                        uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 700.,
-                        302.,
-                    ),
-                    scale: cgmath::Vector2::new(16., 16.),
-                },
-                &SECOND_SCROLL,
-            );
-
-            let scroll_3 = create_scroll(
-                component::PositionComponent {
-                    position: cgmath::Vector2::new(
-                        uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 1350.,
-                        512.,
-                    ),
-                    scale: cgmath::Vector2::new(16., 16.),
-                },
-                &THIRD_SCROLL,
-            );
+            let metadata_component = component::MetadataComponent::new(true, true);
 
-            let scroll_4 = create_scroll(
-                component::PositionComponent {
-                    position: cgmath::Vector2::new(
-                        uniform::WorldUniform::WORLD_SCREEN_WIDTH as f32 + 2300.,
-                        130.,
-                    ),
-                    scale: cgmath::Vector2::new(16., 16.),
-                },
-                &FOURTH_SCROLL,
-            );
+            self.spawn()
+                .with(position_component)
+                .with(vertex_array_component)
+                .with(sprite_animation_controller)
+                .with(sheet_position_component)
+                .with(character_state_component)
+                .with(collider_box_component)
+                .with(metadata_component)
+                .with(physics::PhysicsComponent::new())
+                .with(component::NameComponent {
+                    name: "player".to_string(),
+                })
+                .build()
         };
 
         // let minotaur = {
@@ -1028,57 +479,197 @@ impl<'a> State<'a> {
         //     None)
         // };
 
-        character
+        self.player = Some(character);
+        Ok(())
+    }
+
+    // read from disk on desktop so the level can be edited without recompiling, the copy
+    // built into the binary is used on the web or when the file can't be read
+    fn scene(&mut self) -> Result<scene::Scene> {
+        scene::Scene::load(&mut self.asset_server, &self.scene_path)
+    }
+
+    // every sprite sheet's texture under its label, in load order, so a sprite sheet's handle
+    // index is its texture index
+    fn register_textures(
+        asset_server: &asset::AssetServer,
+        context: &context::Context,
+    ) -> Result<texture_registry::TextureRegistry> {
+        let mut texture_registry = texture_registry::TextureRegistry::new();
+        asset_server
+            .all::<sprite::SpriteSheet>()
+            .iter()
+            .try_for_each(|sprite_sheet| {
+                let sprite_sheet = sprite_sheet.borrow();
+                texture_registry
+                    .register_region(
+                        sprite_sheet.label(),
+                        sprite_sheet.texture(),
+                        sprite_sheet.uv_rect(),
+                    )
+                    .map(|_| ())
+            })?;
+        Self::check_texture_limit(&texture_registry, context)?;
+        Ok(texture_registry)
+    }
+
+    fn check_texture_limit(
+        texture_registry: &texture_registry::TextureRegistry,
+        context: &context::Context,
+    ) -> Result<()> {
+        let max_textures = context
+            .device
+            .limits()
+            .max_sampled_textures_per_shader_stage;
+        ensure!(
+            texture_registry.views().len() <= max_textures as usize,
+            "{} textures are registered but the device can only sample {}",
+            texture_registry.views().len(),
+            max_textures
+        );
+        Ok(())
+    }
+
+    fn character_animations(&mut self) -> Result<aseprite::AsepriteSheet> {
+        let source = self
+            .asset_server
+            .load_text(Self::CHARACTER_ANIMATIONS_PATH)?;
+        aseprite::AsepriteSheet::from_json(&self.asset_server.get(source))
+    }
+
+    fn preprocess_shaders(
+        shaders: &[Rc<asset::Shader>],
+        texture_registry: &texture_registry::TextureRegistry,
+    ) -> wgsl_preprocessor::WgslPreprocessor {
+        let texture_code = texture_registry.shader_code();
+        let mut shader_code: HashMap<&str, &str> = shaders
+            .iter()
+            .map(|shader| (shader.name.as_str(), shader.source.as_str()))
+            .collect();
+        shader_code.insert(
+            texture_registry::TextureRegistry::SHADER_NAME,
+            &texture_code,
+        );
+
+        wgsl_preprocessor::WgslPreprocessor::new(shader_code)
+    }
+
+    // everything the game loads, built into the binary for the web
+    fn embed_assets(asset_server: &mut asset::AssetServer) {
+        asset::embed!(asset_server, "assets/Geo-Regular.ttf");
+        asset::embed!(asset_server, "assets/Pergament9.png");
+        asset::embed!(asset_server, "assets/bg/P1.png");
+        asset::embed!(asset_server, "assets/bg/P2.png");
+        asset::embed!(asset_server, "assets/bg/P3.png");
+        asset::embed!(asset_server, "assets/bg/P4.png");
+        asset::embed!(asset_server, "assets/mira.json");
+        asset::embed!(asset_server, "assets/mira.png");
+        asset::embed!(asset_server, "assets/scenes/demo.ron");
+        asset::embed!(asset_server, "assets/scroll.png");
+        asset::embed!(asset_server, "assets/signpost.png");
+        asset::embed!(asset_server, "assets/tileset.png");
+        asset::embed!(asset_server, "assets/wood_bg.png");
+        asset::embed!(asset_server, "src/shaders/compute.wgsl");
+        asset::embed!(asset_server, "src/shaders/hover.wgsl");
+        asset::embed!(asset_server, "src/shaders/light.wgsl");
+        asset::embed!(asset_server, "src/shaders/model_vertex.wgsl");
+        asset::embed!(asset_server, "src/shaders/outline.wgsl");
+        asset::embed!(asset_server, "src/shaders/post_popup.wgsl");
+        asset::embed!(asset_server, "src/shaders/post_standard.wgsl");
+        asset::embed!(asset_server, "src/shaders/post.wgsl");
+        asset::embed!(asset_server, "src/shaders/standard.wgsl");
+        asset::embed!(asset_server, "src/shaders/uniform.wgsl");
+        asset::embed!(asset_server, "src/shaders/wireframe.wgsl");
+    }
+
+    // despawns everything and spawns the level again
+    pub fn reset(&mut self) -> Result<()> {
+        let entities: Vec<component::Entity> = self.world.entities().collect();
+        entities
+            .into_iter()
+            .for_each(|entity| self.world.remove_entity(entity));
+        self.init()
+    }
+
+    pub fn schedule() -> Schedule<Self> {
+        let mut schedule: Schedule<Self> = Schedule::new(Self::FIXED_UPDATE_DURATION, Self::sync);
+        schedule.set_fixed_clock(|state| &mut state.current_time);
+
+        schedule.add_system(Stage::FixedUpdate, "physics", |state| {
+            state
+                .physics_system
+                .update(&state.world, state.current_time);
+        });
+
+        schedule.add_system(Stage::Update, "hot_reload", Self::hot_reload);
+        schedule.add_system(Stage::Update, "dialogue", Self::close_finished_dialogue);
+        schedule.add_system(Stage::Update, "level_exit", Self::take_level_exits);
+        schedule.add_system(Stage::Update, "snapshot", Self::handle_snapshot_requests);
+
+        schedule.add_system(Stage::Update, "sprite_sheet", |state| {
+            sprite::SpriteSheetSystem::update(&state.world);
+        });
+        schedule
+            .add_system(Stage::Update, "animation", |state| {
+                animation::AnimationSystem::update_animations(&state.world, state.delta_time);
+            })
+            .after("sprite_sheet");
+
+        schedule.add_system(Stage::Update, "tilemap", |state| {
+            tilemap::TilemapSystem::paint(&state.world, &mut state.paint_reader);
+            tilemap::TilemapSystem::update(&state.world);
+        });
+
+        schedule
+            .add_system(Stage::PostUpdate, "transform_propagation", |state| {
+                transform::TransformSystem::propagate(&state.world);
+            })
+            .before("camera");
+        schedule.add_system(Stage::PostUpdate, "camera", |state| {
+            let player_position = state.player.and_then(|player| {
+                state
+                    .world
+                    .get::<component::PositionComponent>(player)
+                    .map(|position_component| position_component.position)
+            });
+            assert!(player_position.is_some());
+            camera::CameraController::update(player_position.unwrap(), &state.world);
+        });
+        schedule
+            .add_system(
+                Stage::PostUpdate,
+                "platformer_game_state",
+                Self::update_platformer_game_state,
+            )
+            .after("camera");
+
+        schedule.add_system(Stage::Render, "render", |state| {
+            let render_result = state.render_system.render(
+                &mut state.render_options,
+                &state.world,
+                &state.context,
+                &mut state.gui,
+                state.window.clone(),
+                state.current_time,
+            );
+            match render_result {
+                Ok(_) => {}
+                // Reconfigure the surface if lost
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    state.resize(state.size)
+                }
+                // The system is out of memory, we should probably quit
+                Err(wgpu::SurfaceError::OutOfMemory) => SHOULD_EXIT.store(true, Ordering::SeqCst),
+                // All other errors (Outdated, Timeout) should be resolved by the next frame
+                Err(e) => eprintln!("{:?}", e),
+            }
+        });
+
+        schedule
     }
 
-    pub fn add_entity(
-        &mut self,
-        position_component: Option<component::PositionComponent>,
-        vertex_array_component: Option<component::VertexArrayComponent>,
-        sprite_animation_controller_component: Option<
-            animation::SpriteAnimationControllerComponent,
-        >,
-        sheet_position_component: Option<sprite::SheetPositionComponent>,
-        character_state_component: Option<component::CharacterStateComponent>,
-        collider_box_component: Option<physics::ColliderBoxComponent>,
-        light_component: Option<uniform::LightComponent>,
-        metadata_component: Option<component::MetadataComponent>,
-        parallax_component: Option<component::ParallaxComponent>,
-        collectible_component: Option<component::CollectibleComponent>,
-        sign_component: Option<component::SignComponent>,
-        moving_platform_component: Option<component::MovingPlatformComponent>,
-    ) -> component::Entity {
-        let entity = self.position_components.insert(position_component);
-        self.vertex_array_components.insert(vertex_array_component);
-
-        self.sprite_animation_controller_components
-            .insert(sprite_animation_controller_component);
-
-        self.sheet_position_components
-            .insert(sheet_position_component);
-
-        self.character_state_components
-            .insert(character_state_component);
-
-        self.collider_box_components.insert(collider_box_component);
-
-        self.light_components.insert(light_component);
-
-        assert!(metadata_component.is_some());
-        self.metadata_components.insert(metadata_component);
-        self.physics_components
-            .insert(Some(physics::PhysicsComponent::new()));
-        // self.entities.push(entity);
-        self.parallax_components.insert(parallax_component);
-
-        self.collectible_components.insert(collectible_component);
-
-        self.sign_components.insert(sign_component);
-
-        self.moving_platform_components
-            .insert(moving_platform_component);
-
-        entity
+    pub fn spawn(&mut self) -> world::EntityBuilder<'_> {
+        self.world.builder()
     }
 
     // pub fn add_physics_component_to_entity(
@@ -1093,73 +684,243 @@ impl<'a> State<'a> {
     //     }
     // }
 
-    pub fn remove_entity(&mut self, entity: component::Entity) {
-        self.position_components.remove(entity);
-        self.vertex_array_components.remove(entity);
-        self.sprite_animation_controller_components.remove(entity);
-        self.sheet_position_components.remove(entity);
-        self.character_state_components.remove(entity);
-        self.collider_box_components.remove(entity);
-        self.light_components.remove(entity);
-        self.metadata_components.remove(entity);
-        self.physics_components.remove(entity);
-        self.collectible_components.remove(entity);
-        self.sign_components.remove(entity);
-        self.moving_platform_components.remove(entity);
-        // self.entities.
-    }
-
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.context.resize(new_size);
-            self.camera.resize(new_size.width, new_size.height);
-            self.world_uniform.resize(new_size.width, new_size.height);
+            self.world
+                .resource_mut::<camera::OrthographicCamera>()
+                .resize(new_size.width, new_size.height);
+            self.world
+                .resource_mut::<uniform::WorldUniform>()
+                .resize(new_size.width, new_size.height);
+
+            self.render_system
+                .resize(&self.texture_registry, &self.context);
+        }
+    }
+
+    pub fn update_platformer_game_state(&mut self) {
+        self.world
+            .resource_mut::<game::PlatformerGameState>()
+            .update(&self.world);
+    }
 
-            self.sprite_sheets
-                .iter_mut()
-                .for_each(|sprite_sheet| sprite_sheet.borrow_mut().resize(&self.context));
+    fn close_finished_dialogue(&mut self) {
+        let events = self.world.events::<game::DialogueFinished>();
+        if self
+            .world
+            .resource_mut::<game::PlatformerGameState>()
+            .dialogue_reader
+            .read(&events)
+            .count()
+            > 0
+        {
+            *self.world.resource_mut::<game::GameMode>() = game::GameMode::STANDARD;
+        }
+    }
+
+    // the player starts the next level from its beginning, keeping what they've collected
+    fn take_level_exits(&mut self) {
+        let events = self.world.events::<game::LevelExited>();
+        let Some(scene_path) = self
+            .level_exit_reader
+            .read(&events)
+            .last()
+            .map(|level_exited| level_exited.scene.clone())
+        else {
+            return;
+        };
+        drop(events);
 
-            let textures = &self.textures();
-            self.render_system.resize(textures, &self.context);
+        if let Err(e) = self.change_level(scene_path.clone()) {
+            log::error!("failed to load level {}: {:#}", scene_path, e);
         }
     }
 
-    pub fn textures(&self) -> Vec<Arc<texture::Texture>> {
-        self.sprite_sheets
+    // points the texture registry at the sprite sheets' textures again after some were reloaded,
+    // page by page in the same order register_textures puts them in. a repacked atlas can move
+    // sheets around and onto more or fewer pages, and texture.wgsl and the texture bind group
+    // layout follow the pages, so if they changed the renderer is built again
+    fn update_textures(&mut self) -> Result<()> {
+        let shader_code = self.texture_registry.shader_code();
+        let mut pages: Vec<Arc<texture::Texture>> = Vec::new();
+        self.asset_server
+            .all::<sprite::SpriteSheet>()
             .iter()
-            .map(|sprite_sheet| sprite_sheet.borrow().texture())
-            .collect::<Vec<Arc<texture::Texture>>>()
+            .try_for_each(|sprite_sheet| {
+                let sprite_sheet = sprite_sheet.borrow();
+                let texture = sprite_sheet.texture();
+                let page = match pages.iter().position(|page| Arc::ptr_eq(page, &texture)) {
+                    Some(page) => page,
+                    None => {
+                        pages.push(texture.clone());
+                        pages.len() - 1
+                    }
+                };
+                self.texture_registry.replace_region(
+                    sprite_sheet.label(),
+                    texture,
+                    sprite_sheet.uv_rect(),
+                    page as u32,
+                )
+            })?;
+        self.texture_registry.truncate_pages(pages.len())?;
+        Self::check_texture_limit(&self.texture_registry, &self.context)?;
+
+        if self.texture_registry.shader_code() == shader_code {
+            self.render_system
+                .set_textures(&self.texture_registry, &self.context);
+        } else {
+            self.wgsl_preprocessor = Self::preprocess_shaders(
+                &self.asset_server.all::<asset::Shader>(),
+                &self.texture_registry,
+            );
+            self.render_system = render_system::RenderSystem::new(
+                &self.texture_registry,
+                &self.context,
+                &self.wgsl_preprocessor,
+            );
+        }
+        Ok(())
     }
 
-    pub fn update_platformer_game_state(&mut self) {
-        self.platformer_game_state.update(
-            &mut self.position_components,
-            &mut self.collider_box_components,
-            &mut self.metadata_components,
-        );
-        let entities_to_remove: Vec<_> = self
-            .collectible_components
+    // a scene that doesn't parse leaves the running level alone
+    fn change_level(&mut self, scene_path: String) -> Result<()> {
+        scene::Scene::load(&mut self.asset_server, &scene_path)?;
+        self.scene_path = scene_path;
+        self.world
+            .resource_mut::<game::PlatformerGameState>()
+            .character_init_position = Self::CHARACTER_INIT_POSITION;
+        self.reset()
+    }
+
+    fn handle_snapshot_requests(&mut self) {
+        const SNAPSHOT_PATH: &str = "snapshot.ron";
+
+        let requests: Vec<snapshot::SnapshotRequest> = self
+            .snapshot_reader
+            .read(&self.world.events::<snapshot::SnapshotRequest>())
+            .copied()
+            .collect();
+
+        requests.into_iter().for_each(|request| {
+            let result = match request {
+                snapshot::SnapshotRequest::Save => {
+                    snapshot::WorldSnapshot::capture(&self.world, self.current_time)
+                        .save(SNAPSHOT_PATH)
+                }
+                // restored on top of a freshly spawned level
+                snapshot::SnapshotRequest::Load => snapshot::WorldSnapshot::load(SNAPSHOT_PATH)
+                    .and_then(|world_snapshot| {
+                        self.reset()?;
+                        world_snapshot.restore(&mut self.world, self.current_time)
+                    }),
+            };
+            match result {
+                Ok(()) => log::info!("{:?} {}", request, SNAPSHOT_PATH),
+                Err(e) => log::error!("{:#}", e),
+            }
+        });
+    }
+
+    // picks up edits to loaded files. only the pipelines using a changed shader are rebuilt, and
+    // a changed scene is spawned again with the running game's state put back on top
+    fn hot_reload(&mut self) {
+        let changed = self.asset_server.changed();
+        if changed.is_empty() {
+            return;
+        }
+
+        let changed_shaders: HashSet<String> = changed
             .iter()
-            .filter_map(|(e, collectible_component)| match collectible_component {
-                Some(collectible_component) if collectible_component.is_collected => {
-                    Some((e, collectible_component.popup_text))
+            .filter(|path| path.ends_with(".wgsl"))
+            .filter_map(|path| match self.asset_server.reload_shader(path) {
+                Ok(()) => self
+                    .asset_server
+                    .get_handle::<asset::Shader>(path)
+                    .map(|shader| self.asset_server.get(shader).name.clone()),
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    None
                 }
-                _ => None,
             })
             .collect();
+        if !changed_shaders.is_empty() {
+            self.wgsl_preprocessor = Self::preprocess_shaders(
+                &self.asset_server.all::<asset::Shader>(),
+                &self.texture_registry,
+            );
+            self.render_system.reload_shaders(
+                &changed_shaders,
+                &self.context,
+                &self.wgsl_preprocessor,
+            );
+        }
+
+        let mut textures_changed = false;
+        changed
+            .iter()
+            .filter(|path| path.ends_with(".png"))
+            .for_each(
+                |path| match self.asset_server.reload_textures(&self.context, path) {
+                    Ok(reloaded) => textures_changed |= reloaded,
+                    Err(e) => log::error!("{:#}", e),
+                },
+            );
+        if textures_changed {
+            if let Err(e) = self.update_textures() {
+                log::error!("{:#}", e);
+            }
+        }
+
+        if changed.contains(&self.scene_path) {
+            if let Err(e) = self.reload_scene() {
+                log::error!("failed to reload {}: {:#}", self.scene_path, e);
+            }
+        }
 
-        self.platformer_game_state.notes_collected += entities_to_remove.len() as u32;
+        changed
+            .iter()
+            .for_each(|path| log::info!("{} changed", path));
+    }
 
-        entities_to_remove.iter().for_each(|(entity, _)| {
-            self.remove_entity(*entity);
-        });
+    // a scene that doesn't parse leaves the running level alone
+    fn reload_scene(&mut self) -> Result<()> {
+        let world_snapshot = snapshot::WorldSnapshot::capture(&self.world, self.current_time);
+        let scene_names = self.scene_names.clone();
 
-        if entities_to_remove.len() > 0 {
-            self.game_mode = game::GameMode::POPUP;
-            self.gui_info.popup_text = entities_to_remove.get(0).unwrap().1;
-            self.gui_info.popup_type = gui::PopupType::SCROLL;
+        self.asset_server.reload_text(&self.scene_path)?;
+        self.scene()?;
+        self.reset()?;
+        world_snapshot.reapply(&mut self.world, self.current_time, &scene_names);
+        Ok(())
+    }
+
+    fn show_collected_popup(event: &EngineEvent, state: &mut Self) {
+        if let EngineEvent::CollectibleCollected { popup_text, .. } = event {
+            let mut platformer_game_state = state.world.resource_mut::<game::PlatformerGameState>();
+            let mut gui_info = state.world.resource_mut::<gui::GuiInfo>();
+            platformer_game_state.notes_collected += 1;
+            gui_info.notes_collected = platformer_game_state.notes_collected;
+
+            *state.world.resource_mut::<game::GameMode>() = game::GameMode::POPUP;
+            gui_info.popup_text = popup_text.clone();
+            gui_info.popup_type = gui::PopupType::SCROLL;
         }
-        self.gui_info.notes_collected = self.platformer_game_state.notes_collected;
+    }
+
+    // the sync point between the update stages and rendering
+    fn sync(&mut self) {
+        self.world.apply_commands();
+
+        let event_bus = std::mem::take(&mut self.event_bus);
+        event_bus.dispatch(self.world.drain_events(), self);
+        self.event_bus = event_bus;
+
+        // anything the subscribers queued up
+        self.world.apply_commands();
+
+        self.world.update_events();
     }
 }
@@ -107,29 +107,6 @@ pub struct Texture {
 }
 
 impl Texture {
-    pub fn from_path(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        texture_path: String,
-        normal_path: Option<String>,
-        manual_premultiply: bool,
-    ) -> Result<Self> {
-        let texture_bytes =
-            std::fs::read(texture_path.clone()).expect("Failed to read sprite sheet image");
-        let normal_bytes = normal_path.map(|normal_path| {
-            std::fs::read(normal_path.clone()).expect("Failed to read normal sprite sheet image")
-        });
-
-        Self::from_bytes(
-            device,
-            queue,
-            &texture_bytes,
-            normal_bytes.as_deref(),
-            &texture_path,
-            manual_premultiply,
-        )
-    }
-
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -0,0 +1,418 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use anyhow::{anyhow, ensure, Result};
+use cgmath::Vector2;
+
+use crate::texture;
+
+// the part of a bound texture (a page) that a texture index refers to, in texture coordinates
+// of the page. the renderer maps a vertex array's texture coordinates into it, so sprite sheets
+// packed into an atlas work the same as ones with a texture of their own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRegion {
+    pub page: u32,
+    pub offset: Vector2<f32>,
+    pub size: Vector2<f32>,
+}
+
+impl TextureRegion {
+    pub fn map(&self, tex_coord: Vector2<f32>) -> Vector2<f32> {
+        Vector2::new(
+            self.offset.x + tex_coord.x * self.size.x,
+            self.offset.y + tex_coord.y * self.size.y,
+        )
+    }
+}
+
+// the textures the renderer samples from, by name. the texture bind group and texture.wgsl,
+// which picks a page by the index in the low byte of extra_info, are both generated from it
+// in the same order, so a page index always means the same texture on both sides.
+#[derive(Default)]
+pub struct TextureRegistry {
+    pages: Vec<Arc<texture::Texture>>,
+    textures: Vec<(String, TextureRegion)>,
+}
+
+impl TextureRegistry {
+    // the page index has to fit in the low byte of extra_info
+    pub const MAX_PAGES: usize = 256;
+
+    // what the generated code is included by
+    pub const SHADER_NAME: &'static str = "texture.wgsl";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // returns the texture index vertex arrays refer to it by
+    pub fn register(&mut self, name: &str, texture: Arc<texture::Texture>) -> Result<u32> {
+        self.register_region(name, texture, (Vector2::new(0., 0.), Vector2::new(1., 1.)))
+    }
+
+    // a part of a texture, e.g. an atlas page, which is only bound once however many parts of
+    // it are registered. the name ends up in a comment in the shader, so it has to be an
+    // identifier
+    pub fn register_region(
+        &mut self,
+        name: &str,
+        texture: Arc<texture::Texture>,
+        (offset, size): (Vector2<f32>, Vector2<f32>),
+    ) -> Result<u32> {
+        ensure!(
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "texture name {:?} isn't a valid identifier",
+            name
+        );
+        ensure!(
+            self.index_of(name).is_none(),
+            "texture {} is already registered",
+            name
+        );
+
+        let page = match self
+            .pages
+            .iter()
+            .position(|page| Arc::ptr_eq(page, &texture))
+        {
+            Some(page) => page,
+            None => {
+                ensure!(
+                    self.pages.len() < Self::MAX_PAGES,
+                    "can't bind more than {} textures",
+                    Self::MAX_PAGES
+                );
+                self.pages.push(texture);
+                self.pages.len() - 1
+            }
+        };
+
+        self.textures.push((
+            name.to_string(),
+            TextureRegion {
+                page: page as u32,
+                offset,
+                size,
+            },
+        ));
+        Ok(self.textures.len() as u32 - 1)
+    }
+
+    // points the named texture at a part of another texture on the given page, e.g. where its
+    // atlas put it after being packed again. the page is swapped for the texture if it holds a
+    // different one, and a page one past the last adds a page. the bindings have to be created
+    // again afterwards, and texture.wgsl too if the pages changed
+    pub fn replace_region(
+        &mut self,
+        name: &str,
+        texture: Arc<texture::Texture>,
+        (offset, size): (Vector2<f32>, Vector2<f32>),
+        page: u32,
+    ) -> Result<()> {
+        let index = self
+            .index_of(name)
+            .ok_or_else(|| anyhow!("no texture {} is registered", name))?;
+        match (page as usize).cmp(&self.pages.len()) {
+            std::cmp::Ordering::Less => self.pages[page as usize] = texture,
+            std::cmp::Ordering::Equal => {
+                ensure!(
+                    self.pages.len() < Self::MAX_PAGES,
+                    "can't bind more than {} textures",
+                    Self::MAX_PAGES
+                );
+                self.pages.push(texture);
+            }
+            std::cmp::Ordering::Greater => {
+                return Err(anyhow!(
+                    "page {} is past the {} pages of the registry",
+                    page,
+                    self.pages.len()
+                ))
+            }
+        }
+        self.textures[index as usize].1 = TextureRegion { page, offset, size };
+        Ok(())
+    }
+
+    // drops the pages from page_count on, which nothing can be on anymore
+    pub fn truncate_pages(&mut self, page_count: usize) -> Result<()> {
+        if let Some((name, region)) = self
+            .textures
+            .iter()
+            .find(|(_, region)| region.page as usize >= page_count)
+        {
+            return Err(anyhow!("texture {} is still on page {}", name, region.page));
+        }
+        self.pages.truncate(page_count);
+        Ok(())
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<u32> {
+        self.textures
+            .iter()
+            .position(|(registered, _)| registered == name)
+            .map(|index| index as u32)
+    }
+
+    // by texture index
+    pub fn regions(&self) -> Vec<TextureRegion> {
+        self.textures.iter().map(|(_, region)| *region).collect()
+    }
+
+    // the views to bind, from binding 1 on (the sampler is binding 0). a page's normal map goes
+    // right after it
+    pub fn views(&self) -> Vec<&wgpu::TextureView> {
+        self.pages
+            .iter()
+            .flat_map(|page| {
+                std::iter::once(&page.view).chain(
+                    page.normal_info
+                        .as_ref()
+                        .map(|(_, normal_view)| normal_view),
+                )
+            })
+            .collect()
+    }
+
+    // the contents of texture.wgsl
+    pub fn shader_code(&self) -> String {
+        let mut code = String::from("@group(1) @binding(0)\nvar pixel_sampler: sampler;\n");
+        let mut binding = 1;
+        self.pages
+            .iter()
+            .enumerate()
+            .for_each(|(page_index, page)| {
+                let names: Vec<&str> = self
+                    .textures
+                    .iter()
+                    .filter(|(_, region)| region.page as usize == page_index)
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+                write!(
+                    code,
+                    "\n// {}\n@group(1) @binding({})\nvar t_page_{}: texture_2d<f32>;\n",
+                    names.join(", "),
+                    binding,
+                    page_index
+                )
+                .unwrap();
+                binding += 1;
+                if page.normal_info.is_some() {
+                    write!(
+                        code,
+                        "\n@group(1) @binding({})\nvar n_page_{}: texture_2d<f32>;\n",
+                        binding, page_index
+                    )
+                    .unwrap();
+                    binding += 1;
+                }
+            });
+
+        code.push_str(
+            "
+struct TextureInfo {
+    color: vec4<f32>,
+    normal: vec4<f32>,
+}
+
+/* disable_uniformity_analysis */
+
+fn get_texture_color(extra_info: u32, tex_coords: vec2<f32>) -> TextureInfo {
+    var color: vec4<f32>;
+    var normal: vec4<f32> = vec4(0.);
+
+    switch extra_info & 255u {
+",
+        );
+        self.pages.iter().enumerate().for_each(|(page_index, page)| {
+            write!(
+                code,
+                "        case {}u: {{\n            color = textureSampleLevel(t_page_{}, pixel_sampler, tex_coords, 0.0);\n",
+                page_index, page_index
+            )
+            .unwrap();
+            if page.normal_info.is_some() {
+                writeln!(
+                    code,
+                    "            normal = textureSampleLevel(n_page_{}, pixel_sampler, tex_coords, 0.0);",
+                    page_index
+                )
+                .unwrap();
+            }
+            code.push_str("        }\n");
+        });
+        code.push_str(
+            "        default: {
+            color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+        }
+    }
+
+    var is_flipped = (extra_info & (1u << 8)) != 0;
+
+    if (is_flipped) {
+        normal = vec4((1. - normal.x) * normal.w, normal.y, normal.z, normal.w);
+    }
+
+    var t_info: TextureInfo;
+    t_info.color = color;
+    t_info.normal = normal;
+    return t_info;
+}
+",
+        );
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::{Atlas, AtlasBuilder};
+    use crate::context;
+
+    fn pack(sizes: &[(&str, u32)]) -> Atlas {
+        let mut builder = AtlasBuilder::new((16, 16), 0);
+        sizes.iter().for_each(|(name, size)| {
+            builder
+                .add(
+                    name,
+                    &image::DynamicImage::ImageRgba8(image::RgbaImage::new(*size, *size)),
+                )
+                .unwrap()
+        });
+        builder.build().unwrap()
+    }
+
+    fn pages(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &Atlas,
+    ) -> Vec<Arc<texture::Texture>> {
+        atlas
+            .pages
+            .iter()
+            .map(|page| {
+                Arc::new(
+                    texture::Texture::from_image(
+                        device,
+                        queue,
+                        &image::DynamicImage::ImageRgba8(page.clone()),
+                        None,
+                        None,
+                        false,
+                    )
+                    .unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn region(atlas: &Atlas, name: &str) -> TextureRegion {
+        let (offset, size) = atlas.uv_rect(name).unwrap();
+        TextureRegion {
+            page: atlas.regions[name].page as u32,
+            offset,
+            size,
+        }
+    }
+
+    #[test]
+    fn replacing_regions_follows_a_repacked_atlas() {
+        let Some((device, queue)) = context::headless_device() else {
+            eprintln!("no graphics adapter, skipping");
+            return;
+        };
+
+        let atlas = pack(&[("a", 8), ("b", 4)]);
+        let mut registry = TextureRegistry::new();
+        let textures = pages(&device, &queue, &atlas);
+        ["a", "b"].iter().for_each(|name| {
+            let region = region(&atlas, name);
+            registry
+                .register_region(
+                    name,
+                    textures[region.page as usize].clone(),
+                    (region.offset, region.size),
+                )
+                .unwrap();
+        });
+        assert_eq!(
+            registry.regions(),
+            vec![region(&atlas, "a"), region(&atlas, "b")]
+        );
+        assert_eq!(registry.page_count(), 1);
+
+        // b grew, so it doesn't fit next to a anymore
+        let repacked = pack(&[("a", 8), ("b", 12)]);
+        let textures = pages(&device, &queue, &repacked);
+        ["a", "b"].iter().for_each(|name| {
+            let region = region(&repacked, name);
+            registry
+                .replace_region(
+                    name,
+                    textures[region.page as usize].clone(),
+                    (region.offset, region.size),
+                    region.page,
+                )
+                .unwrap();
+        });
+        registry.truncate_pages(textures.len()).unwrap();
+
+        assert_eq!(
+            registry.regions(),
+            vec![region(&repacked, "a"), region(&repacked, "b")]
+        );
+        assert_ne!(registry.regions()[1].page, registry.regions()[0].page);
+        assert_eq!(registry.page_count(), 2);
+        assert!(registry.shader_code().contains("var t_page_1"));
+
+        // and back onto one page
+        let textures = pages(&device, &queue, &atlas);
+        ["b", "a"].iter().for_each(|name| {
+            let region = region(&atlas, name);
+            registry
+                .replace_region(
+                    name,
+                    textures[region.page as usize].clone(),
+                    (region.offset, region.size),
+                    region.page,
+                )
+                .unwrap();
+        });
+        registry.truncate_pages(1).unwrap();
+        assert_eq!(
+            registry.regions(),
+            vec![region(&atlas, "a"), region(&atlas, "b")]
+        );
+        assert!(!registry.shader_code().contains("var t_page_1"));
+    }
+
+    #[test]
+    fn replace_region_checks_names_and_pages() {
+        let Some((device, queue)) = context::headless_device() else {
+            eprintln!("no graphics adapter, skipping");
+            return;
+        };
+
+        let atlas = pack(&[("a", 8)]);
+        let textures = pages(&device, &queue, &atlas);
+        let mut registry = TextureRegistry::new();
+        registry.register("a", textures[0].clone()).unwrap();
+        let whole = (Vector2::new(0., 0.), Vector2::new(1., 1.));
+
+        assert!(registry
+            .replace_region("missing", textures[0].clone(), whole, 0)
+            .is_err());
+        assert!(registry
+            .replace_region("a", textures[0].clone(), whole, 2)
+            .is_err());
+        // a is still on page 0
+        assert!(registry.truncate_pages(0).is_err());
+    }
+}
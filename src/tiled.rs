@@ -0,0 +1,692 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::physics::CollisionLayer;
+use crate::scene::{Depth, Scene, SceneComponent, SceneEntity};
+use crate::tilemap::Tile;
+
+// imports maps saved from Tiled as JSON (.tmj) into a scene. only orthogonal, finite maps with
+// embedded tilesets and csv tile data are supported. tiled's y axis points down, so everything
+// is flipped vertically, one pixel in the map is one world unit.
+//
+// tile layers become a tilemap per tileset they use. a tile is solid if its layer or its tile in
+// the tileset has a `solid` property. objects are turned into entities by their type (class):
+//   collider    - just a collider
+//   trigger     - just a trigger, which reports what's in it instead of blocking it
+//   checkpoint  - trigger the player respawns at after walking into it
+//   kill_zone   - trigger that sends the player back to the last checkpoint
+//   level_exit  - trigger that moves the player on to another level, needs `scene`, its path
+//   sign        - signpost, needs `popup_text`
+//   collectible - collectible scroll, needs `popup_text`
+//   light       - circle light, `color`, `linear_dropoff`, `quadratic_dropoff`,
+//                 `ambient_strength` and `diffuse_strength` are optional
+// anything else with a gid becomes a plain sprite, which can set `outline`. on top of that, any
+// tile layer or object can have `solid`, `physics` and `moving_amplitude`/`moving_period_secs`/
+// `moving_horizontal` properties, which add the matching components. colliders of objects are
+// put on collision layers with `collision_layers` and `collides_with`, comma separated layer
+// names, and default to Terrain colliding with every layer. triggers only take `collides_with`.
+//
+// tilesets are matched to sprite sheets by name, or by a `sheet` property on the tileset. tiles
+// are found in the sheet by their column and row in the tileset, in tile layers and tile objects
+// alike, so the sheet's sprites have to be the tileset's tiles.
+
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+const ROTATED_HEXAGONAL: u32 = 0x10000000;
+const GID_MASK: u32 =
+    !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+
+#[derive(Deserialize)]
+struct TiledMap {
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    layers: Vec<TiledLayer>,
+    tilesets: Vec<TiledTileset>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TiledLayer {
+    TileLayer {
+        name: String,
+        width: u32,
+        #[serde(default)]
+        encoding: Option<String>,
+        data: serde_json::Value,
+        #[serde(default = "visible")]
+        visible: bool,
+        #[serde(default)]
+        properties: Vec<TiledProperty>,
+    },
+    ObjectGroup {
+        name: String,
+        objects: Vec<TiledObject>,
+        #[serde(default = "visible")]
+        visible: bool,
+        #[serde(default)]
+        properties: Vec<TiledProperty>,
+    },
+    Group {
+        layers: Vec<TiledLayer>,
+        #[serde(default = "visible")]
+        visible: bool,
+    },
+    #[serde(other)]
+    Other,
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct TiledTileset {
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledObject {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type", alias = "class")]
+    kind: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default = "visible")]
+    visible: bool,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn property<'a>(properties: &'a [TiledProperty], name: &str) -> Option<&'a serde_json::Value> {
+    properties
+        .iter()
+        .find(|property| property.name == name)
+        .map(|property| &property.value)
+}
+
+fn bool_property(properties: &[TiledProperty], name: &str) -> bool {
+    property(properties, name)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn float_property(properties: &[TiledProperty], name: &str) -> Option<f32> {
+    property(properties, name)
+        .and_then(|value| value.as_f64())
+        .map(|value| value as f32)
+}
+
+fn string_property(properties: &[TiledProperty], name: &str) -> Option<String> {
+    property(properties, name)
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+}
+
+// None if it isn't set, so the scene's default applies. an empty string is no layers
+fn layers_property(
+    properties: &[TiledProperty],
+    name: &str,
+) -> Result<Option<Vec<CollisionLayer>>> {
+    let Some(layers) = string_property(properties, name) else {
+        return Ok(None);
+    };
+    layers
+        .split(',')
+        .filter(|layer| !layer.trim().is_empty())
+        .map(|layer| {
+            ron::from_str(layer.trim())
+                .map_err(|_| anyhow!("invalid collision layer {} in {}", layer.trim(), name))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+fn collider(properties: &[TiledProperty]) -> Result<SceneComponent> {
+    Ok(SceneComponent::Collider {
+        bottom_left_offset: None,
+        top_right_offset: None,
+        layers: layers_property(properties, "collision_layers")?,
+        collides_with: layers_property(properties, "collides_with")?,
+    })
+}
+
+fn trigger(properties: &[TiledProperty]) -> Result<SceneComponent> {
+    Ok(SceneComponent::Trigger {
+        bottom_left_offset: None,
+        top_right_offset: None,
+        collides_with: layers_property(properties, "collides_with")?,
+    })
+}
+
+// tiled writes colors as #AARRGGBB, or #RRGGBB without alpha
+fn color_property(properties: &[TiledProperty], name: &str) -> Result<Option<(f32, f32, f32)>> {
+    let Some(color) = string_property(properties, name) else {
+        return Ok(None);
+    };
+    let hex = color.trim_start_matches('#');
+    let rgb = match hex.len() {
+        6 => hex,
+        8 => &hex[2..],
+        _ => return Err(anyhow!("invalid color {}", color)),
+    };
+    let channel = |i: usize| {
+        u8::from_str_radix(&rgb[i..i + 2], 16)
+            .map(|channel| channel as f32 / 255.)
+            .map_err(|_| anyhow!("invalid color {}", color))
+    };
+    Ok(Some((channel(0)?, channel(2)?, channel(4)?)))
+}
+
+// components that can be added to anything through custom properties
+fn property_components(properties: &[TiledProperty]) -> Result<Vec<SceneComponent>> {
+    let mut components = Vec::new();
+    if bool_property(properties, "solid") {
+        components.push(collider(properties)?);
+    }
+    if bool_property(properties, "physics") {
+        components.push(SceneComponent::Physics);
+    }
+    if let Some(amplitude) = float_property(properties, "moving_amplitude") {
+        components.push(SceneComponent::MovingPlatform {
+            amplitude,
+            period_secs: float_property(properties, "moving_period_secs")
+                .ok_or_else(|| anyhow!("moving_amplitude needs a moving_period_secs"))?,
+            horizontal: bool_property(properties, "moving_horizontal"),
+        });
+    }
+    Ok(components)
+}
+
+struct TileImporter<'a> {
+    map: &'a TiledMap,
+}
+
+impl<'a> TileImporter<'a> {
+    fn tileset(&self, gid: u32) -> Result<&'a TiledTileset> {
+        let tile_id = gid & GID_MASK;
+        let tileset = self
+            .map
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.firstgid <= tile_id)
+            .max_by_key(|tileset| tileset.firstgid)
+            .ok_or_else(|| anyhow!("no tileset for tile {}", tile_id))?;
+        if let Some(source) = &tileset.source {
+            return Err(anyhow!(
+                "external tileset {} isn't supported, embed it in the map",
+                source
+            ));
+        }
+        if tileset.columns == 0 {
+            return Err(anyhow!("tileset {} has no columns", tileset.name));
+        }
+        Ok(tileset)
+    }
+
+    fn sheet(tileset: &TiledTileset) -> String {
+        string_property(&tileset.properties, "sheet").unwrap_or_else(|| tileset.name.clone())
+    }
+
+    fn sprite(&self, gid: u32, depth: Depth) -> Result<SceneComponent> {
+        let tileset = self.tileset(gid)?;
+        let local_id = (gid & GID_MASK) - tileset.firstgid;
+        Ok(SceneComponent::Sprite {
+            sheet: Self::sheet(tileset),
+            depth,
+            sheet_position: Some((local_id % tileset.columns, local_id / tileset.columns)),
+            flipped: gid & FLIPPED_HORIZONTALLY != 0,
+        })
+    }
+
+    fn import_layers(&self, layers: &[TiledLayer], entities: &mut Vec<SceneEntity>) -> Result<()> {
+        for layer in layers {
+            match layer {
+                TiledLayer::TileLayer {
+                    name,
+                    width,
+                    encoding,
+                    data,
+                    visible,
+                    properties,
+                } if *visible => self
+                    .import_tile_layer(name, *width, encoding, data, properties, entities)
+                    .with_context(|| format!("in tile layer {}", name))?,
+                TiledLayer::ObjectGroup {
+                    name,
+                    objects,
+                    visible,
+                    properties,
+                } if *visible => objects
+                    .iter()
+                    .filter(|object| object.visible)
+                    .try_for_each(|object| {
+                        let object_name = if object.name.is_empty() {
+                            format!("{}_{}", name, object.id)
+                        } else {
+                            object.name.clone()
+                        };
+                        let entity = self
+                            .import_object(object, object_name, properties)
+                            .with_context(|| {
+                                format!("in object {} of layer {}", object.id, name)
+                            })?;
+                        entities.extend(entity);
+                        Ok::<(), anyhow::Error>(())
+                    })?,
+                TiledLayer::Group { layers, visible } if *visible => {
+                    self.import_layers(layers, entities)?
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // one tilemap for every tileset the layer uses
+    fn import_tile_layer(
+        &self,
+        name: &str,
+        width: u32,
+        encoding: &Option<String>,
+        data: &serde_json::Value,
+        properties: &[TiledProperty],
+        entities: &mut Vec<SceneEntity>,
+    ) -> Result<()> {
+        if let Some(encoding) = encoding.as_ref().filter(|encoding| *encoding != "csv") {
+            return Err(anyhow!(
+                "{} tile layer data isn't supported, use the csv layer format",
+                encoding
+            ));
+        }
+        let data: Vec<u32> = serde_json::from_value(data.clone())?;
+        let layer_is_solid = bool_property(properties, "solid");
+
+        // keyed by firstgid
+        let mut tilemaps = BTreeMap::new();
+        for (index, gid) in data.iter().enumerate() {
+            if *gid == 0 {
+                continue;
+            }
+            let tileset = self.tileset(*gid)?;
+            let local_id = (gid & GID_MASK) - tileset.firstgid;
+            let (_, tiles, solid_tiles) = tilemaps
+                .entry(tileset.firstgid)
+                .or_insert_with(|| (tileset, vec![None; data.len()], BTreeSet::new()));
+            tiles[index] = Some(Tile {
+                index: local_id,
+                flipped: gid & FLIPPED_HORIZONTALLY != 0,
+            });
+            let is_solid = layer_is_solid
+                || tileset
+                    .tiles
+                    .iter()
+                    .find(|tile| tile.id == local_id)
+                    .is_some_and(|tile| bool_property(&tile.properties, "solid"));
+            if is_solid {
+                solid_tiles.insert(local_id);
+            }
+        }
+
+        // the layer's colliders come from its solid tiles
+        let layer_components: Vec<SceneComponent> = property_components(properties)?
+            .into_iter()
+            .filter(|component| !matches!(component, SceneComponent::Collider { .. }))
+            .collect();
+        let tilemap_count = tilemaps.len();
+        for (tileset, tiles, solid_tiles) in tilemaps.into_values() {
+            let mut components = vec![
+                SceneComponent::Position {
+                    position: (0., 0.),
+                    scale: (1., 1.),
+                    rotation: 0.,
+                    pivot: (0., 0.),
+                },
+                SceneComponent::Tilemap {
+                    sheet: Self::sheet(tileset),
+                    depth: Depth::Foreground,
+                    tile_size: (self.map.tilewidth as f32, self.map.tileheight as f32),
+                    width,
+                    tiles,
+                    solid_tiles: solid_tiles.into_iter().collect(),
+                    auto_tile: None,
+                    columns: Some(tileset.columns),
+                },
+            ];
+            components.extend(layer_components.iter().cloned());
+
+            entities.push(SceneEntity {
+                name: Some(if tilemap_count > 1 {
+                    format!("{}_{}", name, tileset.name)
+                } else {
+                    name.to_string()
+                }),
+                components,
+            });
+        }
+        Ok(())
+    }
+
+    fn import_object(
+        &self,
+        object: &TiledObject,
+        name: String,
+        layer_properties: &[TiledProperty],
+    ) -> Result<Option<SceneEntity>> {
+        let map_height = (self.map.height * self.map.tileheight) as f32;
+        let properties = &object.properties;
+
+        // rectangles are placed by their top left corner, tile objects by their bottom left, and
+        // they rotate clockwise around that corner
+        let (center_y, pivot) = if object.gid.is_some() {
+            (object.y - object.height / 2., (-0.5, -0.5))
+        } else {
+            (object.y + object.height / 2., (-0.5, 0.5))
+        };
+        let position = SceneComponent::Position {
+            position: (object.x + object.width / 2., map_height - center_y),
+            scale: (object.width, object.height),
+            rotation: -object.rotation.to_radians(),
+            pivot,
+        };
+        let popup_text = || {
+            string_property(properties, "popup_text")
+                .ok_or_else(|| anyhow!("{} needs a popup_text", object.kind))
+        };
+        let sheet = || -> Result<String> {
+            match object.gid {
+                Some(gid) => self.tileset(gid).map(Self::sheet),
+                None => string_property(properties, "sheet")
+                    .ok_or_else(|| anyhow!("{} needs a gid or a sheet", object.kind)),
+            }
+        };
+
+        let mut components = vec![position];
+        match object.kind.as_str() {
+            "collider" => components.push(collider(properties)?),
+            "trigger" => components.push(trigger(properties)?),
+            "checkpoint" => components.extend([trigger(properties)?, SceneComponent::Checkpoint]),
+            "kill_zone" => components.extend([trigger(properties)?, SceneComponent::KillZone]),
+            "level_exit" => components.extend([
+                trigger(properties)?,
+                SceneComponent::LevelExit {
+                    scene: string_property(properties, "scene")
+                        .ok_or_else(|| anyhow!("level_exit needs a scene"))?,
+                },
+            ]),
+            "sign" => components.push(SceneComponent::Signpost {
+                sheet: sheet()?,
+                popup_text: popup_text()?,
+            }),
+            "collectible" => components.push(SceneComponent::CollectibleScroll {
+                sheet: sheet()?,
+                popup_text: popup_text()?,
+            }),
+            "light" => components.extend([
+                SceneComponent::Circle {
+                    depth: Depth::Foreground,
+                },
+                SceneComponent::Light {
+                    color: color_property(properties, "color")?.unwrap_or((1., 1., 1.)),
+                    linear_dropoff: float_property(properties, "linear_dropoff").unwrap_or(0.0007),
+                    quadratic_dropoff: float_property(properties, "quadratic_dropoff")
+                        .unwrap_or(0.0001),
+                    ambient_strength: float_property(properties, "ambient_strength").unwrap_or(10.),
+                    diffuse_strength: float_property(properties, "diffuse_strength").unwrap_or(15.),
+                },
+                SceneComponent::Metadata {
+                    outline: false,
+                    controllable: false,
+                },
+            ]),
+            _ => match object.gid {
+                Some(gid) => components.extend([
+                    self.sprite(gid, Depth::Object)?,
+                    SceneComponent::Metadata {
+                        outline: bool_property(properties, "outline"),
+                        controllable: false,
+                    },
+                ]),
+                // nothing to show or collide with
+                None if properties.is_empty() && layer_properties.is_empty() => return Ok(None),
+                None => {}
+            },
+        }
+        components.extend(property_components(layer_properties)?);
+        components.extend(property_components(properties)?);
+
+        Ok(Some(SceneEntity {
+            name: Some(name),
+            components,
+        }))
+    }
+}
+
+pub fn import(source: &str) -> Result<Scene> {
+    let map: TiledMap =
+        serde_json::from_str(source).map_err(|e| anyhow!("invalid tiled map: {}", e))?;
+    if map.orientation != "orthogonal" {
+        return Err(anyhow!("{} maps aren't supported", map.orientation));
+    }
+    if map.infinite {
+        return Err(anyhow!("infinite maps aren't supported"));
+    }
+
+    let mut entities = Vec::new();
+    TileImporter { map: &map }.import_layers(&map.layers, &mut entities)?;
+    Ok(Scene { entities })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two tilesets, a 3x2 tile layer using both and a layer of objects
+    const MAP: &str = r#"{
+        "orientation": "orthogonal",
+        "infinite": false,
+        "width": 3,
+        "height": 2,
+        "tilewidth": 16,
+        "tileheight": 16,
+        "tilesets": [
+            {
+                "firstgid": 1,
+                "name": "tiles",
+                "columns": 4,
+                "tiles": [{ "id": 2, "properties": [{ "name": "solid", "type": "bool", "value": true }] }]
+            },
+            {
+                "firstgid": 9,
+                "name": "props",
+                "columns": 2,
+                "properties": [{ "name": "sheet", "type": "string", "value": "props_sheet" }]
+            }
+        ],
+        "layers": [
+            {
+                "type": "tilelayer",
+                "name": "ground",
+                "width": 3,
+                "height": 2,
+                "data": [1, 0, 2147483651, 0, 10, 1073741828]
+            },
+            {
+                "type": "objectgroup",
+                "name": "objects",
+                "objects": [
+                    {
+                        "id": 1,
+                        "type": "collider",
+                        "x": 16, "y": 0, "width": 32, "height": 8,
+                        "properties": [{ "name": "collision_layers", "type": "string", "value": "Player, Enemy" }]
+                    },
+                    {
+                        "id": 2,
+                        "name": "bush",
+                        "gid": 2147483655,
+                        "x": 0, "y": 32, "width": 16, "height": 16
+                    },
+                    { "id": 3, "x": 0, "y": 0 },
+                    {
+                        "id": 4,
+                        "type": "level_exit",
+                        "x": 32, "y": 16, "width": 16, "height": 16,
+                        "properties": [{ "name": "scene", "type": "string", "value": "assets/scenes/next.ron" }]
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    fn entity<'s>(scene: &'s Scene, name: &str) -> &'s SceneEntity {
+        scene
+            .entities
+            .iter()
+            .find(|entity| entity.name.as_deref() == Some(name))
+            .unwrap_or_else(|| panic!("no entity {}", name))
+    }
+
+    #[test]
+    fn tile_layers_become_a_tilemap_per_tileset() {
+        let scene = import(MAP).unwrap();
+        let tile = |index, flipped| Some(Tile { index, flipped });
+
+        let tiles = entity(&scene, "ground_tiles");
+        assert_eq!(
+            tiles.components[1],
+            SceneComponent::Tilemap {
+                sheet: "tiles".to_string(),
+                depth: Depth::Foreground,
+                tile_size: (16., 16.),
+                width: 3,
+                // only the horizontal flip is kept
+                tiles: vec![
+                    tile(0, false),
+                    None,
+                    tile(2, true),
+                    None,
+                    None,
+                    tile(3, false)
+                ],
+                solid_tiles: vec![2],
+                auto_tile: None,
+                columns: Some(4),
+            }
+        );
+        let props = entity(&scene, "ground_props");
+        assert!(matches!(
+            &props.components[1],
+            SceneComponent::Tilemap { sheet, tiles, columns: Some(2), .. }
+                if sheet == "props_sheet" && tiles[4] == tile(1, false)
+        ));
+    }
+
+    #[test]
+    fn objects_are_flipped_onto_the_y_axis_and_typed() {
+        let scene = import(MAP).unwrap();
+        // the empty object doesn't make an entity
+        assert_eq!(scene.entities.len(), 5);
+
+        assert_eq!(
+            entity(&scene, "objects_1").components,
+            vec![
+                SceneComponent::Position {
+                    position: (32., 28.),
+                    scale: (32., 8.),
+                    rotation: 0.,
+                    pivot: (-0.5, 0.5),
+                },
+                SceneComponent::Collider {
+                    bottom_left_offset: None,
+                    top_right_offset: None,
+                    layers: Some(vec![CollisionLayer::Player, CollisionLayer::Enemy]),
+                    collides_with: None,
+                },
+            ]
+        );
+        assert_eq!(
+            entity(&scene, "bush").components,
+            vec![
+                SceneComponent::Position {
+                    position: (8., 8.),
+                    scale: (16., 16.),
+                    rotation: 0.,
+                    pivot: (-0.5, -0.5),
+                },
+                SceneComponent::Sprite {
+                    sheet: "tiles".to_string(),
+                    depth: Depth::Object,
+                    // tile 6 of a tileset 4 tiles wide
+                    sheet_position: Some((2, 1)),
+                    flipped: true,
+                },
+                SceneComponent::Metadata {
+                    outline: false,
+                    controllable: false,
+                },
+            ]
+        );
+        assert!(matches!(
+            &entity(&scene, "objects_4").components[1..],
+            [
+                SceneComponent::Trigger { collides_with: None, .. },
+                SceneComponent::LevelExit { scene },
+            ] if scene == "assets/scenes/next.ron"
+        ));
+    }
+
+    #[test]
+    fn rejects_what_it_cant_import() {
+        let error = |map: String| format!("{:#}", import(&map).unwrap_err());
+
+        assert!(error(MAP.replace("orthogonal", "isometric")).contains("isometric"));
+        assert!(
+            error(MAP.replace("\"name\": \"tiles\",", "\"source\": \"tiles.tsj\","))
+                .contains("tiles.tsj")
+        );
+        assert!(error(MAP.replace("\"scene\"", "\"other\"")).contains("level_exit needs a scene"));
+        assert!(error(MAP.replace("Player, Enemy", "Player, Bush")).contains("Bush"));
+        // tile 17 is past both tilesets' tiles, but still gets the last tileset
+        assert!(import(&MAP.replace("[1, 0,", "[17, 0,")).is_ok());
+    }
+}
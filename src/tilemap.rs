@@ -0,0 +1,590 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use cgmath::{ElementWise, Rotation, Rotation2};
+use serde::{Deserialize, Serialize};
+
+use crate::component::{self, Component, Entity};
+use crate::event::EventReader;
+use crate::physics;
+use crate::sprite;
+use crate::transform::TransformSystem;
+use crate::world::World;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tile {
+    // index into the sprite sheet, left to right then top to bottom
+    pub index: u32,
+    #[serde(default)]
+    pub flipped: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Neighbourhood {
+    Four,
+    Eight,
+}
+
+// picks a ground tile from which of its neighbours are ground too. the mask has a bit for each
+// neighbour that is, see the constants below. a diagonal only counts when both of the edges next
+// to it are ground, and an eight neighbour mask without a rule falls back to its four edge bits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoTileRules {
+    pub neighbourhood: Neighbourhood,
+    // neighbour mask to sheet position
+    pub tiles: HashMap<u8, (u32, u32)>,
+    // whether ground collides, whichever tile it ends up as
+    #[serde(default)]
+    pub solid: bool,
+}
+
+impl AutoTileRules {
+    pub const NORTH: u8 = 1;
+    pub const EAST: u8 = 2;
+    pub const SOUTH: u8 = 4;
+    pub const WEST: u8 = 8;
+    pub const NORTH_EAST: u8 = 16;
+    pub const SOUTH_EAST: u8 = 32;
+    pub const SOUTH_WEST: u8 = 64;
+    pub const NORTH_WEST: u8 = 128;
+
+    // is_ground takes a column and row offset, rows go down
+    pub fn mask(&self, is_ground: impl Fn(i64, i64) -> bool) -> u8 {
+        let mut mask = [
+            (Self::NORTH, (0, -1)),
+            (Self::EAST, (1, 0)),
+            (Self::SOUTH, (0, 1)),
+            (Self::WEST, (-1, 0)),
+        ]
+        .into_iter()
+        .filter(|(_, (column, row))| is_ground(*column, *row))
+        .fold(0, |mask, (bit, _)| mask | bit);
+
+        if self.neighbourhood == Neighbourhood::Eight {
+            mask |= [
+                (Self::NORTH_EAST, (1, -1), Self::NORTH | Self::EAST),
+                (Self::SOUTH_EAST, (1, 1), Self::SOUTH | Self::EAST),
+                (Self::SOUTH_WEST, (-1, 1), Self::SOUTH | Self::WEST),
+                (Self::NORTH_WEST, (-1, -1), Self::NORTH | Self::WEST),
+            ]
+            .into_iter()
+            .filter(|(_, (column, row), edges)| mask & edges == *edges && is_ground(*column, *row))
+            .fold(0, |mask, (bit, _, _)| mask | bit);
+        }
+        mask
+    }
+
+    pub fn sheet_position(&self, mask: u8) -> Option<(u32, u32)> {
+        self.tiles
+            .get(&mask)
+            .or_else(|| self.tiles.get(&(mask & 0xf)))
+            .copied()
+    }
+}
+
+// sent to paint ground onto, or erase it from, the auto tiled tilemaps under a point in the world
+#[derive(Debug, Clone, Copy)]
+pub struct PaintGround {
+    pub position: cgmath::Vector2<f32>,
+    pub is_ground: bool,
+}
+
+// a grid of tiles from one sprite sheet. the entity's PositionComponent is the bottom left corner
+// of the map and row 0 is the top row. the tiles are drawn by chunk entities, one vertex batch
+// per chunk, and collided with through merged collider entities. both are children of the
+// tilemap and get rebuilt whenever the map changes. tile_size is what sizes the map, its scale
+// isn't passed on to children like any other entity's.
+pub struct TilemapComponent {
+    pub sprite_sheet: Rc<RefCell<sprite::SpriteSheet>>,
+    pub texture_index: u32,
+    pub tile_size: cgmath::Vector2<f32>,
+    pub z_value: f32,
+    width: u32,
+    height: u32,
+    tiles: Vec<Option<Tile>>,
+    // sheet indices of the tiles that collide
+    solid_tiles: HashSet<u32>,
+    // with rules, every tile counts as ground and gets its index from its neighbours
+    auto_tile: Option<AutoTileRules>,
+    is_dirty: bool,
+    spawned: Vec<Entity>,
+}
+
+impl Component for TilemapComponent {
+    fn name(&self) -> String {
+        "Tilemap".to_string()
+    }
+}
+
+impl TilemapComponent {
+    pub const CHUNK_SIZE: u32 = 16;
+
+    pub fn new(
+        sprite_sheet: Rc<RefCell<sprite::SpriteSheet>>,
+        texture_index: u32,
+        tile_size: cgmath::Vector2<f32>,
+        z_value: f32,
+        width: u32,
+        tiles: Vec<Option<Tile>>,
+    ) -> Self {
+        assert!(width > 0 && tiles.chunks_exact(width as usize).remainder().is_empty());
+        Self {
+            sprite_sheet,
+            texture_index,
+            tile_size,
+            z_value,
+            width,
+            height: (tiles.len() / width as usize) as u32,
+            tiles,
+            solid_tiles: HashSet::new(),
+            auto_tile: None,
+            is_dirty: true,
+            spawned: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, column: u32, row: u32) -> Option<Tile> {
+        if column >= self.width || row >= self.height {
+            return None;
+        }
+        self.tiles[(row * self.width + column) as usize]
+    }
+
+    pub fn set(&mut self, column: u32, row: u32, tile: Option<Tile>) {
+        assert!(column < self.width && row < self.height);
+        self.tiles[(row * self.width + column) as usize] = tile;
+        // the neighbours' masks changed too
+        for neighbour_row in row.saturating_sub(1)..(row + 2).min(self.height) {
+            for neighbour_column in column.saturating_sub(1)..(column + 2).min(self.width) {
+                self.retile(neighbour_column, neighbour_row);
+            }
+        }
+        self.is_dirty = true;
+    }
+
+    pub fn set_auto_tile(&mut self, rules: AutoTileRules) {
+        self.auto_tile = Some(rules);
+        for row in 0..self.height {
+            for column in 0..self.width {
+                self.retile(column, row);
+            }
+        }
+        self.is_dirty = true;
+    }
+
+    // the tile a point relative to the map's bottom left corner is in
+    pub fn cell_at(&self, point: cgmath::Vector2<f32>) -> Option<(u32, u32)> {
+        let cell = point.div_element_wise(self.tile_size).map(f32::floor);
+        if cell.x < 0. || cell.y < 0. || cell.x >= self.width as f32 || cell.y >= self.height as f32
+        {
+            return None;
+        }
+        Some((cell.x as u32, self.height - 1 - cell.y as u32))
+    }
+
+    pub fn set_solid_tiles(&mut self, solid_tiles: HashSet<u32>) {
+        self.solid_tiles = solid_tiles;
+        self.is_dirty = true;
+    }
+
+    pub fn is_solid(&self, column: u32, row: u32) -> bool {
+        self.get(column, row).is_some_and(|tile| {
+            self.solid_tiles.contains(&tile.index)
+                || self.auto_tile.as_ref().is_some_and(|rules| rules.solid)
+        })
+    }
+
+    fn retile(&mut self, column: u32, row: u32) {
+        let (Some(rules), Some(tile)) = (&self.auto_tile, self.get(column, row)) else {
+            return;
+        };
+        let mask = rules.mask(|column_offset, row_offset| {
+            let (neighbour_column, neighbour_row) =
+                (column as i64 + column_offset, row as i64 + row_offset);
+            neighbour_column >= 0
+                && neighbour_row >= 0
+                && self
+                    .get(neighbour_column as u32, neighbour_row as u32)
+                    .is_some()
+        });
+        if let Some((x, y)) = rules.sheet_position(mask) {
+            let index = self
+                .sprite_sheet
+                .borrow()
+                .get_index_by_position(cgmath::Vector2::new(x, y));
+            self.tiles[(row * self.width + column) as usize] = Some(Tile { index, ..tile });
+        }
+    }
+
+    // bottom left corner of a tile relative to the map's
+    fn tile_origin(&self, column: u32, row: u32) -> cgmath::Vector2<f32> {
+        cgmath::Vector2::new(
+            column as f32 * self.tile_size.x,
+            (self.height - 1 - row) as f32 * self.tile_size.y,
+        )
+    }
+}
+
+pub struct TilemapSystem {}
+
+impl TilemapSystem {
+    pub fn paint(world: &World, reader: &mut EventReader<PaintGround>) {
+        reader
+            .read(&world.events::<PaintGround>())
+            .for_each(|paint| {
+                world
+                    .query::<(&component::PositionComponent, &mut TilemapComponent)>()
+                    .iter()
+                    .filter(|(_, (_, tilemap))| tilemap.auto_tile.is_some())
+                    .for_each(|(_, (position_component, tilemap))| {
+                        // undoes the map's transform the way the transform system applies it
+                        // to the chunks, so scale doesn't come into it
+                        let point =
+                            cgmath::Basis2::from_angle(cgmath::Rad(position_component.rotation))
+                                .invert()
+                                .rotate_vector(paint.position - position_component.position);
+                        if let Some((column, row)) = tilemap.cell_at(point) {
+                            let tile = paint.is_ground.then_some(Tile {
+                                index: 0,
+                                flipped: false,
+                            });
+                            tilemap.set(column, row, tile);
+                        }
+                    });
+            });
+    }
+
+    // rebuilding spawns and despawns entities, so it's left to the sync point
+    pub fn update(world: &World) {
+        world
+            .query::<&mut TilemapComponent>()
+            .iter()
+            .for_each(|(entity, tilemap)| {
+                if tilemap.is_dirty {
+                    tilemap.is_dirty = false;
+                    world
+                        .commands()
+                        .add(move |world| Self::rebuild(world, entity));
+                }
+            });
+    }
+
+    fn rebuild(world: &mut World, entity: Entity) {
+        let (previous, chunks, colliders) = {
+            let Some(mut tilemap) = world.get_mut::<TilemapComponent>(entity) else {
+                return;
+            };
+            (
+                std::mem::take(&mut tilemap.spawned),
+                Self::chunks(&tilemap),
+                Self::colliders(&tilemap),
+            )
+        };
+        previous
+            .into_iter()
+            .for_each(|child| TransformSystem::despawn_recursive(world, child));
+
+        let mut spawned: Vec<Entity> = chunks
+            .into_iter()
+            .map(|(local_position, vertex_array_component)| {
+                world
+                    .builder()
+                    .with(local_position)
+                    .with(vertex_array_component)
+                    .with(component::MetadataComponent::new(false, false))
+                    .build()
+            })
+            .collect();
+        spawned.extend(
+            colliders
+                .into_iter()
+                .map(|(local_position, collider_box_component)| {
+                    world
+                        .builder()
+                        .with(local_position)
+                        .with(collider_box_component)
+                        .build()
+                }),
+        );
+
+        spawned
+            .iter()
+            .try_for_each(|child| TransformSystem::set_parent(world, *child, entity))
+            .expect("chunks and colliders are new entities with a LocalPositionComponent");
+        world.get_mut::<TilemapComponent>(entity).unwrap().spawned = spawned;
+
+        // so the new colliders are in place before physics next runs
+        TransformSystem::propagate(world);
+    }
+
+    fn chunks(
+        tilemap: &TilemapComponent,
+    ) -> Vec<(
+        component::LocalPositionComponent,
+        component::VertexArrayComponent,
+    )> {
+        let sprite_sheet = tilemap.sprite_sheet.borrow();
+        let chunk_size = TilemapComponent::CHUNK_SIZE;
+
+        let mut chunks = Vec::new();
+        for chunk_row in 0..tilemap.height.div_ceil(chunk_size) {
+            for chunk_column in 0..tilemap.width.div_ceil(chunk_size) {
+                let columns =
+                    chunk_column * chunk_size..((chunk_column + 1) * chunk_size).min(tilemap.width);
+                let rows =
+                    chunk_row * chunk_size..((chunk_row + 1) * chunk_size).min(tilemap.height);
+                let chunk_origin = tilemap.tile_origin(columns.start, rows.end - 1);
+
+                let mut vertices = Vec::new();
+                let mut indices = Vec::new();
+                let mut tex_coords = Vec::new();
+                for row in rows {
+                    for column in columns.clone() {
+                        let Some(tile) = tilemap.get(column, row) else {
+                            continue;
+                        };
+                        let origin = tilemap.tile_origin(column, row) - chunk_origin;
+                        let size = tilemap.tile_size;
+                        let base = vertices.len() as u32;
+                        vertices.extend([
+                            cgmath::Vector2::new(origin.x, origin.y + size.y), // TOP-LEFT
+                            cgmath::Vector2::new(origin.x + size.x, origin.y + size.y), // TOP-RIGHT
+                            origin,                                            // BOTTOM-LEFT
+                            cgmath::Vector2::new(origin.x + size.x, origin.y), // BOTTOM-RIGHT
+                        ]);
+                        indices.extend([0, 2, 3, 0, 3, 1].map(|index| base + index));
+
+                        let (top_left, step) =
+                            sprite_sheet.tex_rect(sprite_sheet.get_position_by_index(tile.index));
+                        let (left, right) = if tile.flipped {
+                            (top_left.x + step.x, top_left.x)
+                        } else {
+                            (top_left.x, top_left.x + step.x)
+                        };
+                        tex_coords.extend([
+                            cgmath::Vector2::new(left, top_left.y),
+                            cgmath::Vector2::new(right, top_left.y),
+                            cgmath::Vector2::new(left, top_left.y + step.y),
+                            cgmath::Vector2::new(right, top_left.y + step.y),
+                        ]);
+                    }
+                }
+                if vertices.is_empty() {
+                    continue;
+                }
+
+                chunks.push((
+                    component::LocalPositionComponent {
+                        position: chunk_origin,
+                        scale: cgmath::Vector2::new(1., 1.),
+                        rotation: 0.,
+                    },
+                    component::VertexArrayComponent {
+                        vertices,
+                        indices,
+                        whole_tex_coords: tex_coords.clone(),
+                        tex_coords,
+                        texture_index: tilemap.texture_index,
+                        is_flipped: false,
+                        shader_type: component::ShaderType::STANDARD,
+                        z_value: tilemap.z_value,
+                    },
+                ));
+            }
+        }
+        chunks
+    }
+
+    // greedily grows each solid tile right and then down into the largest rectangle it can, so a
+    // solid area becomes a handful of boxes rather than one per tile. the rectangles are column,
+    // row, columns and rows, from their top left tile
+    fn merge_solid(
+        width: u32,
+        height: u32,
+        is_solid: impl Fn(u32, u32) -> bool,
+    ) -> Vec<(u32, u32, u32, u32)> {
+        let mut is_covered = vec![false; (width * height) as usize];
+        let is_free = |is_covered: &[bool], column: u32, row: u32| {
+            is_solid(column, row) && !is_covered[(row * width + column) as usize]
+        };
+
+        let mut rectangles = Vec::new();
+        for row in 0..height {
+            for column in 0..width {
+                if !is_free(&is_covered, column, row) {
+                    continue;
+                }
+                let mut columns = 1;
+                while column + columns < width && is_free(&is_covered, column + columns, row) {
+                    columns += 1;
+                }
+                let mut rows = 1;
+                while row + rows < height
+                    && (column..column + columns)
+                        .all(|column| is_free(&is_covered, column, row + rows))
+                {
+                    rows += 1;
+                }
+                for covered_row in row..row + rows {
+                    for covered_column in column..column + columns {
+                        is_covered[(covered_row * width + covered_column) as usize] = true;
+                    }
+                }
+                rectangles.push((column, row, columns, rows));
+            }
+        }
+        rectangles
+    }
+
+    fn colliders(
+        tilemap: &TilemapComponent,
+    ) -> Vec<(
+        component::LocalPositionComponent,
+        physics::ColliderBoxComponent,
+    )> {
+        Self::merge_solid(tilemap.width, tilemap.height, |column, row| {
+            tilemap.is_solid(column, row)
+        })
+        .into_iter()
+        .map(|(column, row, columns, rows)| {
+            let size = cgmath::Vector2::new(
+                columns as f32 * tilemap.tile_size.x,
+                rows as f32 * tilemap.tile_size.y,
+            );
+            let center = tilemap.tile_origin(column, row + rows - 1) + size / 2.;
+            (
+                component::LocalPositionComponent {
+                    position: center,
+                    scale: size,
+                    rotation: 0.,
+                },
+                physics::ColliderBoxComponent {
+                    bounding_box: physics::BoundingBox {
+                        position: center,
+                        bottom_left_offset: -size / 2.,
+                        top_right_offset: size / 2.,
+                        rotation: 0.,
+                        pivot: cgmath::Vector2::new(0., 0.),
+                    },
+                    filter: physics::CollisionFilter {
+                        layers: physics::CollisionLayer::Terrain.bit(),
+                        ..physics::CollisionFilter::all()
+                    },
+                },
+            )
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(neighbourhood: Neighbourhood) -> AutoTileRules {
+        AutoTileRules {
+            neighbourhood,
+            tiles: HashMap::new(),
+            solid: true,
+        }
+    }
+
+    // '#' is ground, rows go down
+    fn grid<'a>(rows: &'a [&'a str]) -> impl Fn(i64, i64) -> bool + 'a {
+        move |column, row| {
+            row >= 0
+                && column >= 0
+                && rows
+                    .get(row as usize)
+                    .and_then(|row| row.as_bytes().get(column as usize))
+                    == Some(&b'#')
+        }
+    }
+
+    // the mask of the tile in the middle of a 3x3 grid
+    fn mask(rules: &AutoTileRules, rows: &[&str]) -> u8 {
+        let is_ground = grid(rows);
+        rules.mask(|column, row| is_ground(1 + column, 1 + row))
+    }
+
+    #[test]
+    fn four_neighbour_masks_ignore_diagonals() {
+        let rules = rules(Neighbourhood::Four);
+        assert_eq!(mask(&rules, &["...", ".#.", "..."]), 0);
+        assert_eq!(
+            mask(&rules, &[".#.", ".##", "..."]),
+            AutoTileRules::NORTH | AutoTileRules::EAST
+        );
+        assert_eq!(mask(&rules, &["###", "###", "###"]), 0xf);
+    }
+
+    #[test]
+    fn eight_neighbour_diagonals_need_both_edges() {
+        let rules = rules(Neighbourhood::Eight);
+        assert_eq!(
+            mask(&rules, &[".##", ".##", "..."]),
+            AutoTileRules::NORTH | AutoTileRules::EAST | AutoTileRules::NORTH_EAST
+        );
+        // only one edge next to the corner is ground
+        assert_eq!(mask(&rules, &["..#", ".##", "..."]), AutoTileRules::EAST);
+        assert_eq!(mask(&rules, &["#.#", ".#.", "#.#"]), 0);
+        assert_eq!(mask(&rules, &["###", "###", "###"]), 0xff);
+    }
+
+    #[test]
+    fn sheet_position_falls_back_to_the_edges() {
+        let mut rules = rules(Neighbourhood::Eight);
+        rules.tiles.insert(0xf, (1, 1));
+        rules.tiles.insert(0xff, (4, 4));
+        assert_eq!(rules.sheet_position(0xff), Some((4, 4)));
+        assert_eq!(
+            rules.sheet_position(0xf | AutoTileRules::NORTH_EAST),
+            Some((1, 1))
+        );
+        assert_eq!(rules.sheet_position(AutoTileRules::NORTH), None);
+    }
+
+    fn merge(rows: &[&str]) -> Vec<(u32, u32, u32, u32)> {
+        let is_solid = grid(rows);
+        TilemapSystem::merge_solid(rows[0].len() as u32, rows.len() as u32, |column, row| {
+            is_solid(column as i64, row as i64)
+        })
+    }
+
+    #[test]
+    fn merge_solid_grows_right_then_down() {
+        assert_eq!(merge(&["....", "...."]), vec![]);
+        assert_eq!(merge(&["###.", "###.", "...."]), vec![(0, 0, 3, 2)]);
+        // the first row is wider, so the rest becomes its own box
+        assert_eq!(
+            merge(&["####", "##..", "##.."]),
+            vec![(0, 0, 4, 1), (0, 1, 2, 2)]
+        );
+        assert_eq!(
+            merge(&["#..#", "#..#", "####"]),
+            vec![(0, 0, 1, 3), (3, 0, 1, 3), (1, 2, 2, 1)]
+        );
+    }
+
+    #[test]
+    fn merge_solid_covers_every_solid_tile_once() {
+        let rows = ["##.#.###", "#####..#", ".#.####.", "########", "#..#..##"];
+        let is_solid = grid(&rows);
+        let mut covered = vec![0; 8 * rows.len()];
+        merge(&rows)
+            .into_iter()
+            .for_each(|(column, row, columns, rows)| {
+                (row..row + rows).for_each(|row| {
+                    (column..column + columns).for_each(|column| {
+                        assert!(is_solid(column as i64, row as i64));
+                        covered[(row * 8 + column) as usize] += 1;
+                    })
+                })
+            });
+        covered.iter().enumerate().for_each(|(i, count)| {
+            let expected = is_solid(i as i64 % 8, i as i64 / 8) as i32;
+            assert_eq!(*count, expected, "tile {}", i);
+        });
+    }
+}
@@ -0,0 +1,330 @@
+use anyhow::{anyhow, ensure, Result};
+use cgmath::{Rotation, Rotation2};
+
+use crate::component::{self, Entity};
+use crate::physics;
+use crate::world::World;
+
+#[derive(Clone, Copy)]
+struct GlobalTransform {
+    position: cgmath::Vector2<f32>,
+    rotation: f32,
+    is_flipped: bool,
+}
+
+impl GlobalTransform {
+    fn basis(&self) -> cgmath::Basis2<f32> {
+        cgmath::Basis2::from_angle(cgmath::Rad(self.rotation))
+    }
+}
+
+pub struct TransformSystem {}
+
+impl TransformSystem {
+    // walks every hierarchy from its root down, so a child is always placed after its parent
+    // has been. children follow the parent's rotation and flip, flipping mirrors their local
+    // x offset and rotation. scale is only the size of an entity's own quad, not part of its
+    // transform, so a parent's scale doesn't change its children.
+    pub fn propagate(world: &World) {
+        let roots: Vec<Entity> = world
+            .query::<(
+                &component::ChildrenComponent,
+                Option<&component::ParentComponent>,
+            )>()
+            .iter()
+            .filter(|(_, (_, parent))| parent.is_none())
+            .map(|(entity, _)| entity)
+            .collect();
+
+        let mut stack: Vec<(Entity, GlobalTransform)> = Vec::new();
+        roots.into_iter().for_each(|root| {
+            let Some(transform) = Self::global_transform(world, root) else {
+                return;
+            };
+            Self::push_children(world, root, transform, &mut stack);
+        });
+
+        while let Some((entity, parent_transform)) = stack.pop() {
+            let Some(local) = world
+                .get::<component::LocalPositionComponent>(entity)
+                .map(|local| local.clone())
+            else {
+                continue;
+            };
+
+            let (offset, rotation) = if parent_transform.is_flipped {
+                (
+                    cgmath::Vector2::new(-local.position.x, local.position.y),
+                    -local.rotation,
+                )
+            } else {
+                (local.position, local.rotation)
+            };
+            let transform = GlobalTransform {
+                position: parent_transform.position
+                    + parent_transform.basis().rotate_vector(offset),
+                rotation: parent_transform.rotation + rotation,
+                is_flipped: parent_transform.is_flipped,
+            };
+
+            if let Some(mut position_component) =
+                world.get_mut::<component::PositionComponent>(entity)
+            {
+                position_component.position = transform.position;
+                position_component.scale = local.scale;
+                position_component.rotation = transform.rotation;
+            }
+            if let Some(mut vertex_array_component) =
+                world.get_mut::<component::VertexArrayComponent>(entity)
+            {
+                vertex_array_component.is_flipped = transform.is_flipped;
+            }
+            if let Some(mut collider_box_component) =
+                world.get_mut::<physics::ColliderBoxComponent>(entity)
+            {
+                collider_box_component
+                    .bounding_box
+                    .update(transform.position);
+                collider_box_component.bounding_box.rotation = transform.rotation;
+            }
+
+            Self::push_children(world, entity, transform, &mut stack);
+        }
+    }
+
+    fn global_transform(world: &World, entity: Entity) -> Option<GlobalTransform> {
+        let (position, rotation) = world
+            .get::<component::PositionComponent>(entity)
+            .map(|position_component| (position_component.position, position_component.rotation))?;
+        let is_flipped = world
+            .get::<component::VertexArrayComponent>(entity)
+            .is_some_and(|vertex_array_component| vertex_array_component.is_flipped);
+        Some(GlobalTransform {
+            position,
+            rotation,
+            is_flipped,
+        })
+    }
+
+    fn push_children(
+        world: &World,
+        entity: Entity,
+        transform: GlobalTransform,
+        stack: &mut Vec<(Entity, GlobalTransform)>,
+    ) {
+        if let Some(children_component) = world.get::<component::ChildrenComponent>(entity) {
+            stack.extend(
+                children_component
+                    .children
+                    .iter()
+                    .map(|child| (*child, transform)),
+            );
+        }
+    }
+
+    // the child keeps its current world position, unless it already has a local one. nothing
+    // changes if it fails, e.g. because the parent is the child or underneath it, which would
+    // make a cycle propagation never reaches
+    pub fn set_parent(world: &mut World, child: Entity, parent: Entity) -> Result<()> {
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            ensure!(
+                entity != child,
+                "can't parent {:?} to {:?}, which is underneath it",
+                child,
+                parent
+            );
+            ancestor = world
+                .get::<component::ParentComponent>(entity)
+                .map(|parent_component| parent_component.parent);
+        }
+
+        if world
+            .get::<component::LocalPositionComponent>(child)
+            .is_none()
+        {
+            let parent_transform = Self::global_transform(world, parent)
+                .ok_or_else(|| anyhow!("parent {:?} needs a PositionComponent", parent))?;
+            let position_component = world
+                .get::<component::PositionComponent>(child)
+                .map(|position_component| position_component.clone())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "child {:?} needs a PositionComponent or LocalPositionComponent",
+                        child
+                    )
+                })?;
+            let mut offset = parent_transform
+                .basis()
+                .invert()
+                .rotate_vector(position_component.position - parent_transform.position);
+            let mut rotation = position_component.rotation - parent_transform.rotation;
+            if parent_transform.is_flipped {
+                offset.x = -offset.x;
+                rotation = -rotation;
+            }
+            world.insert(
+                child,
+                component::LocalPositionComponent {
+                    position: offset,
+                    scale: position_component.scale,
+                    rotation,
+                },
+            );
+        }
+
+        if world.get::<component::PositionComponent>(child).is_none() {
+            let scale = world
+                .get::<component::LocalPositionComponent>(child)
+                .unwrap()
+                .scale;
+            world.insert(
+                child,
+                component::PositionComponent::new(cgmath::Vector2::new(0., 0.), scale),
+            );
+        }
+
+        Self::remove_parent(world, child);
+        world.insert(child, component::ParentComponent { parent });
+        if world.get::<component::ChildrenComponent>(parent).is_none() {
+            world.insert(parent, component::ChildrenComponent::default());
+        }
+        world
+            .get_mut::<component::ChildrenComponent>(parent)
+            .unwrap()
+            .children
+            .push(child);
+        Ok(())
+    }
+
+    pub fn remove_parent(world: &mut World, child: Entity) {
+        let Some(parent_component) = world.remove::<component::ParentComponent>(child) else {
+            return;
+        };
+        if let Some(mut children_component) =
+            world.get_mut::<component::ChildrenComponent>(parent_component.parent)
+        {
+            children_component
+                .children
+                .retain(|entity| *entity != child);
+        }
+    }
+
+    // removes the entity along with everything underneath it
+    pub fn despawn_recursive(world: &mut World, entity: Entity) {
+        Self::remove_parent(world, entity);
+
+        let mut stack = vec![entity];
+        while let Some(entity) = stack.pop() {
+            if let Some(children_component) = world.remove::<component::ChildrenComponent>(entity) {
+                stack.extend(children_component.children);
+            }
+            world.remove_entity(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<component::PositionComponent>();
+        world.register::<component::LocalPositionComponent>();
+        world.register::<component::ParentComponent>();
+        world.register::<component::ChildrenComponent>();
+        world.register::<component::VertexArrayComponent>();
+        world.register::<physics::ColliderBoxComponent>();
+        world
+    }
+
+    fn spawn(world: &mut World, x: f32) -> Entity {
+        world
+            .builder()
+            .with(component::PositionComponent::new(
+                cgmath::Vector2::new(x, 0.),
+                cgmath::Vector2::new(1., 1.),
+            ))
+            .build()
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut world = world();
+        let (a, b, c) = (
+            spawn(&mut world, 0.),
+            spawn(&mut world, 1.),
+            spawn(&mut world, 2.),
+        );
+        TransformSystem::set_parent(&mut world, b, a).unwrap();
+        TransformSystem::set_parent(&mut world, c, b).unwrap();
+
+        assert!(TransformSystem::set_parent(&mut world, a, a).is_err());
+        assert!(TransformSystem::set_parent(&mut world, a, c).is_err());
+        // nothing changed
+        assert!(world.get::<component::ParentComponent>(a).is_none());
+        assert_eq!(
+            world.get::<component::ParentComponent>(c).unwrap().parent,
+            b
+        );
+        assert_eq!(
+            world
+                .get::<component::ChildrenComponent>(b)
+                .unwrap()
+                .children,
+            vec![c]
+        );
+    }
+
+    #[test]
+    fn children_keep_their_world_position() {
+        let mut world = world();
+        let (parent, child) = (spawn(&mut world, 10.), spawn(&mut world, 15.));
+        TransformSystem::set_parent(&mut world, child, parent).unwrap();
+        assert_eq!(
+            world
+                .get::<component::LocalPositionComponent>(child)
+                .unwrap()
+                .position,
+            cgmath::Vector2::new(5., 0.)
+        );
+
+        world
+            .get_mut::<component::PositionComponent>(parent)
+            .unwrap()
+            .position = cgmath::Vector2::new(20., 0.);
+        TransformSystem::propagate(&world);
+        assert_eq!(
+            world
+                .get::<component::PositionComponent>(child)
+                .unwrap()
+                .position,
+            cgmath::Vector2::new(25., 0.)
+        );
+    }
+
+    #[test]
+    fn set_parent_moves_a_child_between_parents() {
+        let mut world = world();
+        let (a, b, child) = (
+            spawn(&mut world, 0.),
+            spawn(&mut world, 1.),
+            spawn(&mut world, 2.),
+        );
+        TransformSystem::set_parent(&mut world, child, a).unwrap();
+        TransformSystem::set_parent(&mut world, child, b).unwrap();
+        assert!(world
+            .get::<component::ChildrenComponent>(a)
+            .unwrap()
+            .children
+            .is_empty());
+        assert_eq!(
+            world
+                .get::<component::ChildrenComponent>(b)
+                .unwrap()
+                .children,
+            vec![child]
+        );
+    }
+}
@@ -1,11 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct WgslPreprocessor {
     shader_code: HashMap<String, String>,
+    // every file each shader includes, directly or not
+    includes: HashMap<String, HashSet<String>>,
 }
 
 impl WgslPreprocessor {
-    fn process(shader_code: &HashMap<&'static str, &'static str>, file_name: &String) -> String {
+    fn process(shader_code: &HashMap<&str, &str>, file_name: &String) -> String {
         match shader_code.get(file_name.as_str()) {
             Some(code) => {
                 // let path = Path::new(shader_file);
@@ -31,7 +33,24 @@ impl WgslPreprocessor {
             }
         }
     }
-    pub fn new(shader_code: HashMap<&'static str, &'static str>) -> Self {
+    fn collect_includes(
+        shader_code: &HashMap<&str, &str>,
+        file_name: &str,
+        includes: &mut HashSet<String>,
+    ) {
+        let Some(code) = shader_code.get(file_name) else {
+            return;
+        };
+        code.lines()
+            .filter_map(|line| line.trim().strip_prefix("//#include"))
+            .for_each(|included| {
+                if includes.insert(included.trim().to_string()) {
+                    Self::collect_includes(shader_code, included.trim(), includes);
+                }
+            });
+    }
+
+    pub fn new(shader_code: HashMap<&str, &str>) -> Self {
         let new_shader_code = shader_code
             .keys()
             .fold(HashMap::new(), |mut acc, file_name| {
@@ -40,11 +59,30 @@ impl WgslPreprocessor {
                 acc
             });
 
+        let includes = shader_code
+            .keys()
+            .map(|file_name| {
+                let mut includes = HashSet::new();
+                Self::collect_includes(&shader_code, file_name, &mut includes);
+                (file_name.to_string(), includes)
+            })
+            .collect();
+
         Self {
             shader_code: new_shader_code,
+            includes,
         }
     }
 
+    // whether the shader is one of the files or includes one
+    pub fn depends_on(&self, file_name: &str, file_names: &HashSet<String>) -> bool {
+        file_names.contains(file_name)
+            || self
+                .includes
+                .get(file_name)
+                .is_some_and(|includes| !includes.is_disjoint(file_names))
+    }
+
     pub fn get_code(&self, file_name: String) -> String {
         assert!(self.shader_code.contains_key(&file_name));
         self.shader_code.get(&file_name).unwrap().clone()
@@ -0,0 +1,273 @@
+use std::any::{type_name, Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+
+use slotmap::{basic, SlotMap};
+
+use crate::commands::Commands;
+use crate::component::{Entity, EntityMap};
+use crate::event::{EngineEvent, Events};
+use crate::query::{Query, QueryBorrow};
+use crate::resources::Resources;
+
+trait ComponentStorage {
+    fn remove(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> ComponentStorage for RefCell<EntityMap<T>> {
+    fn remove(&mut self, entity: Entity) {
+        self.get_mut().remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+trait EventStorage {
+    fn update(&mut self);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: 'static> EventStorage for RefCell<Events<T>> {
+    fn update(&mut self) {
+        self.get_mut().update();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct World {
+    entities: SlotMap<Entity, ()>,
+    storages: HashMap<TypeId, Box<dyn ComponentStorage>>,
+    commands: RefCell<Commands>,
+    events: RefCell<Vec<EngineEvent>>,
+    channels: HashMap<TypeId, Box<dyn EventStorage>>,
+    resources: Resources,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            entities: SlotMap::new(),
+            storages: HashMap::new(),
+            commands: RefCell::new(Commands::default()),
+            events: RefCell::new(Vec::new()),
+            channels: HashMap::new(),
+            resources: Resources::default(),
+        }
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        let entity = self.entities.insert(());
+        self.send_event(EngineEvent::Spawned(entity));
+        entity
+    }
+
+    pub fn builder(&mut self) -> EntityBuilder<'_> {
+        let entity = self.spawn();
+        EntityBuilder {
+            world: self,
+            entity,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains_key(entity)
+    }
+
+    pub fn entities(&self) -> basic::Keys<'_, Entity, ()> {
+        self.entities.keys()
+    }
+
+    pub fn register<T: 'static>(&mut self) {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RefCell::new(EntityMap::<T>::new())));
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+        assert!(self.contains(entity));
+        self.register::<T>();
+        self.storage_mut::<T>().get_mut().insert(entity, component);
+        self.send_event(EngineEvent::ComponentAdded {
+            entity,
+            component: type_name::<T>(),
+        });
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.storages.get_mut(&TypeId::of::<T>())?;
+        self.storage_mut::<T>().get_mut().remove(entity)
+    }
+
+    pub fn remove_entity(&mut self, entity: Entity) {
+        if self.entities.remove(entity).is_some() {
+            self.storages
+                .values_mut()
+                .for_each(|storage| storage.remove(entity));
+            self.send_event(EngineEvent::Despawned(entity));
+        }
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.try_components::<T>()?, |components| {
+            components.get(entity)
+        })
+        .ok()
+    }
+
+    pub fn get_mut<T: 'static>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.try_components_mut::<T>()?, |components| {
+            components.get_mut(entity)
+        })
+        .ok()
+    }
+
+    pub fn commands(&self) -> RefMut<'_, Commands> {
+        self.commands.borrow_mut()
+    }
+
+    pub fn apply_commands(&mut self) {
+        // commands can queue more commands, those run on the next sync point
+        std::mem::take(self.commands.get_mut()).apply(self);
+    }
+
+    pub fn send_event(&self, event: EngineEvent) {
+        self.events.borrow_mut().push(event);
+    }
+
+    pub fn drain_events(&mut self) -> Vec<EngineEvent> {
+        std::mem::take(self.events.get_mut())
+    }
+
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+        self.resources.insert(resource);
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        self.resources.remove::<T>()
+    }
+
+    pub fn resource<T: 'static>(&self) -> Ref<'_, T> {
+        self.resources.get::<T>()
+    }
+
+    pub fn resource_mut<T: 'static>(&self) -> RefMut<'_, T> {
+        self.resources.get_mut::<T>()
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    pub fn add_events<T: 'static>(&mut self) {
+        self.channels
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RefCell::new(Events::<T>::default())));
+    }
+
+    pub fn events<T: 'static>(&self) -> Ref<'_, Events<T>> {
+        self.channel::<T>().borrow()
+    }
+
+    pub fn events_mut<T: 'static>(&self) -> RefMut<'_, Events<T>> {
+        self.channel::<T>().borrow_mut()
+    }
+
+    pub fn send<T: 'static>(&self, event: T) {
+        self.events_mut::<T>().send(event);
+    }
+
+    pub fn update_events(&mut self) {
+        self.channels
+            .values_mut()
+            .for_each(|channel| channel.update());
+    }
+
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
+        QueryBorrow::new(self)
+    }
+
+    // borrows are checked at runtime, so different component types can be borrowed
+    // mutably at the same time but the same type can't
+    pub fn components<T: 'static>(&self) -> Ref<'_, EntityMap<T>> {
+        self.try_components::<T>()
+            .unwrap_or_else(|| panic!("component {} is not registered", type_name::<T>()))
+    }
+
+    pub fn components_mut<T: 'static>(&self) -> RefMut<'_, EntityMap<T>> {
+        self.try_components_mut::<T>()
+            .unwrap_or_else(|| panic!("component {} is not registered", type_name::<T>()))
+    }
+
+    pub fn try_components<T: 'static>(&self) -> Option<Ref<'_, EntityMap<T>>> {
+        self.storage::<T>().map(|storage| {
+            storage.try_borrow().unwrap_or_else(|_| {
+                panic!("component {} is already borrowed mutably", type_name::<T>())
+            })
+        })
+    }
+
+    pub fn try_components_mut<T: 'static>(&self) -> Option<RefMut<'_, EntityMap<T>>> {
+        self.storage::<T>().map(|storage| {
+            storage
+                .try_borrow_mut()
+                .unwrap_or_else(|_| panic!("component {} is already borrowed", type_name::<T>()))
+        })
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&RefCell<EntityMap<T>>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut RefCell<EntityMap<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut())
+            .unwrap_or_else(|| panic!("component {} is not registered", type_name::<T>()))
+    }
+
+    fn channel<T: 'static>(&self) -> &RefCell<Events<T>> {
+        self.channels
+            .get(&TypeId::of::<T>())
+            .and_then(|channel| channel.as_any().downcast_ref())
+            .unwrap_or_else(|| panic!("event {} is not registered", type_name::<T>()))
+    }
+}
+
+// a group of components that always get spawned together, see bundle.rs
+pub trait Bundle {
+    fn insert(self, world: &mut World, entity: Entity);
+}
+
+pub struct EntityBuilder<'w> {
+    world: &'w mut World,
+    entity: Entity,
+}
+
+impl<'w> EntityBuilder<'w> {
+    pub fn with<T: 'static>(self, component: T) -> Self {
+        self.world.insert(self.entity, component);
+        self
+    }
+
+    pub fn with_bundle<B: Bundle>(self, bundle: B) -> Self {
+        bundle.insert(self.world, self.entity);
+        self
+    }
+
+    pub fn build(self) -> Entity {
+        self.entity
+    }
+}